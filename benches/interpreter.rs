@@ -0,0 +1,110 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lmc_assembly::{bench, Config, ExecutionState, Output, LMCIO};
+
+const STEPS: u64 = 10_000;
+
+fn assemble(code: &str) -> [i16; 100] {
+    lmc_assembly::assemble(lmc_assembly::parse(code, false).unwrap()).unwrap()
+}
+
+/// Counts down from 999 over and over, so it never leaves the ADD/SUB/BRZ loop.
+fn arithmetic_loop() -> [i16; 100] {
+    assemble(
+        "loop  LDA count
+               SUB one
+               STA count
+               ADD total
+               STA total
+               LDA count
+               BRZ reset
+               BRA loop
+         reset LDA start
+               STA count
+               BRA loop
+         count DAT 999
+         total DAT 0
+         one   DAT 1
+         start DAT 999",
+    )
+}
+
+/// A chain of conditional branches, half of them taken.
+fn branch_heavy() -> [i16; 100] {
+    assemble(
+        "loop LDA zero
+              BRZ a
+              HLT
+         a    BRP b
+              HLT
+         b    LDA neg
+              BRP loop
+              BRZ loop
+              BRA loop
+         zero DAT 0
+         neg  DAT -1",
+    )
+}
+
+/// Echoes its input forever.
+fn io_heavy() -> [i16; 100] {
+    assemble(
+        "loop INP
+              OUT
+              OTC
+              BRA loop",
+    )
+}
+
+struct SinkIO;
+
+impl LMCIO for SinkIO {
+    fn get_input(&mut self) -> i16 {
+        65
+    }
+
+    fn print_output(&mut self, val: Output) {
+        std::hint::black_box(val);
+    }
+}
+
+fn interpreter(c: &mut Criterion) {
+    let programs = [
+        ("arithmetic_loop", arithmetic_loop()),
+        ("branch_heavy", branch_heavy()),
+        ("io_heavy", io_heavy()),
+    ];
+
+    let mut group = c.benchmark_group("interpreter");
+    group.throughput(criterion::Throughput::Elements(STEPS));
+    for (name, program) in programs {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut state = ExecutionState::new(program);
+                for _ in 0..STEPS {
+                    state.step(&mut SinkIO).unwrap();
+                }
+                state
+            })
+        });
+        group.bench_function(format!("{name}_predecoded"), |b| {
+            let config = Config {
+                predecode: true,
+                ..Config::default()
+            };
+            b.iter(|| {
+                let mut state = ExecutionState::with_config(program, config.clone());
+                for _ in 0..STEPS {
+                    state.step(&mut SinkIO).unwrap();
+                }
+                state
+            })
+        });
+        group.bench_function(format!("{name}_compiled"), |b| {
+            b.iter(|| bench::measure_compiled(program, STEPS))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,167 @@
+//! Static analysis of programs, for visualising their structure.
+
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{lint::branch_operand, Instruction, Label, Operand, Program, SymbolTable};
+
+/// A basic block: instructions that always run one after another.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub addresses: Range<usize>,
+    /// Each instruction as it would be written in source, e.g. `LOOP LDA A`.
+    pub lines: Vec<String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// Carrying on to the next mailbox, including when a BRZ or BRP isn't taken.
+    Next,
+    /// BRA, or BRZ or BRP when taken.
+    Branch,
+    Call,
+}
+
+/// `from` and `to` are indices into `Graph::nodes`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph {
+    /// In address order.
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Graph {
+    /// Renders the graph for Graphviz, e.g. `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            "digraph program {\n    node [shape=box, fontname=\"monospace\"];\n".to_string();
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let label: String = node
+                .addresses
+                .clone()
+                .zip(&node.lines)
+                .map(|(address, line)| format!("{:02}  {}\\l", address, escape(line)))
+                .collect();
+            dot += &format!("    n{} [label=\"{}\"];\n", i, label);
+        }
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::Next => "",
+                EdgeKind::Branch => " [label=\"branch\"]",
+                EdgeKind::Call => " [label=\"call\", style=dashed]",
+            };
+            dot += &format!("    n{} -> n{}{};\n", edge.from, edge.to, attributes);
+        }
+
+        dot + "}\n"
+    }
+}
+
+/// Splits `program` into basic blocks and connects them by how control can pass between
+/// them. Data cells aren't part of any block, and indirect branches, RET and RTI have no
+/// edges since where they go depends on the run.
+pub fn control_flow_graph(program: &Program) -> Graph {
+    let symbols = SymbolTable::from_program(program);
+    let is_code = |address: usize| {
+        program
+            .get(address)
+            .is_some_and(|(_, instruction)| !matches!(instruction, Instruction::DAT(_)))
+    };
+    let target = |instruction: &Instruction| {
+        let address = match branch_operand(instruction)? {
+            Operand::Value(address) => *address as usize,
+            Operand::Label(name) => symbols.get(name)? as usize,
+            Operand::Indirect(_) => return None,
+        };
+        is_code(address).then_some(address)
+    };
+
+    let mut starts = vec![false; program.len()];
+    for (address, (_, instruction)) in program.iter().enumerate() {
+        if !is_code(address) {
+            continue;
+        }
+        if address == 0 || !is_code(address - 1) || ends_block(&program[address - 1].1) {
+            starts[address] = true;
+        }
+        if let Some(target) = target(instruction) {
+            starts[target] = true;
+        }
+    }
+
+    let mut nodes: Vec<Node> = vec![];
+    let mut block_at = vec![None; program.len()];
+    for (address, (label, instruction)) in program.iter().enumerate() {
+        if !is_code(address) {
+            continue;
+        }
+        if starts[address] {
+            block_at[address] = Some(nodes.len());
+            nodes.push(Node {
+                addresses: address..address,
+                lines: vec![],
+            });
+        }
+        let node = nodes.last_mut().unwrap();
+        node.addresses.end = address + 1;
+        node.lines.push(match label {
+            Label::LBL(name) => format!("{} {}", name, instruction),
+            Label::None => instruction.to_string(),
+        });
+    }
+
+    let mut edges = vec![];
+    for (from, node) in nodes.iter().enumerate() {
+        let last = node.addresses.end - 1;
+        let instruction = &program[last].1;
+        let mut connect = |address: usize, kind| {
+            if let Some(to) = block_at.get(address).copied().flatten() {
+                edges.push(Edge { from, to, kind });
+            }
+        };
+
+        let falls_through = !matches!(
+            instruction,
+            Instruction::HLT | Instruction::BRA(_) | Instruction::RET | Instruction::RTI
+        );
+        if falls_through {
+            connect(last + 1, EdgeKind::Next);
+        }
+        if let Some(target) = target(instruction) {
+            let kind = match instruction {
+                Instruction::CALL(_) => EdgeKind::Call,
+                _ => EdgeKind::Branch,
+            };
+            connect(target, kind);
+        }
+    }
+
+    Graph { nodes, edges }
+}
+
+/// Whether control can leave the straight line after `instruction`.
+fn ends_block(instruction: &Instruction) -> bool {
+    branch_operand(instruction).is_some()
+        || matches!(
+            instruction,
+            Instruction::HLT | Instruction::RET | Instruction::RTI
+        )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,147 @@
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    build, io::BufferedIO, BuildOptions, Diagnostic, DiagnosticCode, Diagnostics, ExecutionState,
+    Expr, HaltReason, Output, SymbolTable,
+};
+
+/// A `// ASSERT` comment. `// ASSERT RAM[COUNT] == 0` is checked when the program halts,
+/// and `// ASSERT LOOP: ACC > 0` every time the instruction at `LOOP` is about to run.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    /// The 1-based source line of the comment.
+    pub line: usize,
+    /// Where it's checked, or `None` for at HLT.
+    pub address: Option<i16>,
+    pub condition: Expr,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionFailure {
+    pub assertion: Assertion,
+    /// What the condition evaluated to (zero), or why it couldn't be evaluated.
+    pub value: Result<i32, String>,
+    pub steps: u64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionReport {
+    /// Number of times an assertion was checked and held.
+    pub passed: usize,
+    pub failures: Vec<AssertionFailure>,
+    pub halt_reason: HaltReason,
+    pub outputs: Vec<Output>,
+}
+
+impl AssertionReport {
+    /// Whether the program halted normally without any assertion failing.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty() && self.halt_reason == HaltReason::Halted
+    }
+}
+
+/// Finds the `// ASSERT` comments in `source`, resolving labels with `symbols`.
+pub fn assertions(source: &str, symbols: &SymbolTable) -> Result<Vec<Assertion>, Diagnostics> {
+    let mut assertions = vec![];
+    let mut diagnostics = vec![];
+
+    for (i, text) in source.lines().enumerate() {
+        let Some(rest) = text.trim_start().strip_prefix("//") else {
+            continue;
+        };
+        let Some(rest) = rest.trim_start().strip_prefix("ASSERT ") else {
+            continue;
+        };
+
+        let (address, condition) = match rest.split_once(':') {
+            Some((at, condition)) => (Some(at.trim()), condition),
+            None => (None, rest),
+        };
+        let address = address.map(|at| {
+            symbols
+                .get(at)
+                .or_else(|| at.parse().ok())
+                .ok_or_else(|| format!("Invalid label... {}", at))
+        });
+
+        match (
+            address.transpose(),
+            Expr::parse_with_symbols(condition, symbols),
+        ) {
+            (Ok(address), Ok(condition)) => assertions.push(Assertion {
+                line: i + 1,
+                address,
+                condition,
+            }),
+            (Err(message), _) | (_, Err(message)) => diagnostics.push(
+                Diagnostic::error(
+                    DiagnosticCode::InvalidAssertion,
+                    format!("Invalid assertion... {}", message),
+                )
+                .at_line(i + 1),
+            ),
+        }
+    }
+
+    match diagnostics.is_empty() {
+        true => Ok(assertions),
+        false => Err(Diagnostics(diagnostics)),
+    }
+}
+
+/// Builds `source` and runs it on `inputs`, checking its `// ASSERT` comments along the way.
+/// Problems building the program or its assertions are returned as diagnostics, as is a
+/// run that can't continue (e.g. running out of input).
+pub fn check_assertions(source: &str, inputs: &[i16]) -> Result<AssertionReport, Diagnostics> {
+    let assembly = build(source, &BuildOptions::default())?;
+    let assertions = assertions(source, &assembly.symbols)?;
+
+    let mut state = ExecutionState::from_assembly(&assembly);
+    let mut io_handler = BufferedIO::new(inputs.iter().copied());
+    let mut report = AssertionReport {
+        passed: 0,
+        failures: vec![],
+        halt_reason: HaltReason::Halted,
+        outputs: vec![],
+    };
+
+    let mut check = |state: &ExecutionState, at: Option<i16>| {
+        for assertion in assertions
+            .iter()
+            .filter(|assertion| assertion.address == at)
+        {
+            match assertion.condition.eval(state) {
+                Ok(value) if value != 0 => report.passed += 1,
+                value => report.failures.push(AssertionFailure {
+                    assertion: assertion.clone(),
+                    value,
+                    steps: state.steps_executed,
+                }),
+            }
+        }
+    };
+
+    let halt_reason = loop {
+        check(&state, Some(state.pc));
+        let event = state.step(&mut io_handler).map_err(|error| {
+            Diagnostics(vec![Diagnostic::error(
+                DiagnosticCode::RuntimeError,
+                error.to_string(),
+            )])
+        })?;
+        if let Some(halt_reason) = event.halt_reason {
+            break halt_reason;
+        }
+    };
+    if halt_reason == HaltReason::Halted {
+        check(&state, None);
+    }
+
+    report.halt_reason = halt_reason;
+    report.outputs = io_handler.take_outputs();
+    Ok(report)
+}
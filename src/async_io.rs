@@ -0,0 +1,111 @@
+use std::{future::Future, time::Instant};
+
+use crate::{
+    DecodedInstruction, ExecutionObserver, ExecutionState, ExecutionStats, HaltReason,
+    InputResponse, IoError, Output, RunResult, RuntimeError, LMCIO,
+};
+
+/// An IO handler that can wait for input without blocking the thread, e.g. for a web server
+/// taking input over a WebSocket. Used with `run_async`.
+pub trait AsyncLMCIO: Send {
+    fn get_input(&mut self) -> impl Future<Output = Result<InputResponse, IoError>> + Send;
+
+    fn print_output(&mut self, val: Output) -> impl Future<Output = Result<(), IoError>> + Send;
+
+    /// Reads a character for INA. By default this asks `get_input` for a character code.
+    fn get_char_input(
+        &mut self,
+    ) -> impl Future<Output = Result<InputResponse<char>, IoError>> + Send {
+        async {
+            Ok(match self.get_input().await? {
+                InputResponse::Value(input) => InputResponse::Value(input as u8 as char),
+                InputResponse::Eof => InputResponse::Eof,
+            })
+        }
+    }
+}
+
+/// Like `run`, but awaits the IO handler instead of blocking on it.
+pub async fn run_async<T: AsyncLMCIO>(
+    program: [i16; 100],
+    io_handler: &mut T,
+) -> Result<RunResult, RuntimeError> {
+    run_async_with_state(ExecutionState::new(program), io_handler).await
+}
+
+/// Like `run_with_state`, but awaits the IO handler instead of blocking on it.
+pub async fn run_async_with_state<T: AsyncLMCIO, const N: usize>(
+    mut state: ExecutionState<N>,
+    io_handler: &mut T,
+) -> Result<RunResult<N>, RuntimeError> {
+    let mut stats = ExecutionStats::new(N);
+    let mut steps = 0;
+    let start = Instant::now();
+
+    let halt_reason = loop {
+        if let Some(halt_reason) = state.config.limit_reached(steps, start) {
+            break halt_reason;
+        }
+
+        // fetch whatever input the instruction wants up front, so `step` itself never waits
+        let mut step_io = StepIO::default();
+        if state.awaiting_input() {
+            let word = state.ram[state.pc as usize];
+            match DecodedInstruction::decode_with(word, N as i16, &state.config.extensions) {
+                Ok(DecodedInstruction::Ina) => {
+                    step_io.char_input = Some(io_handler.get_char_input().await)
+                }
+                _ => step_io.input = Some(io_handler.get_input().await),
+            }
+        }
+
+        let event = state.step(&mut step_io)?;
+        steps += 1;
+        stats.after_step(&state, &event);
+
+        if let Some(output) = step_io.output {
+            if let Err(error) = io_handler.print_output(output).await {
+                break HaltReason::IoError(error.to_string());
+            }
+        }
+
+        if let Some(halt_reason) = event.halt_reason {
+            break halt_reason;
+        }
+    };
+
+    stats.elapsed = start.elapsed();
+
+    Ok(RunResult {
+        state,
+        halt_reason,
+        steps,
+        stats,
+    })
+}
+
+/// Gives `step` the input fetched for it and keeps its output to be sent afterwards.
+#[derive(Default)]
+struct StepIO {
+    input: Option<Result<InputResponse, IoError>>,
+    char_input: Option<Result<InputResponse<char>, IoError>>,
+    output: Option<Output>,
+}
+
+impl LMCIO for StepIO {
+    fn get_input(&mut self) -> i16 {
+        unreachable!("the machine only calls the try_ methods")
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.output = Some(val);
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        self.input.take().unwrap_or(Ok(InputResponse::Eof))
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        self.char_input.take().unwrap_or(Ok(InputResponse::Eof))
+    }
+}
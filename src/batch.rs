@@ -0,0 +1,56 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{io::BufferedIO, run_with_config, Config, Output, RunResult, RuntimeError};
+
+/// Runs `program` once for each set of inputs, spread over as many threads as the machine
+/// has cores. Results (and everything each run output) come back in the same order as
+/// `inputs`.
+pub fn run_batch(
+    program: [i16; 100],
+    inputs: Vec<Vec<i16>>,
+) -> Vec<Result<(RunResult, Vec<Output>), RuntimeError>> {
+    run_batch_with_config(program, inputs, Config::default())
+}
+
+pub fn run_batch_with_config(
+    program: [i16; 100],
+    inputs: Vec<Vec<i16>>,
+    config: Config,
+) -> Vec<Result<(RunResult, Vec<Output>), RuntimeError>> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |workers| workers.get())
+        .min(inputs.len());
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(inputs) = inputs.get(index) else {
+                            break results;
+                        };
+                        let mut io_handler = BufferedIO::new(inputs.iter().copied());
+                        let result =
+                            run_with_config(program, &mut io_handler, false, config.clone())
+                                .map(|result| (result, io_handler.take_outputs()));
+                        results.push((index, result));
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,84 @@
+//! Timing helpers for comparing interpreter changes. The criterion benchmarks in
+//! `benches/` cover the same ground in more detail.
+
+use std::time::{Duration, Instant};
+
+use crate::{CompiledProgram, Config, ExecutionState, Output, LMCIO};
+
+/// How long a number of steps took.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+impl Measurement {
+    pub fn steps_per_second(&self) -> f64 {
+        self.steps as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Answers every INP with 1 and throws output away, so IO doesn't dominate the timings.
+struct SinkIO;
+
+impl LMCIO for SinkIO {
+    fn get_input(&mut self) -> i16 {
+        1
+    }
+
+    fn print_output(&mut self, _val: Output) {}
+}
+
+/// Interprets `program` for `steps` steps, starting it again from the top whenever it stops.
+pub fn measure(program: [i16; 100], steps: u64) -> Measurement {
+    measure_with(program, steps, |state| {
+        state
+            .step(&mut SinkIO)
+            .map_or(true, |event| event.halt_reason.is_some())
+    })
+}
+
+/// Like `measure`, but runs `program` with a `CompiledProgram`.
+pub fn measure_compiled(program: [i16; 100], steps: u64) -> Measurement {
+    let compiled = CompiledProgram::compile(&ExecutionState::new(program));
+    let mut remaining = steps;
+
+    let start = Instant::now();
+    while remaining > 0 {
+        let mut state = ExecutionState::with_config(
+            program,
+            Config {
+                max_steps: Some(remaining),
+                ..Config::default()
+            },
+        );
+        let _ = compiled.run(&mut state, &mut SinkIO);
+        remaining -= state.steps_executed.clamp(1, remaining);
+    }
+
+    Measurement {
+        steps,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Calls `step` (which returns whether the machine stopped) `steps` times.
+fn measure_with(
+    program: [i16; 100],
+    steps: u64,
+    mut step: impl FnMut(&mut ExecutionState) -> bool,
+) -> Measurement {
+    let mut state = ExecutionState::new(program);
+
+    let start = Instant::now();
+    for _ in 0..steps {
+        if step(&mut state) {
+            state = ExecutionState::new(program);
+        }
+    }
+
+    Measurement {
+        steps,
+        elapsed: start.elapsed(),
+    }
+}
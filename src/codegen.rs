@@ -0,0 +1,388 @@
+//! Translates assembled programs into other languages.
+
+use std::fmt::Write;
+
+use crate::DecodedInstruction;
+
+/// Emits a self-contained Rust module that runs `program`, with one `match` arm per
+/// mailbox so the translation of each instruction can be read next to it.
+///
+/// The generated `run` function follows the default `Config`: standard instructions only,
+/// wrapping arithmetic and BRP testing the negative flag. IO goes through the `Io` trait it
+/// declares. If the program executes something the translation doesn't cover, such as an
+/// extension instruction or a mailbox it has overwritten, `run` returns `Err` with its
+/// address.
+pub fn to_rust(program: &[i16; 100]) -> String {
+    let used = program
+        .iter()
+        .rposition(|word| *word != 0)
+        .map_or(0, |last| last + 1);
+
+    let mut code = String::new();
+    code.push_str(PRELUDE);
+
+    let words: Vec<String> = program.iter().map(i16::to_string).collect();
+    writeln!(code, "const PROGRAM: [i16; 100] = [{}];", words.join(", ")).unwrap();
+    code.push_str(RUN_START);
+
+    for (address, &word) in program.iter().enumerate().take(used) {
+        let decoded = DecodedInstruction::decode(word).ok();
+        let comment = decoded.map_or(format!("DAT {}", word), |decoded| decoded.to_string());
+        writeln!(code, "            // {}", comment).unwrap();
+        writeln!(code, "            {} => {{", address).unwrap();
+        let next = address + 1;
+        let body = match decoded {
+            Some(DecodedInstruction::Lda(a)) => {
+                format!("acc = ram[{a}];\n    negative = false;\n    pc = {next};")
+            }
+            Some(DecodedInstruction::Sta(a)) => format!("ram[{a}] = acc;\n    pc = {next};"),
+            Some(DecodedInstruction::Add(a)) => format!(
+                "acc = wrap(i32::from(acc) + i32::from(ram[{a}]));\n    negative = false;\n    pc = {next};"
+            ),
+            Some(DecodedInstruction::Sub(a)) => format!(
+                "let result = i32::from(acc) - i32::from(ram[{a}]);\n    negative = result < 0;\n    acc = wrap(result);\n    pc = {next};"
+            ),
+            Some(DecodedInstruction::Inp) => {
+                format!("acc = io.input();\n    negative = false;\n    pc = {next};")
+            }
+            Some(DecodedInstruction::Ina) => {
+                format!("acc = io.input_char() as i16;\n    negative = false;\n    pc = {next};")
+            }
+            Some(DecodedInstruction::Out) => format!("io.output(acc);\n    pc = {next};"),
+            Some(DecodedInstruction::Otc) => {
+                format!("io.output_char(acc as u8 as char);\n    pc = {next};")
+            }
+            Some(DecodedInstruction::Hlt) => "return Ok(());".to_string(),
+            Some(DecodedInstruction::Bra(a)) => format!("pc = {a};"),
+            Some(DecodedInstruction::Brz(a)) => {
+                format!("pc = if acc == 0 {{ {a} }} else {{ {next} }};")
+            }
+            Some(DecodedInstruction::Brp(a)) => {
+                format!("pc = if !negative {{ {a} }} else {{ {next} }};")
+            }
+            _ => format!("return Err({address});"),
+        };
+        for line in body.lines() {
+            writeln!(code, "                {}", line.trim_start()).unwrap();
+        }
+        code.push_str("            }\n");
+    }
+
+    code.push_str(RUN_END);
+    code
+}
+
+const PRELUDE: &str = "// Generated by lmc-assembly.
+
+pub trait Io {
+    fn input(&mut self) -> i16;
+    fn input_char(&mut self) -> char;
+    fn output(&mut self, value: i16);
+    fn output_char(&mut self, c: char);
+}
+
+fn wrap(value: i32) -> i16 {
+    ((value + 999).rem_euclid(1999) - 999) as i16
+}
+
+";
+
+const RUN_START: &str = "
+/// Runs the program until it halts. Returns `Err` with the address of an instruction this
+/// translation can't execute.
+#[allow(unused_mut, unused_assignments, unused_variables)]
+pub fn run<T: Io>(io: &mut T) -> Result<(), usize> {
+    let mut ram = PROGRAM;
+    let mut acc: i16 = 0;
+    let mut negative = false;
+    let mut pc: usize = 0;
+
+    loop {
+        if pc >= ram.len() || ram[pc] != PROGRAM[pc] {
+            return Err(pc);
+        }
+
+        match pc {
+";
+
+const RUN_END: &str = "            // the rest of memory is zero, i.e. HLT
+            _ => return Ok(()),
+        }
+    }
+}
+";
+
+/// Emits a WebAssembly module that runs `program`, with the same semantics as `to_rust`.
+///
+/// The module imports `env.input` and `env.input_char` (both `() -> i32`) and
+/// `env.output` and `env.output_char` (both `(i32) -> ()`). It exports its `memory`, where
+/// mailbox `n` is the `i32` at byte `4 * n`, and `run: () -> i32`, which returns -1 once
+/// the program halts or the address of an instruction the translation can't execute.
+pub fn to_wasm(program: &[i16; 100]) -> Vec<u8> {
+    let used = program
+        .iter()
+        .rposition(|word| *word != 0)
+        .map_or(0, |last| last + 1);
+
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+    // types: 0 is () -> i32, 1 is (i32) -> ()
+    section(&mut module, 1, &[2, 0x60, 0, 1, I32, 0x60, 1, I32, 0]);
+
+    let mut imports = vec![4];
+    for (name, ty) in [
+        ("input", 0),
+        ("input_char", 0),
+        ("output", 1),
+        ("output_char", 1),
+    ] {
+        name_bytes(&mut imports, "env");
+        name_bytes(&mut imports, name);
+        imports.extend([0, ty]);
+    }
+    section(&mut module, 2, &imports);
+
+    section(&mut module, 3, &[1, 0]);
+    // one page, comfortably more than the 400 bytes needed
+    section(&mut module, 5, &[1, 0, 1]);
+
+    let mut exports = vec![2];
+    name_bytes(&mut exports, "run");
+    exports.extend([0, 4]);
+    name_bytes(&mut exports, "memory");
+    exports.extend([2, 0]);
+    section(&mut module, 7, &exports);
+
+    let body = run_body(program, used);
+    let mut code = vec![1];
+    uleb(&mut code, body.len() as u32);
+    code.extend(body);
+    section(&mut module, 10, &code);
+
+    let mut data = vec![1, 0, OP_I32_CONST, 0, OP_END];
+    uleb(&mut data, 400);
+    for word in program {
+        data.extend(i32::from(*word).to_le_bytes());
+    }
+    section(&mut module, 11, &data);
+
+    module
+}
+
+const I32: u8 = 0x7f;
+const EMPTY: u8 = 0x40;
+const OP_UNREACHABLE: u8 = 0x00;
+const OP_BLOCK: u8 = 0x02;
+const OP_LOOP: u8 = 0x03;
+const OP_IF: u8 = 0x04;
+const OP_END: u8 = 0x0b;
+const OP_BR: u8 = 0x0c;
+const OP_BR_TABLE: u8 = 0x0e;
+const OP_RETURN: u8 = 0x0f;
+const OP_CALL: u8 = 0x10;
+const OP_SELECT: u8 = 0x1b;
+const OP_LOCAL_GET: u8 = 0x20;
+const OP_LOCAL_SET: u8 = 0x21;
+const OP_LOCAL_TEE: u8 = 0x22;
+const OP_I32_LOAD: u8 = 0x28;
+const OP_I32_STORE: u8 = 0x36;
+const OP_I32_CONST: u8 = 0x41;
+const OP_I32_EQZ: u8 = 0x45;
+const OP_I32_NE: u8 = 0x47;
+const OP_I32_LT_S: u8 = 0x48;
+const OP_I32_GE_U: u8 = 0x4f;
+const OP_I32_ADD: u8 = 0x6a;
+const OP_I32_SUB: u8 = 0x6b;
+const OP_I32_MUL: u8 = 0x6c;
+const OP_I32_REM_U: u8 = 0x70;
+const OP_I32_AND: u8 = 0x71;
+
+// locals of `run`
+const PC: u8 = 0;
+const ACC: u8 = 1;
+const NEGATIVE: u8 = 2;
+const RESULT: u8 = 3;
+
+/// `run` dispatches on the PC with a `br_table` into nested blocks, one per mailbox, all
+/// inside a loop that each instruction branches back to.
+fn run_body(program: &[i16; 100], used: usize) -> Vec<u8> {
+    let mut body = vec![1, 4, I32];
+
+    body.extend([OP_LOOP, EMPTY, OP_BLOCK, EMPTY]);
+    body.extend((0..used).flat_map(|_| [OP_BLOCK, EMPTY]));
+    body.extend([OP_LOCAL_GET, PC, OP_BR_TABLE]);
+    uleb(&mut body, used as u32);
+    for case in 0..=used {
+        uleb(&mut body, case as u32);
+    }
+
+    for (address, &word) in program.iter().enumerate().take(used) {
+        body.push(OP_END);
+        let address = address as i32;
+        let next = address + 1;
+
+        // give up on mailboxes the program has overwritten
+        load(&mut body, address);
+        i32_const(&mut body, i32::from(word));
+        body.extend([OP_I32_NE, OP_IF, EMPTY]);
+        i32_const(&mut body, address);
+        body.extend([OP_RETURN, OP_END]);
+
+        match DecodedInstruction::decode(word).ok() {
+            Some(DecodedInstruction::Lda(a)) => {
+                load(&mut body, i32::from(a));
+                body.extend([OP_LOCAL_SET, ACC]);
+                clear_negative(&mut body);
+            }
+            Some(DecodedInstruction::Sta(a)) => {
+                i32_const(&mut body, i32::from(a) * 4);
+                body.extend([OP_LOCAL_GET, ACC, OP_I32_STORE, 2, 0]);
+            }
+            Some(DecodedInstruction::Add(a)) => {
+                body.extend([OP_LOCAL_GET, ACC]);
+                load(&mut body, i32::from(a));
+                body.push(OP_I32_ADD);
+                wrap(&mut body);
+                body.extend([OP_LOCAL_SET, ACC]);
+                clear_negative(&mut body);
+            }
+            Some(DecodedInstruction::Sub(a)) => {
+                body.extend([OP_LOCAL_GET, ACC]);
+                load(&mut body, i32::from(a));
+                body.extend([OP_I32_SUB, OP_LOCAL_TEE, RESULT]);
+                wrap(&mut body);
+                body.extend([OP_LOCAL_SET, ACC, OP_LOCAL_GET, RESULT]);
+                i32_const(&mut body, 0);
+                body.extend([OP_I32_LT_S, OP_LOCAL_SET, NEGATIVE]);
+            }
+            Some(DecodedInstruction::Inp) => {
+                body.extend([OP_CALL, 0, OP_LOCAL_SET, ACC]);
+                clear_negative(&mut body);
+            }
+            Some(DecodedInstruction::Ina) => {
+                body.extend([OP_CALL, 1, OP_LOCAL_SET, ACC]);
+                clear_negative(&mut body);
+            }
+            Some(DecodedInstruction::Out) => body.extend([OP_LOCAL_GET, ACC, OP_CALL, 2]),
+            Some(DecodedInstruction::Otc) => {
+                body.extend([OP_LOCAL_GET, ACC]);
+                i32_const(&mut body, 0xff);
+                body.extend([OP_I32_AND, OP_CALL, 3]);
+            }
+            Some(DecodedInstruction::Hlt) => {
+                i32_const(&mut body, -1);
+                body.push(OP_RETURN);
+            }
+            Some(DecodedInstruction::Bra(a)) => i32_const(&mut body, i32::from(a) - next),
+            Some(DecodedInstruction::Brz(a)) => {
+                i32_const(&mut body, i32::from(a) - next);
+                i32_const(&mut body, 0);
+                body.extend([OP_LOCAL_GET, ACC, OP_I32_EQZ, OP_SELECT]);
+            }
+            Some(DecodedInstruction::Brp(a)) => {
+                i32_const(&mut body, i32::from(a) - next);
+                i32_const(&mut body, 0);
+                body.extend([OP_LOCAL_GET, NEGATIVE, OP_I32_EQZ, OP_SELECT]);
+            }
+            _ => {
+                i32_const(&mut body, address);
+                body.push(OP_RETURN);
+            }
+        }
+
+        // branches leave how far to jump from the next mailbox on the stack
+        let branches = matches!(
+            DecodedInstruction::decode(word),
+            Ok(DecodedInstruction::Bra(_)
+                | DecodedInstruction::Brz(_)
+                | DecodedInstruction::Brp(_))
+        );
+        i32_const(&mut body, next);
+        if branches {
+            body.push(OP_I32_ADD);
+        }
+        body.extend([OP_LOCAL_SET, PC, OP_BR]);
+        uleb(&mut body, (used as i32 - address) as u32);
+    }
+
+    // past the mailboxes in use, where memory is zero (HLT) unless the program wrote to it
+    body.push(OP_END);
+    body.extend([OP_LOCAL_GET, PC]);
+    i32_const(&mut body, 100);
+    body.extend([
+        OP_I32_GE_U,
+        OP_IF,
+        EMPTY,
+        OP_LOCAL_GET,
+        PC,
+        OP_RETURN,
+        OP_END,
+    ]);
+    body.extend([OP_LOCAL_GET, PC]);
+    i32_const(&mut body, 4);
+    body.extend([OP_I32_MUL, OP_I32_LOAD, 2, 0, OP_IF, EMPTY]);
+    body.extend([OP_LOCAL_GET, PC, OP_RETURN, OP_END]);
+    i32_const(&mut body, -1);
+    body.extend([OP_RETURN, OP_END, OP_UNREACHABLE, OP_END]);
+
+    body
+}
+
+fn load(body: &mut Vec<u8>, address: i32) {
+    i32_const(body, address * 4);
+    body.extend([OP_I32_LOAD, 2, 0]);
+}
+
+fn clear_negative(body: &mut Vec<u8>) {
+    i32_const(body, 0);
+    body.extend([OP_LOCAL_SET, NEGATIVE]);
+}
+
+/// Wraps the value on the stack into -999..=999, like `OverflowPolicy::Wrap`. Sums and
+/// differences of two values are at least -1998, so adding 2998 keeps the remainder
+/// unsigned.
+fn wrap(body: &mut Vec<u8>) {
+    i32_const(body, 2998);
+    body.push(OP_I32_ADD);
+    i32_const(body, 1999);
+    body.push(OP_I32_REM_U);
+    i32_const(body, 999);
+    body.push(OP_I32_SUB);
+}
+
+fn i32_const(body: &mut Vec<u8>, value: i32) {
+    body.push(OP_I32_CONST);
+    let mut value = value;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        body.push(if done { byte } else { byte | 0x80 });
+        if done {
+            break;
+        }
+    }
+}
+
+fn uleb(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            break;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn name_bytes(bytes: &mut Vec<u8>, name: &str) {
+    uleb(bytes, name.len() as u32);
+    bytes.extend(name.bytes());
+}
+
+fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    uleb(module, contents.len() as u32);
+    module.extend(contents);
+}
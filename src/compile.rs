@@ -0,0 +1,168 @@
+use std::time::Instant;
+
+use crate::{
+    run_in_place, BrpMode, DecodedInstruction, ExecutionState, HaltReason, RuntimeError,
+    UninitializedReads, LMCIO,
+};
+
+/// Runs one instruction without the interpreter, or returns `false` (having changed
+/// nothing) to have the interpreter run it instead.
+type Op = Box<dyn Fn(&mut ExecutionState) -> bool + Send + Sync>;
+
+/// A program compiled to one closure per mailbox, for running millions of steps quickly.
+///
+/// Loads, stores, arithmetic and branches run as closures; everything else (IO, HLT,
+/// extension instructions, and any ADD or SUB that overflows) is handed to the interpreter
+/// a step at a time. A mailbox that has been overwritten since it was compiled is
+/// interpreted too, so self-modifying programs still behave exactly as they would in
+/// `run_with_state`. The fast path only tracks what `ExecutionState` itself holds: no
+/// `StepEvent`s are produced, so runs that need watchpoints, breakpoints, tracing,
+/// profiling, history, a timer or strict checks are interpreted throughout.
+pub struct CompiledProgram {
+    ops: Vec<Option<Op>>,
+}
+
+impl CompiledProgram {
+    pub fn compile(state: &ExecutionState) -> Self {
+        let last = state.ram.len() - 1;
+        let ops = state
+            .ram
+            .iter()
+            .enumerate()
+            .map(|(address, &word)| {
+                // running off the end of memory is left to the interpreter
+                if address == last {
+                    return None;
+                }
+                let instruction =
+                    DecodedInstruction::decode_with(word, 100, &state.config.extensions).ok()?;
+                compile_instruction(address as i16, word, instruction)
+            })
+            .collect();
+
+        CompiledProgram { ops }
+    }
+
+    /// Runs until the machine stops, like `run_with_state`, returning why.
+    pub fn run<T: LMCIO>(
+        &self,
+        state: &mut ExecutionState,
+        io_handler: &mut T,
+    ) -> Result<HaltReason, RuntimeError> {
+        if !Self::supports(state) {
+            return run_in_place(state, io_handler, &mut ()).map(|(halt_reason, ..)| halt_reason);
+        }
+
+        let mut steps = 0;
+        let start = Instant::now();
+        loop {
+            if let Some(halt_reason) = state.config.limit_reached(steps, start) {
+                return Ok(halt_reason);
+            }
+            steps += 1;
+
+            let op = self.ops.get(state.pc as usize).and_then(Option::as_ref);
+            if op.is_some_and(|op| op(state)) {
+                continue;
+            }
+
+            if let Some(halt_reason) = state.step(io_handler)?.halt_reason {
+                return Ok(halt_reason);
+            }
+        }
+    }
+
+    /// Whether the closures can be used for `state`, rather than interpreting every step.
+    fn supports(state: &ExecutionState) -> bool {
+        state.watchpoints.is_empty()
+            && state.breakpoints.is_empty()
+            && state.trace.is_none()
+            && state.profile.is_none()
+            && state.heatmap.is_none()
+            && state.history.is_none()
+            && state.config.timer.is_none()
+            && state.config.uninitialized_reads == UninitializedReads::Allow
+            && !state.config.strict_values
+    }
+}
+
+fn compile_instruction(pc: i16, word: i16, instruction: DecodedInstruction) -> Option<Op> {
+    // the bookkeeping every instruction does, as in `ExecutionState::step`
+    let fetch = move |state: &mut ExecutionState, cost: u64| {
+        state.mar = pc;
+        state.pc = pc + 1;
+        state.mdr = word;
+        state.cir = word;
+        state.executed[pc as usize] = true;
+        state.steps_executed += 1;
+        state.cycles += cost;
+    };
+    // the mailbox has been overwritten since it was compiled
+    let stale = move |state: &ExecutionState| state.ram[pc as usize] != word;
+
+    Some(match instruction {
+        DecodedInstruction::Lda(address) => Box::new(move |state| {
+            if stale(state) {
+                return false;
+            }
+            fetch(state, state.config.cycle_costs.cost(&instruction));
+            state.mar = address;
+            state.acc = state.ram[address as usize];
+            state.negative = false;
+            true
+        }),
+        DecodedInstruction::Sta(address) => Box::new(move |state| {
+            if stale(state) {
+                return false;
+            }
+            fetch(state, state.config.cycle_costs.cost(&instruction));
+            state.mar = address;
+            state.set_mailbox(address as usize, state.acc);
+            if let Some(initialized) = state.initialized.get_mut(address as usize) {
+                *initialized = true;
+            }
+            true
+        }),
+        DecodedInstruction::Add(address) | DecodedInstruction::Sub(address) => {
+            Box::new(move |state| {
+                let value = i32::from(state.ram[address as usize]);
+                let result = match instruction {
+                    DecodedInstruction::Add(_) => i32::from(state.acc) + value,
+                    _ => i32::from(state.acc) - value,
+                };
+                let max = i32::from(ExecutionState::<100>::MAX_VALUE);
+                if stale(state) || !(-max..=max).contains(&result) {
+                    return false;
+                }
+                fetch(state, state.config.cycle_costs.cost(&instruction));
+                state.mar = address;
+                state.acc = result as i16;
+                state.negative = result < 0 && matches!(instruction, DecodedInstruction::Sub(_));
+                state.overflow = false;
+                true
+            })
+        }
+        DecodedInstruction::Bra(address)
+        | DecodedInstruction::Brz(address)
+        | DecodedInstruction::Brp(address) => Box::new(move |state| {
+            if stale(state) {
+                return false;
+            }
+            let taken = match instruction {
+                DecodedInstruction::Bra(_) => true,
+                DecodedInstruction::Brz(_) => state.acc == 0,
+                _ => match state.config.brp_mode {
+                    BrpMode::NegativeFlag => !state.negative,
+                    BrpMode::SignedAccumulator => state.acc >= 0,
+                },
+            };
+            fetch(state, state.config.cycle_costs.cost(&instruction));
+            state.mar = address;
+            if taken {
+                state.pc = address;
+            }
+            true
+        }),
+        _ => return None,
+    })
+}
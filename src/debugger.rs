@@ -0,0 +1,546 @@
+//! A command-driven debugger that frontends (a CLI, a TUI, a web page) can share. It only
+//! deals in commands and responses; drawing them is up to the frontend.
+
+use std::{ops::Range, str::FromStr};
+
+use crate::{
+    disassemble_word, Assembly, BinOp, CellKind, Encoding, ExecutionState, Expr, Instruction,
+    MemoryWrite, Operand, Output, Register, RegisterChange, Session, SessionStatus, StateDiff,
+    StepEvent, SymbolTable,
+};
+
+/// Lines of source (or mailboxes) shown either side of the current one by `Command::List`
+/// and `Command::Disassemble`.
+const LIST_CONTEXT: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Executes this many instructions, stopping early if the machine stops.
+    Step(usize),
+    /// Runs until a breakpoint, the machine stops, or it needs input.
+    Continue,
+    /// Steps until the next instruction comes from a different source line.
+    Next,
+    /// Runs until the next instruction comes from a source line (or something else stops
+    /// it, as with `Continue`).
+    RunToLine(usize),
+    /// Pauses before the instruction at an address is executed.
+    Break(i16),
+    /// Removes a breakpoint set with `Break`.
+    Delete(i16),
+    /// Evaluates an expression, e.g. `ACC` or `RAM[10] + 1`.
+    Print(Expr),
+    /// Adds an expression to be evaluated and reported whenever instructions are executed.
+    Watch(Expr),
+    /// Removes the watch expression at an index in `Debugger::watches`.
+    Unwatch(usize),
+    /// Reads `count` mailboxes starting at `address`.
+    Read {
+        address: i16,
+        count: usize,
+    },
+    /// Stores a value in a mailbox.
+    Write {
+        address: i16,
+        value: i16,
+    },
+    SetRegister {
+        register: Register,
+        value: i16,
+    },
+    /// Queues a value for INP (or a character code for INA).
+    Input(i16),
+    /// Shows the source around the current instruction.
+    List,
+    /// Disassembles `count` mailboxes starting at `address`, or the ones around PC.
+    Disassemble(Option<(i16, usize)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Instructions were executed. `events` is only filled in for `Step`, since `Continue`
+    /// can run for millions of steps.
+    Ran {
+        steps: usize,
+        events: Vec<StepEvent>,
+        outputs: Vec<Output>,
+        status: SessionStatus,
+        /// The value of each watch expression afterwards.
+        watches: Vec<WatchValue>,
+    },
+    Breakpoints(Vec<i16>),
+    Watches(Vec<Expr>),
+    Value(i32),
+    /// `(address, value)` pairs.
+    Memory(Vec<(i16, i16)>),
+    /// Numbered source lines, along with the one the next instruction came from.
+    Listing {
+        lines: Vec<(usize, String)>,
+        current: Option<usize>,
+    },
+    Disassembly(Vec<DisassembledCell>),
+    /// The machine was changed by `Write` or `SetRegister`, so frontends can update
+    /// without fetching the whole state.
+    Changed(StateDiff),
+    Done,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchValue {
+    pub expr: Expr,
+    /// The error if the expression couldn't be evaluated, e.g. `RAM[ACC]` with ACC negative.
+    pub value: Result<i32, String>,
+}
+
+/// A mailbox as it currently reads, so self-modified code shows up as the new instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassembledCell {
+    pub address: i16,
+    pub word: i16,
+    /// The label defined at this address, if any.
+    pub label: Option<String>,
+    /// Data cells that have never been executed are shown as `DAT`, and addresses are
+    /// replaced by their labels.
+    pub instruction: Instruction,
+    /// Whether this is the next instruction to be executed.
+    pub current: bool,
+}
+
+/// Runs a machine under the control of `Command`s.
+pub struct Debugger {
+    session: Session,
+    breakpoints: Vec<i16>,
+    watches: Vec<Expr>,
+    symbols: SymbolTable,
+    encoding: Encoding,
+    source: Vec<String>,
+    source_lines: Vec<Option<usize>>,
+    /// Where the last command that ran the machine left PC, so `Continue` doesn't stop
+    /// again at a breakpoint it's resuming from.
+    stopped_at: Option<i16>,
+}
+
+impl Debugger {
+    pub fn new(state: ExecutionState) -> Self {
+        Debugger {
+            session: Session::new(state),
+            breakpoints: vec![],
+            watches: vec![],
+            symbols: SymbolTable::default(),
+            encoding: Encoding::default(),
+            source: vec![],
+            source_lines: vec![],
+            stopped_at: None,
+        }
+    }
+
+    /// Debugs an assembled program, so that breakpoints can be given as labels and the
+    /// source can be listed.
+    pub fn with_source(assembly: &Assembly, source: &str) -> Self {
+        Debugger {
+            symbols: assembly.symbols.clone(),
+            encoding: assembly.encoding.clone(),
+            source: source.lines().map(str::to_string).collect(),
+            source_lines: assembly.source_lines.clone(),
+            ..Self::new(ExecutionState::from_assembly(assembly))
+        }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn state(&self) -> &ExecutionState {
+        self.session.state()
+    }
+
+    pub fn breakpoints(&self) -> &[i16] {
+        &self.breakpoints
+    }
+
+    pub fn watches(&self) -> &[Expr] {
+        &self.watches
+    }
+
+    /// Evaluates every watch expression against the current state.
+    pub fn watch_values(&self) -> Vec<WatchValue> {
+        self.watches
+            .iter()
+            .map(|expr| WatchValue {
+                expr: expr.clone(),
+                value: expr.eval(self.state()),
+            })
+            .collect()
+    }
+
+    /// Disassembles the mailboxes in `addresses` from the current RAM.
+    pub fn disassemble(&self, addresses: Range<usize>) -> Vec<DisassembledCell> {
+        let state = self.state();
+        let end = addresses.end.min(state.ram.len());
+
+        (addresses.start.min(end)..end)
+            .map(|address| {
+                let word = state.ram[address];
+                let is_data =
+                    state.kinds.get(address) == Some(&CellKind::Data) && !state.executed[address];
+                let instruction = match is_data {
+                    true => Instruction::DAT(Operand::Value(word)),
+                    false => self.with_labels(disassemble_word(word, &self.encoding)),
+                };
+                DisassembledCell {
+                    address: address as i16,
+                    word,
+                    label: self.label(address as i16).map(str::to_string),
+                    instruction,
+                    current: address == state.pc as usize,
+                }
+            })
+            .collect()
+    }
+
+    /// Parses a command typed by the user, e.g. `step 5`, `break LOOP`, `set ACC 7` or
+    /// `print ACC`.
+    /// Addresses (including in expressions) can be given as labels when the debugger has
+    /// the program's symbols.
+    pub fn parse(&self, line: &str) -> Result<Command, String> {
+        let (name, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let arg = |index: usize| {
+            args.get(index)
+                .copied()
+                .ok_or_else(|| format!("Missing argument for {name}"))
+        };
+
+        Ok(match (name.to_lowercase().as_str(), args.len()) {
+            ("step" | "s", 0) => Command::Step(1),
+            ("step" | "s", 1) => Command::Step(parse_number(arg(0)?)?),
+            ("continue" | "c", 0) => Command::Continue,
+            ("next" | "n", 0) => Command::Next,
+            ("until" | "u", 1) => Command::RunToLine(parse_number(arg(0)?)?),
+            ("break" | "b", 1) => Command::Break(self.address(arg(0)?)?),
+            ("delete" | "d", 1) => Command::Delete(self.address(arg(0)?)?),
+            ("print" | "p", 1..) => Command::Print(self.expr(&args)?),
+            ("watch" | "w", 1..) => Command::Watch(self.expr(&args)?),
+            ("unwatch", 1) => Command::Unwatch(parse_number(arg(0)?)?),
+            ("read" | "x", 1 | 2) => Command::Read {
+                address: self.address(arg(0)?)?,
+                count: args.get(1).map_or(Ok(1), |count| parse_number(count))?,
+            },
+            ("set", 2) if arg(0)?.parse::<Register>().is_ok() => {
+                let register = arg(0)?.parse()?;
+                let value = match register {
+                    Register::Pc | Register::Mar | Register::Sp => self.address(arg(1)?)?,
+                    _ => parse_number(arg(1)?)?,
+                };
+                Command::SetRegister { register, value }
+            }
+            ("write" | "set", 2) => Command::Write {
+                address: self.address(arg(0)?)?,
+                value: parse_number(arg(1)?)?,
+            },
+            ("input" | "i", 1) => Command::Input(parse_number(arg(0)?)?),
+            ("list" | "l", 0) => Command::List,
+            ("disassemble" | "dis", 0) => Command::Disassemble(None),
+            ("disassemble" | "dis", 1 | 2) => Command::Disassemble(Some((
+                self.address(arg(0)?)?,
+                args.get(1).map_or(Ok(1), |count| parse_number(count))?,
+            ))),
+            ("step" | "s" | "continue" | "c" | "next" | "n" | "until" | "u" | "break" | "b", _)
+            | ("delete" | "d" | "print" | "p" | "read" | "x" | "write" | "set", _)
+            | ("watch" | "w" | "unwatch" | "input" | "i" | "list" | "l", _)
+            | ("disassemble" | "dis", _) => {
+                return Err(format!("Wrong number of arguments for {name}"))
+            }
+            _ => return Err(format!("Unknown command... {name}")),
+        })
+    }
+
+    /// Parses and runs a command.
+    pub fn execute_str(&mut self, line: &str) -> Result<Response, String> {
+        let command = self.parse(line)?;
+        self.execute(command)
+    }
+
+    pub fn execute(&mut self, command: Command) -> Result<Response, String> {
+        match command {
+            Command::Step(count) => {
+                let mut events = vec![];
+                while events.len() < count {
+                    match self.session.step() {
+                        Some(result) => {
+                            let event = result?;
+                            let stopped = event.halt_reason.is_some();
+                            events.push(event);
+                            if stopped {
+                                break;
+                            }
+                        }
+                        None => break,
+                    }
+                }
+                Ok(self.ran(events.len(), events))
+            }
+            Command::Continue => {
+                // breakpoints are checked after each step, so one on the first instruction
+                // of a fresh run (or wherever PC was moved to) is checked here instead
+                let pc = self.state().pc;
+                if self.breakpoints.contains(&pc) && self.stopped_at != Some(pc) {
+                    self.session.pause();
+                    return Ok(self.ran(0, vec![]));
+                }
+                self.session.resume();
+                let steps = self.run_until(|_| false)?;
+                Ok(self.ran(steps, vec![]))
+            }
+            Command::Next => {
+                self.check_source()?;
+                let start = self.current_line();
+                let steps = self.run_until(|debugger| {
+                    debugger
+                        .current_line()
+                        .is_some_and(|line| Some(line) != start)
+                })?;
+                Ok(self.ran(steps, vec![]))
+            }
+            Command::RunToLine(line) => {
+                self.check_source()?;
+                if !self.source_lines.contains(&Some(line)) {
+                    return Err(format!("No instruction on line {line}"));
+                }
+                let steps = self.run_until(|debugger| debugger.current_line() == Some(line))?;
+                Ok(self.ran(steps, vec![]))
+            }
+            Command::Break(address) => {
+                self.check_address(address)?;
+                if !self.breakpoints.contains(&address) {
+                    self.breakpoints.push(address);
+                    self.session
+                        .state_mut()
+                        .breakpoints
+                        .push(breakpoint_condition(address));
+                }
+                Ok(Response::Breakpoints(self.breakpoints.clone()))
+            }
+            Command::Delete(address) => {
+                if !self.breakpoints.contains(&address) {
+                    return Err(format!("No breakpoint at {address}"));
+                }
+                self.breakpoints.retain(|breakpoint| *breakpoint != address);
+                let condition = breakpoint_condition(address);
+                self.session
+                    .state_mut()
+                    .breakpoints
+                    .retain(|breakpoint| *breakpoint != condition);
+                Ok(Response::Breakpoints(self.breakpoints.clone()))
+            }
+            Command::Print(expr) => Ok(Response::Value(expr.eval(self.state())?)),
+            Command::Watch(expr) => {
+                self.watches.push(expr);
+                Ok(Response::Watches(self.watches.clone()))
+            }
+            Command::Unwatch(index) => {
+                if index >= self.watches.len() {
+                    return Err(format!("No watch expression... {index}"));
+                }
+                self.watches.remove(index);
+                Ok(Response::Watches(self.watches.clone()))
+            }
+            Command::Read { address, count } => {
+                self.check_address(address)?;
+                let end = (address as usize)
+                    .saturating_add(count)
+                    .min(self.state().ram.len());
+                Ok(Response::Memory(
+                    (address as usize..end)
+                        .map(|address| (address as i16, self.state().ram[address]))
+                        .collect(),
+                ))
+            }
+            Command::Write { address, value } => {
+                self.check_address(address)?;
+                check_value(value)?;
+                let old = self.state().ram[address as usize];
+                let mut diff = StateDiff::default();
+                if old != value {
+                    diff.ram.push(MemoryWrite {
+                        address,
+                        old,
+                        new: value,
+                    });
+                }
+                Ok(self.change(diff))
+            }
+            Command::SetRegister { register, value } => {
+                match register {
+                    Register::Pc | Register::Mar => self.check_address(value)?,
+                    // SP is the number of mailboxes when the stack is empty
+                    Register::Sp if value != self.state().ram.len() as i16 => {
+                        self.check_address(value)?
+                    }
+                    Register::Sp => {}
+                    _ => check_value(value)?,
+                }
+                let old = Expr::Register(register).eval(self.state())? as i16;
+                let mut diff = StateDiff::default();
+                if old != value {
+                    diff.registers.push(RegisterChange {
+                        register,
+                        old,
+                        new: value,
+                    });
+                }
+                Ok(self.change(diff))
+            }
+            Command::Input(value) => {
+                self.session.provide_input(value);
+                Ok(Response::Done)
+            }
+            Command::List => {
+                self.check_source()?;
+                let current = self.current_line();
+                let centre = current.unwrap_or(1);
+                let first = centre.saturating_sub(LIST_CONTEXT).max(1);
+                let last = (centre + LIST_CONTEXT).min(self.source.len());
+                Ok(Response::Listing {
+                    lines: (first..=last)
+                        .map(|line| (line, self.source[line - 1].clone()))
+                        .collect(),
+                    current,
+                })
+            }
+            Command::Disassemble(Some((address, count))) => {
+                self.check_address(address)?;
+                let start = address as usize;
+                Ok(Response::Disassembly(
+                    self.disassemble(start..start.saturating_add(count)),
+                ))
+            }
+            Command::Disassemble(None) => {
+                // PC can be anywhere once the machine has run off the end of memory
+                let pc = (self.state().pc.max(0) as usize).min(self.state().ram.len());
+                Ok(Response::Disassembly(self.disassemble(
+                    pc.saturating_sub(LIST_CONTEXT)..pc + LIST_CONTEXT + 1,
+                )))
+            }
+        }
+    }
+
+    /// Steps until `done` holds after a step, the machine stops or pauses, it needs input, or
+    /// `Config::max_steps` steps have been taken. Returns the number of steps taken.
+    fn run_until(&mut self, done: impl Fn(&Self) -> bool) -> Result<usize, String> {
+        let limit = self.state().config.max_steps.unwrap_or(u64::MAX);
+        let mut steps = 0;
+
+        while steps < limit {
+            let Some(result) = self.session.step() else {
+                break;
+            };
+            steps += 1;
+            if result?.halt_reason.is_some() || done(self) {
+                break;
+            }
+        }
+
+        Ok(steps as usize)
+    }
+
+    /// The source line the next instruction came from.
+    fn current_line(&self) -> Option<usize> {
+        self.source_lines
+            .get(self.state().pc as usize)
+            .copied()
+            .flatten()
+    }
+
+    fn check_source(&self) -> Result<(), String> {
+        match self.source.is_empty() {
+            true => Err("No source loaded".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    fn ran(&mut self, steps: usize, events: Vec<StepEvent>) -> Response {
+        self.stopped_at = Some(self.state().pc);
+        Response::Ran {
+            steps,
+            events,
+            outputs: self.session.take_outputs(),
+            status: self.session.status().clone(),
+            watches: self.watch_values(),
+        }
+    }
+
+    fn change(&mut self, diff: StateDiff) -> Response {
+        diff.apply(self.session.state_mut());
+        Response::Changed(diff)
+    }
+
+    /// The first label defined at `address`.
+    fn label(&self, address: i16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|(_, value)| *value == address)
+            .map(|(name, _)| name)
+    }
+
+    /// Replaces the addresses in a disassembled instruction with their labels.
+    fn with_labels(&self, instruction: Instruction) -> Instruction {
+        let labelled = |operand: &Operand| match operand {
+            Operand::Value(address) => self
+                .label(*address)
+                .map(|name| Operand::Label(name.to_string())),
+            Operand::Indirect(pointer) => match **pointer {
+                Operand::Value(address) => self
+                    .label(address)
+                    .map(|name| Operand::Indirect(Box::new(Operand::Label(name.to_string())))),
+                _ => None,
+            },
+            Operand::Label(_) => None,
+        };
+
+        instruction
+            .operand()
+            .and_then(labelled)
+            .and_then(|operand| Instruction::from_string(instruction.mnemonic(), Some(operand)))
+            .unwrap_or(instruction)
+    }
+
+    fn expr(&self, args: &[&str]) -> Result<Expr, String> {
+        Expr::parse_with_symbols(&args.join(" "), &self.symbols)
+    }
+
+    fn address(&self, arg: &str) -> Result<i16, String> {
+        match self.symbols.get(arg) {
+            Some(address) => Ok(address),
+            None => parse_number(arg),
+        }
+    }
+
+    fn check_address(&self, address: i16) -> Result<(), String> {
+        match (0..self.state().ram.len() as i16).contains(&address) {
+            true => Ok(()),
+            false => Err(format!("Address out of range... {address}")),
+        }
+    }
+}
+
+/// `PC == address`, which pauses the session just before the instruction at `address`.
+fn breakpoint_condition(address: i16) -> Expr {
+    Expr::Binary(
+        Box::new(Expr::Register(Register::Pc)),
+        BinOp::Eq,
+        Box::new(Expr::Number(address as i32)),
+    )
+}
+
+fn check_value(value: i16) -> Result<(), String> {
+    let max = <ExecutionState>::MAX_VALUE;
+    match (-max..=max).contains(&value) {
+        true => Ok(()),
+        false => Err(format!("Value out of range... {value}")),
+    }
+}
+
+fn parse_number<T: FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("Invalid number... {arg}"))
+}
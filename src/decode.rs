@@ -0,0 +1,270 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Instruction, Operand};
+
+/// Optional instructions on top of the standard set. The assembler only accepts them with
+/// an `Encoding` that has them enabled, and the VM only executes them when they're enabled
+/// in its `Config`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Extensions {
+    /// `LDA @PTR` and `STA @PTR`, which use the address stored in PTR. They're encoded as the
+    /// negated direct instruction, e.g. `LDA @5` is -505.
+    pub indirect: bool,
+    /// A stack growing down from the last mailbox, with PUSH (930), POP (931), RET (932) and
+    /// `CALL X`, encoded as a negated BRA (so `CALL 5` is -605).
+    pub stack: bool,
+    /// `MUL X` (4XX) and `DIV X` (the negated MUL, so `DIV 5` is -405), multiplying or
+    /// dividing ACC by the value at X.
+    pub mul_div: bool,
+    /// RTI (940), which returns from the handler started by `Config::timer`.
+    pub interrupts: bool,
+}
+
+/// An instruction word split into its operation and address.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodedInstruction {
+    Lda(i16),
+    Sta(i16),
+    Add(i16),
+    Sub(i16),
+    Inp,
+    Ina,
+    Out,
+    Otc,
+    Hlt,
+    Brz(i16),
+    Brp(i16),
+    Bra(i16),
+    /// LDA through the pointer at the address.
+    LdaIndirect(i16),
+    /// STA through the pointer at the address.
+    StaIndirect(i16),
+    Push,
+    Pop,
+    Call(i16),
+    Ret,
+    Mul(i16),
+    Div(i16),
+    Rti,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub word: i16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid instruction: {}", self.word)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl DecodedInstruction {
+    /// Decodes a word using the standard LMC instruction set.
+    pub fn decode(word: i16) -> Result<Self, DecodeError> {
+        Self::decode_sized(word, 100)
+    }
+
+    /// Decodes a word, including any enabled extension instructions.
+    pub fn decode_with(
+        word: i16,
+        mailboxes: i16,
+        extensions: &Extensions,
+    ) -> Result<Self, DecodeError> {
+        if word >= 0 {
+            return Self::decode_sized(word, mailboxes).or_else(|error| {
+                match (word / mailboxes, word % mailboxes) {
+                    (9, 30) if extensions.stack => Ok(Self::Push),
+                    (9, 31) if extensions.stack => Ok(Self::Pop),
+                    (9, 32) if extensions.stack => Ok(Self::Ret),
+                    (9, 40) if extensions.interrupts => Ok(Self::Rti),
+                    (4, address) if extensions.mul_div => Ok(Self::Mul(address)),
+                    _ => Err(error),
+                }
+            });
+        }
+
+        // i16::MIN has no positive counterpart, and isn't an instruction either way
+        let Some(positive) = word.checked_neg() else {
+            return Err(DecodeError { word });
+        };
+        match Self::decode_with(positive, mailboxes, extensions) {
+            Ok(Self::Lda(address)) if extensions.indirect => Ok(Self::LdaIndirect(address)),
+            Ok(Self::Sta(address)) if extensions.indirect => Ok(Self::StaIndirect(address)),
+            Ok(Self::Bra(address)) if extensions.stack => Ok(Self::Call(address)),
+            Ok(Self::Mul(address)) => Ok(Self::Div(address)),
+            _ => Err(DecodeError { word }),
+        }
+    }
+
+    /// Decodes a word for a machine with `mailboxes` mailboxes, where the opcode digit is
+    /// multiplied by the memory size instead of 100 (so LDA 123 is 5123 with 1000 mailboxes).
+    pub fn decode_sized(word: i16, mailboxes: i16) -> Result<Self, DecodeError> {
+        if word < 0 {
+            return Err(DecodeError { word });
+        }
+
+        let address = word % mailboxes;
+        Ok(match (word / mailboxes, address) {
+            (0, 0) => Self::Hlt,
+            (9, 1) => Self::Inp,
+            (9, 12) => Self::Ina,
+            (9, 2) => Self::Out,
+            (9, 22) => Self::Otc,
+            (1, _) => Self::Add(address),
+            (2, _) => Self::Sub(address),
+            (3, _) => Self::Sta(address),
+            (5, _) => Self::Lda(address),
+            (6, _) => Self::Bra(address),
+            (7, _) => Self::Brz(address),
+            (8, _) => Self::Brp(address),
+            _ => return Err(DecodeError { word }),
+        })
+    }
+
+    /// Builds an instruction from a mnemonic and its address, if it takes one.
+    pub fn from_parts(mnemonic: &str, address: Option<i16>) -> Option<Self> {
+        Some(match (mnemonic.to_uppercase().as_str(), address) {
+            ("LDA", Some(address)) => Self::Lda(address),
+            ("STA", Some(address)) => Self::Sta(address),
+            ("ADD", Some(address)) => Self::Add(address),
+            ("SUB", Some(address)) => Self::Sub(address),
+            ("INP", None) => Self::Inp,
+            ("INA", None) => Self::Ina,
+            ("OUT", None) => Self::Out,
+            ("OTC", None) => Self::Otc,
+            ("HLT", None) => Self::Hlt,
+            ("BRZ", Some(address)) => Self::Brz(address),
+            ("BRP", Some(address)) => Self::Brp(address),
+            ("BRA", Some(address)) => Self::Bra(address),
+            ("PUSH", None) => Self::Push,
+            ("POP", None) => Self::Pop,
+            ("CALL", Some(address)) => Self::Call(address),
+            ("RET", None) => Self::Ret,
+            ("MUL", Some(address)) => Self::Mul(address),
+            ("DIV", Some(address)) => Self::Div(address),
+            ("RTI", None) => Self::Rti,
+            _ => return None,
+        })
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Lda(_) | Self::LdaIndirect(_) => "LDA",
+            Self::Sta(_) | Self::StaIndirect(_) => "STA",
+            Self::Add(_) => "ADD",
+            Self::Sub(_) => "SUB",
+            Self::Inp => "INP",
+            Self::Ina => "INA",
+            Self::Out => "OUT",
+            Self::Otc => "OTC",
+            Self::Hlt => "HLT",
+            Self::Brz(_) => "BRZ",
+            Self::Brp(_) => "BRP",
+            Self::Bra(_) => "BRA",
+            Self::Push => "PUSH",
+            Self::Pop => "POP",
+            Self::Call(_) => "CALL",
+            Self::Ret => "RET",
+            Self::Mul(_) => "MUL",
+            Self::Div(_) => "DIV",
+            Self::Rti => "RTI",
+        }
+    }
+
+    pub fn address(&self) -> Option<i16> {
+        match self {
+            Self::Lda(address)
+            | Self::Sta(address)
+            | Self::LdaIndirect(address)
+            | Self::StaIndirect(address)
+            | Self::Add(address)
+            | Self::Sub(address)
+            | Self::Brz(address)
+            | Self::Brp(address)
+            | Self::Bra(address)
+            | Self::Call(address)
+            | Self::Mul(address)
+            | Self::Div(address) => Some(*address),
+            Self::Inp
+            | Self::Ina
+            | Self::Out
+            | Self::Otc
+            | Self::Hlt
+            | Self::Push
+            | Self::Pop
+            | Self::Ret
+            | Self::Rti => None,
+        }
+    }
+
+    pub fn is_indirect(&self) -> bool {
+        matches!(self, Self::LdaIndirect(_) | Self::StaIndirect(_))
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.address() {
+            Some(address) if self.is_indirect() => write!(f, "{} @{}", self.mnemonic(), address),
+            Some(address) => write!(f, "{} {}", self.mnemonic(), address),
+            None => write!(f, "{}", self.mnemonic()),
+        }
+    }
+}
+
+impl From<DecodedInstruction> for Instruction {
+    fn from(decoded: DecodedInstruction) -> Self {
+        let operand = decoded
+            .address()
+            .map(|address| match decoded.is_indirect() {
+                true => Operand::Indirect(Box::new(Operand::Value(address))),
+                false => Operand::Value(address),
+            });
+        // every mnemonic produced by `DecodedInstruction` is one `Instruction` knows about
+        Instruction::from_string(decoded.mnemonic(), operand).unwrap()
+    }
+}
+
+/// Decoded copies of the mailboxes, for `Config::predecode`. A mailbox is decoded the first
+/// time it's fetched, and again only after `invalidate` is called for it, e.g. by a STA.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DecodeCache {
+    extensions: Extensions,
+    /// `None` for mailboxes that haven't been decoded since they were last written.
+    entries: Vec<Option<Option<DecodedInstruction>>>,
+}
+
+impl DecodeCache {
+    pub(crate) fn get(
+        &mut self,
+        ram: &[i16],
+        address: usize,
+        extensions: &Extensions,
+    ) -> Option<DecodedInstruction> {
+        // the extensions decide what a word means, and can be changed between steps
+        if self.entries.len() != ram.len() || self.extensions != *extensions {
+            self.extensions = *extensions;
+            self.entries = vec![None; ram.len()];
+        }
+
+        *self.entries[address].get_or_insert_with(|| {
+            DecodedInstruction::decode_with(ram[address], ram.len() as i16, extensions).ok()
+        })
+    }
+
+    /// Forgets the decoded copy of a mailbox that has been written to.
+    pub(crate) fn invalidate(&mut self, address: usize) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = None;
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::{fmt, ops::Range};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    export::string,
+    language::{semantic_tokens, TokenKind},
+    MNEMONICS,
+};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// What kind of problem a diagnostic is about. Unlike the message, this doesn't change
+/// when the wording does, so editors and scripts can rely on it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    InvalidOpcode,
+    MissingOperand,
+    InvalidOperand,
+    /// A line with more tokens than a label, opcode and operand.
+    InvalidLine,
+    IndirectNotSupported,
+    IndirectNotEnabled,
+    NoEncoding,
+    InvalidLabel,
+    DuplicateLabel,
+    LabelInSymbolFile,
+    ProgramTooLarge,
+    UnusedLabel,
+    BranchToData,
+    UnreachableCode,
+    MissingHlt,
+    InvalidAssertion,
+    RuntimeError,
+    ReadFailed,
+}
+
+impl DiagnosticCode {
+    /// The code as a kebab-case identifier, e.g. `invalid-opcode`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::InvalidOpcode => "invalid-opcode",
+            DiagnosticCode::MissingOperand => "missing-operand",
+            DiagnosticCode::InvalidOperand => "invalid-operand",
+            DiagnosticCode::InvalidLine => "invalid-line",
+            DiagnosticCode::IndirectNotSupported => "indirect-not-supported",
+            DiagnosticCode::IndirectNotEnabled => "indirect-not-enabled",
+            DiagnosticCode::NoEncoding => "no-encoding",
+            DiagnosticCode::InvalidLabel => "invalid-label",
+            DiagnosticCode::DuplicateLabel => "duplicate-label",
+            DiagnosticCode::LabelInSymbolFile => "label-in-symbol-file",
+            DiagnosticCode::ProgramTooLarge => "program-too-large",
+            DiagnosticCode::UnusedLabel => "unused-label",
+            DiagnosticCode::BranchToData => "branch-to-data",
+            DiagnosticCode::UnreachableCode => "unreachable-code",
+            DiagnosticCode::MissingHlt => "missing-hlt",
+            DiagnosticCode::InvalidAssertion => "invalid-assertion",
+            DiagnosticCode::RuntimeError => "runtime-error",
+            DiagnosticCode::ReadFailed => "read-failed",
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+    /// 1-based source line, if known.
+    pub line: Option<usize>,
+    /// Mailbox the problem relates to, if any.
+    pub address: Option<usize>,
+    /// The token the problem is about, e.g. `BRN` for an invalid opcode, if there is one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub subject: Option<String>,
+    /// Where the subject is on the line, as byte offsets, if known.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub columns: Option<Range<usize>>,
+}
+
+impl Diagnostic {
+    pub fn error(code: DiagnosticCode, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            line: None,
+            address: None,
+            subject: None,
+            columns: None,
+        }
+    }
+
+    pub fn warning(code: DiagnosticCode, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message)
+        }
+    }
+
+    pub fn at_line(self, line: usize) -> Self {
+        Diagnostic {
+            line: Some(line),
+            ..self
+        }
+    }
+
+    pub fn at_address(self, address: usize) -> Self {
+        Diagnostic {
+            address: Some(address),
+            ..self
+        }
+    }
+
+    pub fn about(self, subject: impl Into<String>) -> Self {
+        Diagnostic {
+            subject: Some(subject.into()),
+            ..self
+        }
+    }
+
+    pub fn at_columns(self, columns: Range<usize>) -> Self {
+        Diagnostic {
+            columns: Some(columns),
+            ..self
+        }
+    }
+
+    /// The diagnostic's columns, if they're within its line of `source` (so a diagnostic
+    /// from another version of the file can't point past the end of a line).
+    pub fn columns(&self, source: &str) -> Option<Range<usize>> {
+        let text = source.lines().nth(self.line?.checked_sub(1)?)?;
+        let columns = self.columns.clone()?;
+        text.get(columns.clone()).map(|_| columns)
+    }
+
+    /// One line of JSON for editors and scripts, with 1-based line and column numbers
+    /// (`null` when unknown):
+    /// `{"file":"sum.lmc","line":2,"column":9,"code":"invalid-opcode","severity":"error","message":"Invalid opcode... BRN"}`
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        let column = self.columns(source).map(|columns| columns.start + 1);
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"code\":{},\"severity\":\"{}\",\"message\":{}}}",
+            string(file),
+            self.line.map_or("null".to_string(), |line| line.to_string()),
+            column.map_or("null".to_string(), |column| column.to_string()),
+            string(self.code.as_str()),
+            self.severity,
+            string(&self.message)
+        )
+    }
+
+    /// A hint for fixing the problem, e.g. `did you mean BRA?` for a misspelt opcode or label.
+    /// Equally close candidates are all suggested.
+    pub fn help(&self, source: &str) -> Option<String> {
+        let subject = self.subject.as_deref()?;
+        let candidates: Vec<String> = match self.code {
+            DiagnosticCode::InvalidOpcode => MNEMONICS.iter().map(|m| m.to_string()).collect(),
+            DiagnosticCode::InvalidLabel => {
+                let lines: Vec<&str> = source.lines().collect();
+                semantic_tokens(source)
+                    .into_iter()
+                    .filter(|token| token.kind == TokenKind::LabelDefinition)
+                    .map(|token| lines[token.span.line - 1][token.span.columns].to_string())
+                    .collect()
+            }
+            _ => return None,
+        };
+
+        // a couple of typos at most, and never more than half the word
+        let limit = (subject.len() / 2).clamp(1, 2);
+        let distances: Vec<(usize, String)> = candidates
+            .into_iter()
+            .map(|candidate| {
+                let distance = distance(&subject.to_uppercase(), &candidate.to_uppercase());
+                (distance, candidate)
+            })
+            .filter(|(distance, _)| (1..=limit).contains(distance))
+            .collect();
+        let closest = distances.iter().map(|(distance, _)| *distance).min()?;
+        let mut suggestions: Vec<String> = distances
+            .into_iter()
+            .filter(|(distance, _)| *distance == closest)
+            .map(|(_, candidate)| candidate)
+            .collect();
+
+        let last = suggestions.pop()?;
+        Some(match suggestions.is_empty() {
+            true => format!("did you mean {}?", last),
+            false => format!("did you mean {} or {}?", suggestions.join(", "), last),
+        })
+    }
+
+    /// Renders the diagnostic for a terminal, with the offending line of `source`, a caret
+    /// under the bad token and a hint where there is one:
+    ///
+    /// ```text
+    /// error: Invalid opcode... BRN
+    ///  --> sum.lmc:2:9
+    ///   |
+    /// 2 | START   BRN END
+    ///   |         ^^^
+    ///   = help: did you mean BRZ, BRP or BRA?
+    /// ```
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let mut rendered = format!("{}: {}\n", self.severity, self.message);
+        let text = self
+            .line
+            .and_then(|line| source.lines().nth(line.checked_sub(1)?));
+
+        let (Some(line), Some(text)) = (self.line, text) else {
+            rendered += &format!(" --> {}\n", file);
+            if let Some(help) = self.help(source) {
+                rendered += &format!("  = help: {}\n", help);
+            }
+            return rendered;
+        };
+
+        let gutter = " ".repeat(line.to_string().len());
+        let columns = self.columns(source);
+        match &columns {
+            Some(columns) => {
+                rendered += &format!("{}--> {}:{}:{}\n", gutter, file, line, columns.start + 1)
+            }
+            None => rendered += &format!("{}--> {}:{}\n", gutter, file, line),
+        }
+        rendered += &format!("{} |\n{} | {}\n", gutter, line, text);
+        if let Some(columns) = columns {
+            // keep tabs so the caret lines up with the text above
+            let padding: String = text[..columns.start]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            rendered += &format!(
+                "{} | {}{}\n",
+                gutter,
+                padding,
+                "^".repeat(columns.len().max(1))
+            );
+        }
+        if let Some(help) = self.help(source) {
+            rendered += &format!("{} = help: {}\n", gutter, help);
+        }
+        rendered
+    }
+}
+
+/// The edit distance between two words.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, x) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous + usize::from(x != *y);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}: {}", line, self.severity, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+    }
+
+    /// Every diagnostic rendered with `Diagnostic::render`, separated by blank lines.
+    pub fn render(&self, file: &str, source: &str) -> String {
+        self.0
+            .iter()
+            .map(|diagnostic| diagnostic.render(file, source))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
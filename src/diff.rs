@@ -0,0 +1,109 @@
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    ExecutionState, HaltReason, MemoryWrite, Output, Register, RegisterChange, RuntimeError,
+    StepEvent, LMCIO,
+};
+
+/// What changed between two states, for remote UIs that apply updates incrementally
+/// instead of receiving the whole machine after every step.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDiff {
+    /// In the order ACC, PC, MAR, MDR, CIR, SP.
+    pub registers: Vec<RegisterChange>,
+    /// Changed mailboxes, in address order.
+    pub ram: Vec<MemoryWrite>,
+    /// The new value of the negative flag, if it changed.
+    pub negative: Option<bool>,
+    /// The new value of the overflow flag, if it changed.
+    pub overflow: Option<bool>,
+    pub outputs: Vec<Output>,
+    /// Why the machine stopped, if it stopped during the step.
+    pub halt_reason: Option<HaltReason>,
+}
+
+impl StateDiff {
+    /// Compares two states. Only the registers, RAM and flags are filled in, since states don't
+    /// keep their output or why they stopped.
+    pub fn between<const N: usize>(old: &ExecutionState<N>, new: &ExecutionState<N>) -> Self {
+        let registers = [
+            (Register::Acc, old.acc, new.acc),
+            (Register::Pc, old.pc, new.pc),
+            (Register::Mar, old.mar, new.mar),
+            (Register::Mdr, old.mdr, new.mdr),
+            (Register::Cir, old.cir, new.cir),
+            (Register::Sp, old.sp, new.sp),
+        ];
+
+        StateDiff {
+            registers: registers
+                .into_iter()
+                .filter(|(_, old, new)| old != new)
+                .map(|(register, old, new)| RegisterChange { register, old, new })
+                .collect(),
+            ram: (0..N)
+                .filter(|&address| old.ram[address] != new.ram[address])
+                .map(|address| MemoryWrite {
+                    address: address as i16,
+                    old: old.ram[address],
+                    new: new.ram[address],
+                })
+                .collect(),
+            negative: (old.negative != new.negative).then_some(new.negative),
+            overflow: (old.overflow != new.overflow).then_some(new.overflow),
+            outputs: vec![],
+            halt_reason: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+
+    /// Brings a copy of the old state up to date.
+    pub fn apply<const N: usize>(&self, state: &mut ExecutionState<N>) {
+        for change in &self.registers {
+            let register = match change.register {
+                Register::Acc => &mut state.acc,
+                Register::Pc => &mut state.pc,
+                Register::Mar => &mut state.mar,
+                Register::Mdr => &mut state.mdr,
+                Register::Cir => &mut state.cir,
+                Register::Sp => &mut state.sp,
+            };
+            *register = change.new;
+        }
+        for write in &self.ram {
+            state.set_mailbox(write.address as usize, write.new);
+        }
+        state.negative = self.negative.unwrap_or(state.negative);
+        state.overflow = self.overflow.unwrap_or(state.overflow);
+    }
+
+    /// `flags` are the negative and overflow flags from before the step.
+    fn from_step<const N: usize>(
+        event: &StepEvent,
+        flags: (bool, bool),
+        state: &ExecutionState<N>,
+    ) -> Self {
+        StateDiff {
+            registers: event.registers.clone(),
+            ram: event.write.iter().cloned().collect(),
+            negative: (flags.0 != state.negative).then_some(state.negative),
+            overflow: (flags.1 != state.overflow).then_some(state.overflow),
+            outputs: event.output.into_iter().collect(),
+            halt_reason: event.halt_reason.clone(),
+        }
+    }
+}
+
+impl<const N: usize> ExecutionState<N> {
+    /// Like `step`, but returns what changed instead of a `StepEvent`.
+    pub fn step_diff<T: LMCIO>(&mut self, io_handler: &mut T) -> Result<StateDiff, RuntimeError> {
+        let flags = (self.negative, self.overflow);
+        let event = self.step(io_handler)?;
+        Ok(StateDiff::from_step(&event, flags, self))
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::Expr;
+
+/// Why a machine couldn't be stepped at all.
+///
+/// Faults in the program itself, like an invalid instruction or an overflow, stop the
+/// machine with a `HaltReason` instead.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// The program counter doesn't point at a mailbox, e.g. when stepping a machine that
+    /// already ran off the end of memory, or under `PcOverflow::Error`.
+    PcOutOfRange { pc: i16 },
+    /// INP or INA at `pc` found no input left, under `EndOfInput::Error`.
+    EndOfInput { pc: i16 },
+    /// A breakpoint condition couldn't be evaluated.
+    Breakpoint { condition: Expr, message: String },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::PcOutOfRange { pc } => write!(f, "PC out of range: {}", pc),
+            RuntimeError::EndOfInput { pc } => write!(f, "Unexpected end of input... at {}", pc),
+            RuntimeError::Breakpoint { condition, message } => {
+                write!(f, "Invalid breakpoint {}... {}", condition, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<RuntimeError> for String {
+    fn from(error: RuntimeError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt::Write;
+
+use crate::{CycleCosts, HaltReason, Output, Trace, TraceStep};
+
+/// The version written as `"version"` by `Trace::to_json`.
+pub const TRACE_JSON_VERSION: u32 = 1;
+
+impl Trace {
+    /// Writes the trace as JSON for external tools, in this format:
+    ///
+    /// ```text
+    /// {
+    ///   "version": 1,
+    ///   "steps": [
+    ///     {
+    ///       "pc": 0,                 address of the instruction
+    ///       "word": 901,
+    ///       "instruction": "INP",    null if the word isn't an instruction
+    ///       "acc": 5, "mar": 0, "mdr": 901, "next_pc": 1,    registers afterwards
+    ///       "registers": [{"register": "ACC", "old": 0, "new": 5}],
+    ///       "read": {"address": 10, "value": 3},              or null
+    ///       "write": {"address": 10, "old": 3, "new": 5},     or null
+    ///       "input": 5,              or null
+    ///       "output": {"int": 5},    {"char": "A"}, or null
+    ///       "branch": true,          whether a branch was taken, null if not a branch
+    ///       "halt_reason": "Halted"  the `HaltReason` variant, or null
+    ///     }
+    ///   ]
+    /// }
+    /// ```
+    pub fn to_json(&self) -> String {
+        let steps: Vec<String> = self.steps.iter().map(step_json).collect();
+        format!(
+            "{{\"version\":{},\"steps\":[{}]}}",
+            TRACE_JSON_VERSION,
+            steps.join(",")
+        )
+    }
+
+    /// Writes the trace in the Chrome trace-event format, for `about:tracing` or Perfetto.
+    /// Each instruction is a complete event named after it, lasting as many microseconds as
+    /// it costs in `cycle_costs`. Input and output are instant events, and ACC is a counter.
+    pub fn to_chrome_trace(&self, cycle_costs: &CycleCosts) -> String {
+        let mut events = vec![];
+        let mut time = 0;
+
+        for step in &self.steps {
+            let event = &step.event;
+            let name = event
+                .instruction
+                .map_or(format!("{:03}", event.word), |instruction| {
+                    instruction.to_string()
+                });
+            let duration = event
+                .instruction
+                .map_or(0, |instruction| cycle_costs.cost(&instruction));
+
+            events.push(format!(
+                "{{\"name\":{},\"cat\":\"instruction\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":1,\"args\":{{\"pc\":{},\"word\":{}}}}}",
+                string(&name),
+                time,
+                duration,
+                event.pc,
+                event.word
+            ));
+            let io = match (event.input, event.output) {
+                (Some(value), _) => Some(format!("INP {}", value)),
+                (_, Some(Output::Int(value))) => Some(format!("OUT {}", value)),
+                (_, Some(Output::Char(c))) => Some(format!("OTC {}", c)),
+                (None, None) => None,
+            };
+            if let Some(io) = io {
+                events.push(format!(
+                    "{{\"name\":{},\"cat\":\"io\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{},\"pid\":1,\"tid\":1}}",
+                    string(&io),
+                    time
+                ));
+            }
+
+            time += duration;
+            events.push(format!(
+                "{{\"name\":\"ACC\",\"ph\":\"C\",\"ts\":{},\"pid\":1,\"args\":{{\"ACC\":{}}}}}",
+                time, step.acc
+            ));
+        }
+
+        format!("{{\"traceEvents\":[{}]}}", events.join(","))
+    }
+}
+
+fn step_json(step: &TraceStep) -> String {
+    let event = &step.event;
+    let mut json = format!(
+        "{{\"pc\":{},\"word\":{},\"instruction\":{}",
+        event.pc,
+        event.word,
+        optional(
+            event
+                .instruction
+                .map(|instruction| string(&instruction.to_string()))
+        )
+    );
+
+    let _ = write!(
+        json,
+        ",\"acc\":{},\"mar\":{},\"mdr\":{},\"next_pc\":{}",
+        step.acc, step.mar, step.mdr, step.next_pc
+    );
+
+    let registers: Vec<String> = event
+        .registers
+        .iter()
+        .map(|change| {
+            format!(
+                "{{\"register\":{},\"old\":{},\"new\":{}}}",
+                string(&change.register.to_string()),
+                change.old,
+                change.new
+            )
+        })
+        .collect();
+    let _ = write!(json, ",\"registers\":[{}]", registers.join(","));
+
+    let read = event
+        .read
+        .as_ref()
+        .map(|read| format!("{{\"address\":{},\"value\":{}}}", read.address, read.value));
+    let write = event.write.as_ref().map(|write| {
+        format!(
+            "{{\"address\":{},\"old\":{},\"new\":{}}}",
+            write.address, write.old, write.new
+        )
+    });
+    let output = event.output.map(output_json);
+    let _ = write!(
+        json,
+        ",\"read\":{},\"write\":{},\"input\":{},\"output\":{},\"branch\":{},\"halt_reason\":{}}}",
+        optional(read),
+        optional(write),
+        optional(event.input),
+        optional(output),
+        optional(event.branch),
+        optional(event.halt_reason.as_ref().map(halt_reason_name)),
+    );
+
+    json
+}
+
+/// Writes outputs as a JSON array, keeping OUT and OTC apart: `[{"int":72},{"char":"H"}]`.
+pub fn outputs_to_json(outputs: &[Output]) -> String {
+    let outputs: Vec<String> = outputs.iter().copied().map(output_json).collect();
+    format!("[{}]", outputs.join(","))
+}
+
+fn output_json(output: Output) -> String {
+    match output {
+        Output::Int(value) => format!("{{\"int\":{}}}", value),
+        Output::Char(c) => format!("{{\"char\":{}}}", string(&c.to_string())),
+    }
+}
+
+/// The name of the variant, e.g. `"Overflow"` for `HaltReason::Overflow { .. }`.
+fn halt_reason_name(halt_reason: &HaltReason) -> String {
+    let debug = format!("{:?}", halt_reason);
+    string(
+        debug
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or(""),
+    )
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or("null".to_string(), |value| value.to_string())
+}
+
+/// A JSON string literal.
+pub(crate) fn string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
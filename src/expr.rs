@@ -0,0 +1,360 @@
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{ExecutionState, SymbolTable};
+
+/// A small expression over registers and memory, e.g. `ACC == 0` or `RAM[42] > 10`.
+///
+/// Comparisons and `&&`/`||` evaluate to 1 or 0, so any expression can be used as a
+/// condition (non-zero meaning true).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i32),
+    Register(Register),
+    Ram(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    Acc,
+    Pc,
+    Mar,
+    Mdr,
+    Cir,
+    Sp,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+}
+
+impl Expr {
+    pub fn eval<const N: usize>(&self, state: &ExecutionState<N>) -> Result<i32, String> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Register(register) => i32::from(match register {
+                Register::Acc => state.acc,
+                Register::Pc => state.pc,
+                Register::Mar => state.mar,
+                Register::Mdr => state.mdr,
+                Register::Cir => state.cir,
+                Register::Sp => state.sp,
+            }),
+            Expr::Ram(address) => {
+                let address = address.eval(state)?;
+                let value = usize::try_from(address)
+                    .ok()
+                    .and_then(|address| state.ram.get(address))
+                    .ok_or_else(|| format!("Address out of range: {}", address))?;
+                i32::from(*value)
+            }
+            Expr::Neg(expr) => expr
+                .eval(state)?
+                .checked_neg()
+                .ok_or_else(|| format!("Overflow in expression... {}", self))?,
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(state)?, rhs.eval(state)?);
+                match op {
+                    BinOp::Add => lhs
+                        .checked_add(rhs)
+                        .ok_or_else(|| format!("Overflow in expression... {}", self))?,
+                    BinOp::Sub => lhs
+                        .checked_sub(rhs)
+                        .ok_or_else(|| format!("Overflow in expression... {}", self))?,
+                    BinOp::Eq => (lhs == rhs) as i32,
+                    BinOp::Ne => (lhs != rhs) as i32,
+                    BinOp::Lt => (lhs < rhs) as i32,
+                    BinOp::Le => (lhs <= rhs) as i32,
+                    BinOp::Gt => (lhs > rhs) as i32,
+                    BinOp::Ge => (lhs >= rhs) as i32,
+                    BinOp::And => (lhs != 0 && rhs != 0) as i32,
+                    BinOp::Or => (lhs != 0 || rhs != 0) as i32,
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Register(register) => write!(f, "{}", register),
+            Expr::Ram(address) => write!(f, "RAM[{}]", address),
+            Expr::Neg(expr) => match **expr {
+                Expr::Binary(..) => write!(f, "-({})", expr),
+                _ => write!(f, "-{}", expr),
+            },
+            Expr::Binary(lhs, op, rhs) => {
+                for (i, side) in [lhs, rhs].into_iter().enumerate() {
+                    if i == 1 {
+                        write!(f, " {} ", op.symbol())?;
+                    }
+                    match **side {
+                        Expr::Binary(..) => write!(f, "({})", side)?,
+                        _ => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Register::Acc => "ACC",
+            Register::Pc => "PC",
+            Register::Mar => "MAR",
+            Register::Mdr => "MDR",
+            Register::Cir => "CIR",
+            Register::Sp => "SP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "ACC" => Ok(Register::Acc),
+            "PC" => Ok(Register::Pc),
+            "MAR" => Ok(Register::Mar),
+            "MDR" => Ok(Register::Mdr),
+            "CIR" => Ok(Register::Cir),
+            "SP" => Ok(Register::Sp),
+            _ => Err(format!("Unknown register... {}", s)),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Expr::parse_with_symbols(s, &SymbolTable::default())
+    }
+}
+
+impl Expr {
+    /// Parses an expression that can also use labels, e.g. `RAM[COUNT] + 1`. A label stands
+    /// for its address.
+    pub fn parse_with_symbols(s: &str, symbols: &SymbolTable) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            depth: 0,
+            symbols,
+        };
+        let expr = parser.or()?;
+
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected token in expression... {}", token)),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut token = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+            {
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        } else {
+            chars.next();
+            let token = match (c, chars.peek()) {
+                ('=', Some('=')) | ('!', Some('=')) | ('<', Some('=')) | ('>', Some('=')) => {
+                    format!("{}=", c)
+                }
+                ('&', Some('&')) => "&&".to_string(),
+                ('|', Some('|')) => "||".to_string(),
+                ('<' | '>' | '+' | '-' | '[' | ']' | '(' | ')', _) => {
+                    tokens.push(c.to_string());
+                    continue;
+                }
+                _ => return Err(format!("Unexpected character in expression... {}", c)),
+            };
+            chars.next();
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// How deeply brackets and negation can nest, so that e.g. a long run of `(` is an error
+/// rather than a stack overflow.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    /// How many `unary`s are being parsed, one inside the other.
+    depth: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "Unexpected end of expression".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("Expected {} in expression... {}", expected, token)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Expr, String>,
+        ops: &[(&str, BinOp)],
+        repeat: bool,
+    ) -> Result<Expr, String> {
+        let mut lhs = operand(self)?;
+
+        while let Some(op) = self
+            .peek()
+            .and_then(|token| ops.iter().find(|(symbol, _)| *symbol == token))
+            .map(|(_, op)| *op)
+        {
+            self.pos += 1;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(operand(self)?));
+
+            if !repeat {
+                break;
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(Self::and, &[("||", BinOp::Or)], true)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(Self::comparison, &[("&&", BinOp::And)], true)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<", BinOp::Lt),
+            ("<=", BinOp::Le),
+            (">", BinOp::Gt),
+            (">=", BinOp::Ge),
+        ];
+        self.binary(Self::sum, &ops, false)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(Self::unary, &[("+", BinOp::Add), ("-", BinOp::Sub)], true)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!(
+                "Expression is nested too deeply... more than {} levels",
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        let expr = self.operand();
+        self.depth -= 1;
+        expr
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some("-") {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+
+        let token = self.next()?;
+
+        if token == "(" {
+            let expr = self.or()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+
+        if let Ok(n) = token.parse::<i32>() {
+            return Ok(Expr::Number(n));
+        }
+
+        if token.eq_ignore_ascii_case("RAM") {
+            self.expect("[")?;
+            let address = self.or()?;
+            self.expect("]")?;
+            return Ok(Expr::Ram(Box::new(address)));
+        }
+
+        match (token.parse::<Register>(), self.symbols.get(&token)) {
+            (Ok(register), _) => Ok(Expr::Register(register)),
+            (Err(_), Some(address)) => Ok(Expr::Number(address as i32)),
+            (Err(_), None) => Err(format!("Unknown name in expression... {}", token)),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{parse_line, Label};
+
+/// Tidies up source: mnemonics are upper-cased and labels, mnemonics and operands are
+/// lined up in columns. Comments and blank lines are kept, and lines that don't parse are
+/// left exactly as they were. Every line, including the last, ends with a newline.
+pub fn format(source: &str) -> String {
+    let lines: Vec<Line> = source.lines().map(Line::read).collect();
+
+    let label_width = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Code { label, .. } => label.map(str::len),
+            _ => None,
+        })
+        .max()
+        .map_or(0, |width| width + 1);
+    let mnemonic_width = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Code { mnemonic, .. } => Some(mnemonic.len()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut formatted = String::new();
+    for line in lines {
+        match line {
+            Line::Code {
+                label,
+                mnemonic,
+                operand,
+            } => {
+                let code = match operand {
+                    Some(operand) => format!("{mnemonic:<mnemonic_width$} {operand}"),
+                    None => mnemonic,
+                };
+                formatted += &format!("{:<label_width$}{code}", label.unwrap_or(""));
+            }
+            Line::Comment(comment) => formatted += comment,
+            Line::Other(text) => formatted += text,
+        }
+        formatted.push('\n');
+    }
+
+    formatted
+}
+
+enum Line<'a> {
+    Code {
+        label: Option<&'a str>,
+        mnemonic: String,
+        operand: Option<&'a str>,
+    },
+    Comment(&'a str),
+    /// Blank lines and lines with errors.
+    Other(&'a str),
+}
+
+impl<'a> Line<'a> {
+    fn read(text: &'a str) -> Self {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+
+        match parse_line(text) {
+            Ok(Some((label, _))) => {
+                let (label, rest) = match label {
+                    Label::LBL(_) => (Some(tokens[0]), &tokens[1..]),
+                    Label::None => (None, &tokens[..]),
+                };
+                Line::Code {
+                    label,
+                    mnemonic: rest[0].to_uppercase(),
+                    operand: rest.get(1).copied(),
+                }
+            }
+            Ok(None) if !tokens.is_empty() => Line::Comment(text.trim()),
+            Ok(None) => Line::Other(""),
+            Err(_) => Line::Other(text),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! `Arbitrary` implementations and entry points for fuzz targets, e.g. with cargo-fuzz:
+//!
+//! ```text
+//! fuzz_target!(|image: MemoryImage| {
+//!     let _ = lmc_assembly::fuzz::run_image(&image, &[1, 2, 3], 10_000);
+//! });
+//! ```
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+    build, format, language, parse, run_with_config, BuildOptions, Config, EndOfInput, Instruction,
+    Label, MemoryImage, Operand, RunResult, RuntimeError, MNEMONICS,
+};
+
+/// Label names used by generated programs, so that branches and loads often refer to a label
+/// the program defines.
+const LABELS: [&str; 6] = ["A", "B", "LOOP", "END", "COUNT", "PTR"];
+
+impl<'a> Arbitrary<'a> for Label {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.ratio(1, 3)? {
+            true => Label::LBL(u.choose(&LABELS)?.to_string()),
+            false => Label::None,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Operand {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let direct = |u: &mut Unstructured<'a>| -> Result<Operand> {
+            Ok(match u.arbitrary()? {
+                true => Operand::Value(u.int_in_range(0..=99)?),
+                false => Operand::Label(u.choose(&LABELS)?.to_string()),
+            })
+        };
+
+        Ok(match u.ratio(1, 8)? {
+            true => Operand::Indirect(Box::new(direct(u)?)),
+            false => direct(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Instruction {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mnemonic = u.choose(&MNEMONICS)?;
+        let operand = match *mnemonic {
+            "DAT" => Operand::Value(u.int_in_range(-999..=999)?),
+            _ => u.arbitrary()?,
+        };
+        // every mnemonic above is known, and an operand is always given
+        Ok(Instruction::from_string(mnemonic, Some(operand)).unwrap())
+    }
+}
+
+impl<'a> Arbitrary<'a> for MemoryImage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut ram = [0; 100];
+        for word in &mut ram {
+            *word = u.int_in_range(-999..=999)?;
+        }
+        Ok(MemoryImage(ram))
+    }
+}
+
+/// Feeds arbitrary bytes (as text) through the parser, the assembler and the editor
+/// support, none of which should panic however bad the source is.
+pub fn parse_text(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+
+    let _ = parse(&source, false);
+    let _ = build(&source, &BuildOptions::default());
+    let _ = format(&source);
+    let _ = language::semantic_tokens(&source);
+}
+
+/// Runs an image for at most `max_steps` steps, halting when `inputs` run out.
+pub fn run_image(
+    image: &MemoryImage,
+    inputs: &[i16],
+    max_steps: u64,
+) -> std::result::Result<RunResult, RuntimeError> {
+    let config = Config {
+        max_steps: Some(max_steps),
+        end_of_input: EndOfInput::Halt,
+        ..Config::default()
+    };
+    let mut io_handler = crate::io::BufferedIO::new(inputs.iter().copied());
+    run_with_config(image.0, &mut io_handler, false, config)
+}
@@ -0,0 +1,152 @@
+//! Grading submissions against a rubric: the cases from `testing`, plus optional limits on
+//! how fast and how small a solution has to be. Results are serializable, for storing or
+//! sending to a gradebook.
+
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    build,
+    testing::{run_case, Case, Outcome},
+    BuildOptions, CellKind, Diagnostic, DiagnosticCode, ExecutionStats, Severity,
+};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rubric {
+    pub cases: Vec<Case>,
+    /// Correct cases that take more steps than this are marked as not efficient.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub step_threshold: Option<u64>,
+    /// Programs using more mailboxes than this are marked as not efficient.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub size_threshold: Option<usize>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseGrade {
+    pub name: String,
+    pub outcome: Outcome,
+    /// Whether the run was within `Rubric::step_threshold`.
+    pub efficient: bool,
+    /// What the run did, unless it couldn't be run to the end.
+    pub stats: Option<ExecutionStats>,
+}
+
+impl CaseGrade {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grade {
+    /// Errors that stopped the submission building (in which case `cases` is empty), or
+    /// warnings from building it.
+    pub diagnostics: Vec<Diagnostic>,
+    pub cases: Vec<CaseGrade>,
+    /// Mailboxes used by the program, code and data.
+    pub mailboxes: usize,
+    /// Whether the program was within `Rubric::size_threshold`.
+    pub small_enough: bool,
+}
+
+impl Grade {
+    pub fn builds(&self) -> bool {
+        !self
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed()).count()
+    }
+
+    /// The fraction of cases passed, from 0 to 1.
+    pub fn score(&self) -> f64 {
+        match self.cases.len() {
+            0 => 0.0,
+            cases => self.passed() as f64 / cases as f64,
+        }
+    }
+}
+
+/// Builds and runs one submission against every case in `rubric`.
+pub fn grade(source: &str, rubric: &Rubric) -> Grade {
+    let assembly = match build(source, &BuildOptions::default()) {
+        Ok(assembly) => assembly,
+        Err(diagnostics) => return failed(diagnostics.0),
+    };
+    let mailboxes = assembly
+        .kinds
+        .iter()
+        .filter(|kind| **kind != CellKind::Unused)
+        .count();
+
+    let cases = rubric
+        .cases
+        .iter()
+        .map(|case| {
+            let (outcome, stats) = run_case(&assembly, case);
+            let efficient = match (rubric.step_threshold, &stats) {
+                (Some(threshold), Some(stats)) => stats.steps <= threshold,
+                _ => true,
+            };
+            CaseGrade {
+                name: case.name.clone(),
+                outcome,
+                efficient,
+                stats,
+            }
+        })
+        .collect();
+
+    Grade {
+        diagnostics: assembly.warnings,
+        cases,
+        mailboxes,
+        small_enough: rubric
+            .size_threshold
+            .is_none_or(|threshold| mailboxes <= threshold),
+    }
+}
+
+/// Grades each submission in turn.
+pub fn grade_all<S: AsRef<str>>(sources: &[S], rubric: &Rubric) -> Vec<Grade> {
+    sources
+        .iter()
+        .map(|source| grade(source.as_ref(), rubric))
+        .collect()
+}
+
+/// Grades the submission in each file. A file that can't be read gets a grade with just an
+/// error diagnostic.
+pub fn grade_files<P: AsRef<Path>>(paths: &[P], rubric: &Rubric) -> Vec<Grade> {
+    paths
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            match std::fs::read_to_string(path) {
+                Ok(source) => grade(&source, rubric),
+                Err(e) => failed(vec![Diagnostic::error(
+                    DiagnosticCode::ReadFailed,
+                    format!("Failed to read {}... {e}", path.display()),
+                )]),
+            }
+        })
+        .collect()
+}
+
+fn failed(diagnostics: Vec<Diagnostic>) -> Grade {
+    Grade {
+        diagnostics,
+        cases: vec![],
+        mailboxes: 0,
+        small_enough: false,
+    }
+}
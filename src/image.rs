@@ -0,0 +1,209 @@
+use std::ops::{Deref, DerefMut};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// The 100 mailboxes of an assembled program.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryImage(#[cfg_attr(feature = "serde", serde(with = "serde_arrays"))] pub [i16; 100]);
+
+impl From<[i16; 100]> for MemoryImage {
+    fn from(ram: [i16; 100]) -> Self {
+        MemoryImage(ram)
+    }
+}
+
+impl From<MemoryImage> for [i16; 100] {
+    fn from(image: MemoryImage) -> Self {
+        image.0
+    }
+}
+
+impl Deref for MemoryImage {
+    type Target = [i16; 100];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for MemoryImage {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl MemoryImage {
+    /// Encodes the image as a URL-safe string.
+    ///
+    /// Runs of zeros are stored as a single byte (`0x80 | length`), every other word as two
+    /// bytes holding `word + 999`, and trailing zeros are dropped. The bytes are then base64
+    /// encoded with the URL-safe alphabet and no padding. Fails if a word is outside
+    /// -999..=999, which couldn't be decoded again.
+    pub fn encode(&self) -> Result<String, String> {
+        let len = self
+            .0
+            .iter()
+            .rposition(|&word| word != 0)
+            .map_or(0, |i| i + 1);
+
+        let mut bytes = vec![];
+        let mut zeros = 0;
+
+        for &word in &self.0[..len] {
+            if word == 0 {
+                zeros += 1;
+                continue;
+            }
+
+            if zeros > 0 {
+                bytes.push(0x80 | zeros);
+                zeros = 0;
+            }
+
+            if !(-999..=999).contains(&word) {
+                return Err(format!("Invalid word in memory image... {}", word));
+            }
+            let value = (word + 999) as u16;
+            bytes.push((value >> 8) as u8);
+            bytes.push(value as u8);
+        }
+
+        Ok(encode_base64(&bytes))
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self, String> {
+        let bytes = decode_base64(encoded)?;
+        let mut ram = [0; 100];
+        let mut pos = 0;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            if bytes[i] & 0x80 != 0 {
+                pos += (bytes[i] & 0x7F) as usize;
+                i += 1;
+                continue;
+            }
+
+            let low = *bytes
+                .get(i + 1)
+                .ok_or_else(|| "Truncated memory image".to_string())?;
+            let value = (bytes[i] as i16) << 8 | low as i16;
+
+            if !(0..=1998).contains(&value) {
+                return Err(format!("Invalid word in memory image... {}", value - 999));
+            }
+            if pos >= ram.len() {
+                return Err("Memory image is larger than 100 mailboxes".to_string());
+            }
+
+            ram[pos] = value - 999;
+            pos += 1;
+            i += 2;
+        }
+
+        if pos > ram.len() {
+            return Err("Memory image is larger than 100 mailboxes".to_string());
+        }
+
+        Ok(MemoryImage(ram))
+    }
+
+    /// Each word as four hex digits (two's complement for negative words), ten to a line.
+    pub fn to_hex(&self) -> String {
+        self.0
+            .chunks(10)
+            .map(|line| {
+                let words: Vec<String> = line
+                    .iter()
+                    .map(|&word| format!("{:04x}", word as u16))
+                    .collect();
+                words.join(" ") + "\n"
+            })
+            .collect()
+    }
+
+    /// An `address,word` row for every mailbox, after a header row.
+    pub fn to_csv(&self) -> String {
+        let rows: String = self
+            .0
+            .iter()
+            .enumerate()
+            .map(|(address, word)| format!("{},{}\n", address, word))
+            .collect();
+        "address,word\n".to_string() + &rows
+    }
+
+    /// The words as a JSON array.
+    pub fn to_json(&self) -> String {
+        let words: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        format!("[{}]", words.join(","))
+    }
+
+    /// Each word as a big-endian `i16`, 200 bytes in all.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != 200 {
+            return Err(format!(
+                "Memory image should be 200 bytes... got {}",
+                bytes.len()
+            ));
+        }
+
+        let mut ram = [0; 100];
+        for (word, pair) in ram.iter_mut().zip(bytes.chunks(2)) {
+            *word = i16::from_be_bytes([pair[0], pair[1]]);
+            if !(-999..=999).contains(word) {
+                return Err(format!("Invalid word in memory image... {}", word));
+            }
+        }
+
+        Ok(MemoryImage(ram))
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+        }
+    }
+
+    out
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in encoded.bytes() {
+        let value = ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(|| format!("Invalid character in memory image... {}", c as char))?;
+
+        buffer = (buffer << 6 | value as u32) & 0xFFFF;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(out)
+}
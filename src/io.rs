@@ -0,0 +1,639 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::RangeInclusive,
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Output, LMCIO};
+
+/// What an IO handler gave the machine when asked for input.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputResponse<T = i16> {
+    Value(T),
+    /// There's no more input, e.g. stdin was closed.
+    Eof,
+}
+
+/// Why an IO handler couldn't give the machine input or take its output.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IoError {
+    /// The input couldn't be read as a number.
+    InvalidInput(String),
+    /// Reading or writing failed, e.g. because stdin was closed.
+    Failed(String),
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoError::InvalidInput(input) => write!(f, "Invalid input... {}", input),
+            IoError::Failed(message) => write!(f, "IO failed... {}", message),
+        }
+    }
+}
+
+impl std::error::Error for IoError {}
+
+impl From<std::io::Error> for IoError {
+    fn from(error: std::io::Error) -> Self {
+        IoError::Failed(error.to_string())
+    }
+}
+
+/// An input the program consumed: a number for INP or a character for INA.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Int(i16),
+    Char(char),
+}
+
+impl From<i16> for Input {
+    fn from(input: i16) -> Self {
+        Input::Int(input)
+    }
+}
+
+impl From<char> for Input {
+    fn from(input: char) -> Self {
+        Input::Char(input)
+    }
+}
+
+/// Wraps another handler and records every input the program consumes, so that a run can
+/// be reproduced exactly by replaying them.
+///
+/// Inputs are taken from the script first (if one was given) and from the wrapped handler
+/// once it runs out. Outputs are always passed through.
+pub struct ReplayIO<T: LMCIO> {
+    inner: T,
+    script: VecDeque<Input>,
+    recorded: Vec<Input>,
+}
+
+impl<T: LMCIO> ReplayIO<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_script(inner, Vec::<Input>::new())
+    }
+
+    pub fn with_script<I: Into<Input>>(inner: T, script: impl IntoIterator<Item = I>) -> Self {
+        ReplayIO {
+            inner,
+            script: script.into_iter().map(Into::into).collect(),
+            recorded: vec![],
+        }
+    }
+
+    /// Every input consumed so far, in order.
+    pub fn recorded(&self) -> &[Input] {
+        &self.recorded
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: LMCIO> LMCIO for ReplayIO<T> {
+    fn get_input(&mut self) -> i16 {
+        let input = match self.script.pop_front() {
+            Some(Input::Int(input)) => input,
+            Some(Input::Char(c)) => c as i16,
+            None => self.inner.get_input(),
+        };
+        self.recorded.push(Input::Int(input));
+        input
+    }
+
+    fn get_char_input(&mut self) -> char {
+        let input = match self.script.pop_front() {
+            Some(Input::Char(c)) => c,
+            Some(Input::Int(input)) => input as u8 as char,
+            None => self.inner.get_char_input(),
+        };
+        self.recorded.push(Input::Char(input));
+        input
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.inner.print_output(val);
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        let input = match self.script.pop_front() {
+            Some(Input::Int(input)) => input,
+            Some(Input::Char(c)) => c as i16,
+            None => match self.inner.try_get_input()? {
+                InputResponse::Value(input) => input,
+                InputResponse::Eof => return Ok(InputResponse::Eof),
+            },
+        };
+        self.recorded.push(Input::Int(input));
+        Ok(InputResponse::Value(input))
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        let input = match self.script.pop_front() {
+            Some(Input::Char(c)) => c,
+            Some(Input::Int(input)) => input as u8 as char,
+            None => match self.inner.try_get_char_input()? {
+                InputResponse::Value(c) => c,
+                InputResponse::Eof => return Ok(InputResponse::Eof),
+            },
+        };
+        self.recorded.push(Input::Char(input));
+        Ok(InputResponse::Value(input))
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        self.inner.try_print_output(val)
+    }
+
+    fn set_pc(&mut self, pc: i16) {
+        self.inner.set_pc(pc)
+    }
+}
+
+/// Something that passed through a `TracingIO`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IoEvent {
+    Input(i16),
+    CharInput(char),
+    Output(Output),
+    /// The wrapped handler ran out of input.
+    EndOfInput,
+    /// The wrapped handler failed.
+    Error(IoError),
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoLogEntry {
+    /// How long after the `TracingIO` was made it happened.
+    pub time: Duration,
+    /// Address of the instruction that did the IO.
+    pub pc: i16,
+    pub event: IoEvent,
+}
+
+impl fmt::Display for IoLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:>8.3}s] {:>2}: ", self.time.as_secs_f64(), self.pc)?;
+        match &self.event {
+            IoEvent::Input(input) => write!(f, "< {input}"),
+            IoEvent::CharInput(c) => write!(f, "< {c:?}"),
+            IoEvent::Output(Output::Int(n)) => write!(f, "> {n}"),
+            IoEvent::Output(Output::Char(c)) => write!(f, "> {c:?}"),
+            IoEvent::EndOfInput => write!(f, "< end of input"),
+            IoEvent::Error(error) => write!(f, "! {error}"),
+        }
+    }
+}
+
+/// Wraps another handler and keeps a timestamped log of every input and output passing
+/// through it, along with the PC at the time, e.g. for debugging interactive programs or
+/// keeping a transcript of a graded run.
+pub struct TracingIO<T: LMCIO> {
+    inner: T,
+    start: Instant,
+    pc: i16,
+    log: Vec<IoLogEntry>,
+}
+
+impl<T: LMCIO> TracingIO<T> {
+    pub fn new(inner: T) -> Self {
+        TracingIO {
+            inner,
+            start: Instant::now(),
+            pc: 0,
+            log: vec![],
+        }
+    }
+
+    pub fn log(&self) -> &[IoLogEntry] {
+        &self.log
+    }
+
+    /// The log with one entry per line.
+    pub fn transcript(&self) -> String {
+        self.log.iter().map(|entry| format!("{entry}\n")).collect()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&mut self, event: IoEvent) {
+        self.log.push(IoLogEntry {
+            time: self.start.elapsed(),
+            pc: self.pc,
+            event,
+        });
+    }
+
+    fn record_input<V>(
+        &mut self,
+        response: Result<InputResponse<V>, IoError>,
+        event: fn(V) -> IoEvent,
+    ) -> Result<InputResponse<V>, IoError>
+    where
+        V: Copy,
+    {
+        self.record(match &response {
+            Ok(InputResponse::Value(value)) => event(*value),
+            Ok(InputResponse::Eof) => IoEvent::EndOfInput,
+            Err(error) => IoEvent::Error(error.clone()),
+        });
+        response
+    }
+}
+
+impl<T: LMCIO> LMCIO for TracingIO<T> {
+    fn get_input(&mut self) -> i16 {
+        let input = self.inner.get_input();
+        self.record(IoEvent::Input(input));
+        input
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.inner.print_output(val);
+        self.record(IoEvent::Output(val));
+    }
+
+    fn get_char_input(&mut self) -> char {
+        let c = self.inner.get_char_input();
+        self.record(IoEvent::CharInput(c));
+        c
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        let response = self.inner.try_get_input();
+        self.record_input(response, IoEvent::Input)
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        let response = self.inner.try_get_char_input();
+        self.record_input(response, IoEvent::CharInput)
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        let result = self.inner.try_print_output(val);
+        self.record(match &result {
+            Ok(()) => IoEvent::Output(val),
+            Err(error) => IoEvent::Error(error.clone()),
+        });
+        result
+    }
+
+    fn set_pc(&mut self, pc: i16) {
+        self.pc = pc;
+        self.inner.set_pc(pc)
+    }
+}
+
+/// Reads a list of inputs, e.g. `3,4` from a command line or a file with one value per
+/// line. Values can be separated by commas or whitespace, and lines starting with `//` are
+/// comments.
+pub fn parse_inputs(text: &str) -> Result<Vec<i16>, String> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|token| !token.is_empty())
+        .map(|token| match token.parse::<i16>() {
+            Ok(value) if (-999..=999).contains(&value) => Ok(value),
+            _ => Err(format!("Invalid input... {}", token)),
+        })
+        .collect()
+}
+
+/// Takes input from a queue, front to back, and collects output, for tests and batch runs.
+///
+/// Once the queue is empty the handler reports `InputResponse::Eof`, so the machine stops
+/// according to `Config::end_of_input` rather than blocking or panicking.
+#[derive(Debug, Clone, Default)]
+pub struct BufferedIO {
+    pub inputs: VecDeque<i16>,
+    pub outputs: Vec<Output>,
+}
+
+impl BufferedIO {
+    pub fn new(inputs: impl IntoIterator<Item = i16>) -> Self {
+        BufferedIO {
+            inputs: inputs.into_iter().collect(),
+            outputs: vec![],
+        }
+    }
+
+    /// Queues the inputs in `text`, as read by `parse_inputs`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        Ok(Self::new(parse_inputs(text)?))
+    }
+
+    /// Queues another input after the ones already given.
+    pub fn push_input(&mut self, input: i16) {
+        self.inputs.push_back(input);
+    }
+
+    /// Inputs that haven't been consumed yet.
+    pub fn remaining_inputs(&self) -> impl Iterator<Item = i16> + '_ {
+        self.inputs.iter().copied()
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// Everything output since the last call.
+    pub fn take_outputs(&mut self) -> Vec<Output> {
+        std::mem::take(&mut self.outputs)
+    }
+}
+
+impl LMCIO for BufferedIO {
+    /// Panics if the queue is empty; the machine itself uses `try_get_input` instead.
+    fn get_input(&mut self) -> i16 {
+        self.inputs
+            .pop_front()
+            .expect("BufferedIO ran out of input")
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.outputs.push(val);
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        Ok(match self.inputs.pop_front() {
+            Some(input) => InputResponse::Value(input),
+            None => InputResponse::Eof,
+        })
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(match self.inputs.pop_front() {
+            Some(input) => InputResponse::Value(input as u8 as char),
+            None => InputResponse::Eof,
+        })
+    }
+}
+
+/// Takes input from an iterator and passes output to a closure, for one-off embedding:
+///
+/// ```
+/// # use lmc_assembly::io::FnIO;
+/// let mut io_handler = FnIO::new([6, 7], |output| println!("{output:?}"));
+/// ```
+///
+/// Reports `InputResponse::Eof` once the iterator runs out.
+pub struct FnIO<I, F> {
+    inputs: I,
+    sink: F,
+}
+
+impl<I: Iterator<Item = i16>, F: FnMut(Output)> FnIO<I, F> {
+    pub fn new(inputs: impl IntoIterator<IntoIter = I>, sink: F) -> Self {
+        FnIO {
+            inputs: inputs.into_iter(),
+            sink,
+        }
+    }
+}
+
+impl<I: Iterator<Item = i16>, F: FnMut(Output)> LMCIO for FnIO<I, F> {
+    /// Panics if the iterator has run out; the machine itself uses `try_get_input` instead.
+    fn get_input(&mut self) -> i16 {
+        self.inputs.next().expect("FnIO ran out of input")
+    }
+
+    fn print_output(&mut self, val: Output) {
+        (self.sink)(val)
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        Ok(self
+            .inputs
+            .next()
+            .map_or(InputResponse::Eof, InputResponse::Value))
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(match self.inputs.next() {
+            Some(input) => InputResponse::Value(input as u8 as char),
+            None => InputResponse::Eof,
+        })
+    }
+}
+
+/// Reads input from a string of whitespace-separated numbers and collects output as text:
+/// OTC characters go into `text`, and each OUT value becomes one of `lines`. INA reads the
+/// next number as a character code.
+#[derive(Debug, Clone, Default)]
+pub struct StringIO {
+    inputs: VecDeque<String>,
+    text: String,
+    lines: Vec<String>,
+}
+
+impl StringIO {
+    pub fn new(input: &str) -> Self {
+        StringIO {
+            inputs: input.split_whitespace().map(str::to_string).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Everything output with OTC.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Everything output with OUT, one value per line.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    fn next_input(&mut self) -> Result<InputResponse, IoError> {
+        match self.inputs.pop_front() {
+            Some(token) => token
+                .parse()
+                .map(InputResponse::Value)
+                .map_err(|_| IoError::InvalidInput(token)),
+            None => Ok(InputResponse::Eof),
+        }
+    }
+}
+
+impl LMCIO for StringIO {
+    /// Panics on a bad number or when the input has run out; the machine itself uses
+    /// `try_get_input` instead.
+    fn get_input(&mut self) -> i16 {
+        match self.next_input() {
+            Ok(InputResponse::Value(input)) => input,
+            Ok(InputResponse::Eof) => panic!("StringIO ran out of input"),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn print_output(&mut self, val: Output) {
+        match val {
+            Output::Char(c) => self.text.push(c),
+            Output::Int(n) => self.lines.push(n.to_string()),
+        }
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        self.next_input()
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(match self.next_input()? {
+            InputResponse::Value(input) => InputResponse::Value(input as u8 as char),
+            InputResponse::Eof => InputResponse::Eof,
+        })
+    }
+}
+
+/// Exchanges IO over channels, so the machine can run on a worker thread while another
+/// thread (e.g. a GUI) supplies input and shows output. Input blocks until a value is sent,
+/// and reports `InputResponse::Eof` once every input sender has been dropped.
+#[derive(Debug)]
+pub struct ChannelIO {
+    inputs: Receiver<i16>,
+    outputs: Sender<Output>,
+}
+
+impl ChannelIO {
+    pub fn new(inputs: Receiver<i16>, outputs: Sender<Output>) -> Self {
+        ChannelIO { inputs, outputs }
+    }
+
+    /// Makes a handler along with the ends for the other thread: a sender for input and a
+    /// receiver for output.
+    pub fn channel() -> (Self, Sender<i16>, Receiver<Output>) {
+        let (input_sender, inputs) = mpsc::channel();
+        let (outputs, output_receiver) = mpsc::channel();
+        (
+            ChannelIO::new(inputs, outputs),
+            input_sender,
+            output_receiver,
+        )
+    }
+}
+
+impl LMCIO for ChannelIO {
+    /// Panics if the input channel has closed; the machine itself uses `try_get_input`
+    /// instead.
+    fn get_input(&mut self) -> i16 {
+        self.inputs.recv().expect("ChannelIO input channel closed")
+    }
+
+    /// Output is dropped if nothing is listening; the machine itself uses `try_print_output`,
+    /// which reports it.
+    fn print_output(&mut self, val: Output) {
+        let _ = self.outputs.send(val);
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        Ok(self
+            .inputs
+            .recv()
+            .map_or(InputResponse::Eof, InputResponse::Value))
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(match self.inputs.recv() {
+            Ok(input) => InputResponse::Value(input as u8 as char),
+            Err(_) => InputResponse::Eof,
+        })
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        self.outputs
+            .send(val)
+            .map_err(|_| IoError::Failed("output channel closed".to_string()))
+    }
+}
+
+/// Feeds the program pseudo-random inputs from a seed, recording them (and the outputs) so a
+/// failing run can be reproduced, e.g. with `ReplayIO::with_script`.
+#[derive(Debug, Clone)]
+pub struct RandomIO {
+    seed: u64,
+    range: RangeInclusive<i16>,
+    inputs: Vec<i16>,
+    outputs: Vec<Output>,
+}
+
+impl RandomIO {
+    /// Generates inputs in 0..=999.
+    pub fn new(seed: u64) -> Self {
+        Self::with_range(seed, 0..=999)
+    }
+
+    pub fn with_range(seed: u64, range: RangeInclusive<i16>) -> Self {
+        RandomIO {
+            seed,
+            range,
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    /// Every input generated so far, in order.
+    pub fn inputs(&self) -> &[i16] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.seed = self.seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl LMCIO for RandomIO {
+    fn get_input(&mut self) -> i16 {
+        let (low, high) = (i64::from(*self.range.start()), i64::from(*self.range.end()));
+        let span = (high - low + 1).max(1) as u64;
+        let input = (low + (self.next_u64() % span) as i64) as i16;
+        self.inputs.push(input);
+        input
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.outputs.push(val);
+    }
+}
+
+/// For stepping without a handler: there's never any input and output goes nowhere.
+pub(crate) struct NoIO;
+
+impl LMCIO for NoIO {
+    fn get_input(&mut self) -> i16 {
+        0
+    }
+
+    fn print_output(&mut self, _val: Output) {}
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        Ok(InputResponse::Eof)
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(InputResponse::Eof)
+    }
+}
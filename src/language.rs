@@ -0,0 +1,255 @@
+//! Editor support for `.lmc` files, for language servers and web editors: diagnostics,
+//! go-to-definition, hover, renaming labels and syntax highlighting. Lines are 1-based, like `Diagnostic::line`,
+//! and columns are byte offsets into the line.
+
+use std::ops::Range;
+
+use crate::{build, parse_line, BuildOptions, Diagnostic, Instruction, Label, Operand};
+
+/// A piece of a source line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub columns: Range<usize>,
+}
+
+/// Replace the text at `span` with `text`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Opcode,
+    LabelDefinition,
+    LabelReference,
+    Number,
+    Comment,
+}
+
+/// A classified piece of source, for syntax highlighting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub kind: TokenKind,
+}
+
+/// What a line assembled to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hover {
+    pub address: usize,
+    pub word: i16,
+}
+
+/// Every problem `build` finds in `source`, errors and warnings alike.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    match build(source, &BuildOptions::default()) {
+        Ok(assembly) => assembly.warnings,
+        Err(diagnostics) => diagnostics.0,
+    }
+}
+
+/// Where the label at a position is defined.
+pub fn definition(source: &str, line: usize, column: usize) -> Option<Span> {
+    let name = label_at(source, line, column)?;
+    labels(source).into_iter().find_map(|label| match label {
+        LabelSpan::Definition(span, defined) if defined == name => Some(span),
+        _ => None,
+    })
+}
+
+/// Every definition and use of the label at a position.
+pub fn references(source: &str, line: usize, column: usize) -> Vec<Span> {
+    match label_at(source, line, column) {
+        Some(name) => labels(source)
+            .into_iter()
+            .filter_map(|label| match label {
+                LabelSpan::Definition(span, other) | LabelSpan::Reference(span, other)
+                    if other == name =>
+                {
+                    Some(span)
+                }
+                _ => None,
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// The mailbox a line was assembled into, if the program builds.
+pub fn hover(source: &str, line: usize) -> Option<Hover> {
+    let assembly = build(source, &BuildOptions::default()).ok()?;
+    let address = assembly
+        .source_lines
+        .iter()
+        .position(|source_line| *source_line == Some(line))?;
+
+    Some(Hover {
+        address,
+        word: assembly.image.0[address],
+    })
+}
+
+/// The edits that rename the label at a position (and every use of it) to `new_name`.
+pub fn rename(
+    source: &str,
+    line: usize,
+    column: usize,
+    new_name: &str,
+) -> Result<Vec<Edit>, String> {
+    if label_at(source, line, column).is_none() {
+        return Err("No label to rename".to_string());
+    }
+    if !is_valid_label(new_name) {
+        return Err(format!("Invalid label... {new_name}"));
+    }
+    let taken = labels(source)
+        .into_iter()
+        .any(|label| matches!(label, LabelSpan::Definition(_, name) if name == new_name));
+    if taken {
+        return Err(format!("Label already defined... {new_name}"));
+    }
+
+    Ok(references(source, line, column)
+        .into_iter()
+        .map(|span| Edit {
+            span,
+            text: new_name.to_string(),
+        })
+        .collect())
+}
+
+/// Classifies every token in `source`, using the same parser as the assembler. Lines that
+/// don't parse are skipped, and the `@` of an indirect operand isn't part of its token.
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    let mut semantic_tokens = vec![];
+
+    for (i, text) in source.lines().enumerate() {
+        let tokens = tokens(text);
+        let mut push = |columns: Range<usize>, kind| {
+            semantic_tokens.push(SemanticToken {
+                span: Span {
+                    line: i + 1,
+                    columns,
+                },
+                kind,
+            })
+        };
+
+        let (label, instruction) = match parse_line(text) {
+            Ok(Some(entry)) => entry,
+            Ok(None) if !tokens.is_empty() => {
+                push(
+                    tokens[0].start..tokens[tokens.len() - 1].end,
+                    TokenKind::Comment,
+                );
+                continue;
+            }
+            _ => continue,
+        };
+
+        let mut tokens = tokens.into_iter();
+        if let Label::LBL(_) = label {
+            push(tokens.next().unwrap(), TokenKind::LabelDefinition);
+        }
+        push(tokens.next().unwrap(), TokenKind::Opcode);
+        if let Some(operand) = tokens.next() {
+            let kind = match instruction.operand().and_then(Operand::label) {
+                Some(_) => TokenKind::LabelReference,
+                None => TokenKind::Number,
+            };
+            let value = text[operand.clone()].trim_start_matches('@');
+            push(operand.end - value.len()..operand.end, kind);
+        }
+    }
+
+    semantic_tokens
+}
+
+/// Applies edits from `rename` to the source they were made for.
+pub fn apply_edits(source: &str, edits: &[Edit]) -> String {
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    // later columns first, so earlier offsets on the same line stay valid
+    edits.sort_by_key(|edit| std::cmp::Reverse((edit.span.line, edit.span.columns.start)));
+    for edit in edits {
+        if let Some(line) = lines.get_mut(edit.span.line - 1) {
+            line.replace_range(edit.span.columns.clone(), &edit.text);
+        }
+    }
+
+    let mut result = lines.join("\n");
+    if source.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+enum LabelSpan {
+    Definition(Span, String),
+    Reference(Span, String),
+}
+
+/// Finds every label definition and reference in lines that parse.
+fn labels(source: &str) -> Vec<LabelSpan> {
+    let lines: Vec<&str> = source.lines().collect();
+
+    semantic_tokens(source)
+        .into_iter()
+        .filter_map(|token| {
+            let name = lines[token.span.line - 1][token.span.columns.clone()].to_string();
+            match token.kind {
+                TokenKind::LabelDefinition => Some(LabelSpan::Definition(token.span, name)),
+                TokenKind::LabelReference => Some(LabelSpan::Reference(token.span, name)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn label_at(source: &str, line: usize, column: usize) -> Option<String> {
+    labels(source).into_iter().find_map(|label| match label {
+        LabelSpan::Definition(span, name) | LabelSpan::Reference(span, name)
+            if span.line == line
+                && (span.columns.contains(&column) || span.columns.end == column) =>
+        {
+            Some(name)
+        }
+        _ => None,
+    })
+}
+
+/// The byte ranges of the whitespace-separated tokens in a line.
+pub(crate) fn tokens(line: &str) -> Vec<Range<usize>> {
+    let mut tokens = vec![];
+    let mut start = None;
+
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(token_start)) => {
+                tokens.push(token_start..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(token_start) = start {
+        tokens.push(token_start..line.len());
+    }
+
+    tokens
+}
+
+/// Whether `name` would be read back as a label.
+fn is_valid_label(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('@')
+        && !name.starts_with("//")
+        && !name.chars().any(char::is_whitespace)
+        && name.parse::<i16>().is_err()
+        && Instruction::from_string(name, Some(Operand::Value(0))).is_none()
+}
@@ -1,25 +1,100 @@
 use std::{
-    io::{self, Write},
+    fmt,
+    io::{BufRead, Write},
+    ops::Range,
     str::FromStr,
+    time::{Duration, Instant},
 };
 
 #[cfg(feature = "serde")]
 use serde_derive::{Deserialize, Serialize};
 
+pub mod analysis;
+mod assertions;
+#[cfg(feature = "async")]
+mod async_io;
+mod batch;
+pub mod bench;
+pub mod codegen;
+mod compile;
+pub mod debugger;
+mod decode;
+mod diagnostics;
+mod diff;
+mod error;
+mod export;
+mod expr;
+mod formatter;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod grader;
+mod image;
+pub mod io;
+pub mod language;
+mod lint;
+mod machine;
+mod observer;
+#[cfg(feature = "serde")]
+mod persist;
+mod profile;
+mod scheduler;
+mod session;
+mod symbols;
+pub mod testing;
+mod trace;
+
+pub use assertions::{assertions, check_assertions, Assertion, AssertionFailure, AssertionReport};
+#[cfg(feature = "async")]
+pub use async_io::{run_async, run_async_with_state, AsyncLMCIO};
+pub use batch::{run_batch, run_batch_with_config};
+pub use compile::CompiledProgram;
+pub use decode::{DecodeError, DecodedInstruction, Extensions};
+pub use diagnostics::{Diagnostic, DiagnosticCode, Diagnostics, Severity};
+pub use diff::StateDiff;
+pub use error::RuntimeError;
+pub use export::{outputs_to_json, TRACE_JSON_VERSION};
+pub use expr::{BinOp, Expr, Register};
+pub use formatter::format;
+pub use image::MemoryImage;
+pub use io::{InputResponse, IoError};
+pub use lint::{lint, lint_with, LintRules};
+pub use machine::{LMCBuilder, LMC};
+pub use observer::{ExecutionObserver, OnWrite};
+#[cfg(feature = "serde")]
+pub use persist::{SavedState, STATE_VERSION};
+pub use profile::{Coverage, ExecutionStats, Heatmap, Profile};
+pub use scheduler::{Machine, Scheduler};
+pub use session::{Session, SessionStatus};
+pub use symbols::SymbolTable;
+pub use trace::{
+    MemoryRead, MemoryWrite, RegisterChange, RuntimeWarning, StepEvent, StepOutcome, Trace,
+    TraceStep,
+};
+
+use decode::DecodeCache;
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     LDA(Operand),
     STA(Operand),
     ADD(Operand),
     SUB(Operand),
     INP,
+    INA,
     OUT,
     OTC,
     HLT,
     BRZ(Operand),
     BRP(Operand),
     BRA(Operand),
+    PUSH,
+    POP,
+    CALL(Operand),
+    RET,
+    MUL(Operand),
+    DIV(Operand),
+    RTI,
     DAT(Operand),
 }
 
@@ -31,45 +106,271 @@ impl Instruction {
             "ADD" => Some(Instruction::ADD(operand.expect("ADD requires an operand"))),
             "SUB" => Some(Instruction::SUB(operand.expect("SUB requires an operand"))),
             "INP" => Some(Instruction::INP),
+            "INA" => Some(Instruction::INA),
             "OUT" => Some(Instruction::OUT),
             "OTC" => Some(Instruction::OTC),
             "HLT" => Some(Instruction::HLT),
             "BRZ" => Some(Instruction::BRZ(operand.expect("BRZ requires an operand"))),
             "BRP" => Some(Instruction::BRP(operand.expect("BRP requires an operand"))),
             "BRA" => Some(Instruction::BRA(operand.expect("BRA requires an operand"))),
+            "PUSH" => Some(Instruction::PUSH),
+            "POP" => Some(Instruction::POP),
+            "CALL" => Some(Instruction::CALL(
+                operand.expect("CALL requires an operand"),
+            )),
+            "RET" => Some(Instruction::RET),
+            "MUL" => Some(Instruction::MUL(operand.expect("MUL requires an operand"))),
+            "DIV" => Some(Instruction::DIV(operand.expect("DIV requires an operand"))),
+            "RTI" => Some(Instruction::RTI),
             "DAT" => Some(Instruction::DAT(operand.unwrap_or(Operand::Value(0)))), // DAT can have an operand, but doesn't have to
             _ => None,
         }
     }
-    fn get_base(&self) -> i16 {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::LDA(_) => "LDA",
+            Self::STA(_) => "STA",
+            Self::ADD(_) => "ADD",
+            Self::SUB(_) => "SUB",
+            Self::INP => "INP",
+            Self::INA => "INA",
+            Self::OUT => "OUT",
+            Self::OTC => "OTC",
+            Self::HLT => "HLT",
+            Self::BRZ(_) => "BRZ",
+            Self::BRP(_) => "BRP",
+            Self::BRA(_) => "BRA",
+            Self::PUSH => "PUSH",
+            Self::POP => "POP",
+            Self::CALL(_) => "CALL",
+            Self::RET => "RET",
+            Self::MUL(_) => "MUL",
+            Self::DIV(_) => "DIV",
+            Self::RTI => "RTI",
+            Self::DAT(_) => "DAT",
+        }
+    }
+
+    pub fn operand(&self) -> Option<&Operand> {
         match self {
-            Self::LDA(_) => 500,
-            Self::STA(_) => 300,
-            Self::ADD(_) => 100,
-            Self::SUB(_) => 200,
-            Self::INP => 901,
-            Self::OUT => 902,
-            Self::OTC => 922,
-            Self::HLT => 0,
-            Self::BRZ(_) => 700,
-            Self::BRP(_) => 800,
-            Self::BRA(_) => 600,
-            Self::DAT(_) => 0,
+            Self::LDA(operand)
+            | Self::STA(operand)
+            | Self::ADD(operand)
+            | Self::SUB(operand)
+            | Self::BRZ(operand)
+            | Self::BRP(operand)
+            | Self::BRA(operand)
+            | Self::CALL(operand)
+            | Self::MUL(operand)
+            | Self::DIV(operand)
+            | Self::DAT(operand) => Some(operand),
+            Self::INP
+            | Self::INA
+            | Self::OUT
+            | Self::OTC
+            | Self::HLT
+            | Self::PUSH
+            | Self::POP
+            | Self::RET
+            | Self::RTI => None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operand() {
+            Some(operand) => write!(f, "{} {}", self.mnemonic(), operand),
+            None => write!(f, "{}", self.mnemonic()),
         }
     }
 }
 
+/// Maps each mnemonic to the base value it assembles to. Instructions that take an address
+/// occupy `base..=base + 99` (or up to the last mailbox, see `for_size`); the rest assemble
+/// to exactly `base`.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Encoding {
+    entries: Vec<(String, i16)>,
+    #[cfg_attr(feature = "serde", serde(default = "default_mailboxes"))]
+    mailboxes: i16,
+    #[cfg_attr(feature = "serde", serde(default))]
+    extensions: Extensions,
+}
+
+#[cfg(feature = "serde")]
+fn default_mailboxes() -> i16 {
+    100
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self {
+            entries: vec![
+                ("LDA".to_string(), 500),
+                ("STA".to_string(), 300),
+                ("ADD".to_string(), 100),
+                ("SUB".to_string(), 200),
+                ("INP".to_string(), 901),
+                ("INA".to_string(), 912),
+                ("OUT".to_string(), 902),
+                ("OTC".to_string(), 922),
+                ("HLT".to_string(), 0),
+                ("BRZ".to_string(), 700),
+                ("BRP".to_string(), 800),
+                ("BRA".to_string(), 600),
+            ],
+            mailboxes: 100,
+            extensions: Extensions::default(),
+        }
+    }
+}
+
+impl Encoding {
+    /// The standard encoding scaled up for a machine with `mailboxes` mailboxes: each opcode
+    /// digit is multiplied by the memory size instead of 100, so with 1000 mailboxes LDA 123
+    /// is 5123 and INP is 9001.
+    pub fn for_size(mailboxes: usize) -> Self {
+        let mailboxes = mailboxes as i16;
+        let standard = Self::default();
+        Self {
+            entries: standard
+                .entries
+                .into_iter()
+                .map(|(mnemonic, base)| (mnemonic, base / 100 * mailboxes + base % 100))
+                .collect(),
+            mailboxes,
+            extensions: standard.extensions,
+        }
+    }
+
+    /// How many mailboxes the encoding addresses (100 unless made with `for_size`).
+    pub fn mailboxes(&self) -> usize {
+        self.mailboxes as usize
+    }
+
+    /// Enables the given extension instructions, on top of any already enabled.
+    pub fn with_extensions(mut self, extensions: &Extensions) -> Self {
+        if extensions.stack && !self.extensions.stack {
+            let call = -(self.base("BRA").unwrap_or(600));
+            let push = 9 * self.mailboxes + 30;
+            self = self
+                .with("PUSH", push)
+                .with("POP", push + 1)
+                .with("RET", push + 2)
+                .with("CALL", call);
+        }
+        if extensions.mul_div && !self.extensions.mul_div {
+            let mul = 4 * self.mailboxes;
+            self = self.with("MUL", mul).with("DIV", -mul);
+        }
+        if extensions.interrupts && !self.extensions.interrupts {
+            let rti = 9 * self.mailboxes + 40;
+            self = self.with("RTI", rti);
+        }
+        self.extensions.indirect |= extensions.indirect;
+        self.extensions.stack |= extensions.stack;
+        self.extensions.mul_div |= extensions.mul_div;
+        self.extensions.interrupts |= extensions.interrupts;
+        self
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Overrides (or adds) the base value for `mnemonic`.
+    pub fn with(mut self, mnemonic: &str, base: i16) -> Self {
+        let mnemonic = mnemonic.to_uppercase();
+        match self.entries.iter_mut().find(|(m, _)| *m == mnemonic) {
+            Some(entry) => entry.1 = base,
+            None => self.entries.push((mnemonic, base)),
+        }
+        self
+    }
+
+    pub fn base(&self, mnemonic: &str) -> Option<i16> {
+        self.entries
+            .iter()
+            .find(|(m, _)| m.eq_ignore_ascii_case(mnemonic))
+            .map(|(_, base)| *base)
+    }
+
+    /// Finds the instruction a word encodes, returning its mnemonic and address (if any).
+    pub fn decode(&self, word: i16) -> Option<(&str, Option<i16>)> {
+        // exact matches take priority so that e.g. 901 decodes as INP rather than an address
+        if let Some((mnemonic, _)) = self
+            .entries
+            .iter()
+            .find(|(m, base)| !takes_address(m) && *base == word)
+        {
+            return Some((mnemonic, None));
+        }
+
+        self.entries.iter().find_map(|(m, base)| {
+            let address = match *base < 0 {
+                true => base.checked_sub(word)?,
+                false => word.checked_sub(*base)?,
+            };
+            (takes_address(m) && (0..self.mailboxes).contains(&address))
+                .then_some((m.as_str(), Some(address)))
+        })
+    }
+
+    pub fn decode_instruction(&self, word: i16) -> Result<DecodedInstruction, DecodeError> {
+        if word < 0 && self.extensions.indirect {
+            // i16::MIN has no positive counterpart, so it falls through to an invalid instruction
+            match word
+                .checked_neg()
+                .map(|positive| self.decode_instruction(positive))
+            {
+                Some(Ok(DecodedInstruction::Lda(address))) => {
+                    return Ok(DecodedInstruction::LdaIndirect(address))
+                }
+                Some(Ok(DecodedInstruction::Sta(address))) => {
+                    return Ok(DecodedInstruction::StaIndirect(address))
+                }
+                _ => {}
+            }
+        }
+
+        self.decode(word)
+            .and_then(|(mnemonic, address)| DecodedInstruction::from_parts(mnemonic, address))
+            .ok_or(DecodeError { word })
+    }
+}
+
+/// Every mnemonic `Instruction::from_string` knows.
+pub(crate) const MNEMONICS: [&str; 20] = [
+    "LDA", "STA", "ADD", "SUB", "INP", "INA", "OUT", "OTC", "HLT", "BRZ", "BRP", "BRA", "PUSH",
+    "POP", "CALL", "RET", "MUL", "DIV", "RTI", "DAT",
+];
+
+fn takes_address(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic,
+        "LDA" | "STA" | "ADD" | "SUB" | "BRZ" | "BRP" | "BRA" | "CALL" | "MUL" | "DIV"
+    )
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Operand {
     Value(i16),
     Label(String),
+    /// `@X`: the address stored at X, rather than X itself.
+    Indirect(Box<Operand>),
 }
 
 impl FromStr for Operand {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(pointer) = s.strip_prefix('@') {
+            return Ok(Operand::Indirect(Box::new(pointer.parse()?)));
+        }
+
         match s.parse::<i16>() {
             Ok(val) => Ok(Operand::Value(val)),
             Err(_) => Ok(Operand::Label(s.to_string())),
@@ -77,24 +378,39 @@ impl FromStr for Operand {
     }
 }
 
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Value(val) => write!(f, "{}", val),
+            Operand::Label(lbl) => write!(f, "{}", lbl),
+            Operand::Indirect(pointer) => write!(f, "@{}", pointer),
+        }
+    }
+}
+
 impl Operand {
-    fn get_value(&self, program: &Program) -> Result<i16, String> {
+    /// The label this operand refers to, if any.
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Operand::Value(_) => None,
+            Operand::Label(lbl) => Some(lbl),
+            Operand::Indirect(pointer) => pointer.label(),
+        }
+    }
+
+    fn get_value(&self, symbols: &SymbolTable) -> Result<i16, String> {
         match self {
             Operand::Value(val) => Ok(*val),
-            Operand::Label(lbl) => {
-                for (pos, (label, _)) in program.iter().enumerate() {
-                    if label == &Label::LBL(lbl.to_string()) {
-                        return Ok(pos as i16);
-                    }
-                }
-                Err(format!("Invalid label... {}", lbl))
-            }
+            Operand::Label(lbl) => symbols
+                .get(lbl)
+                .ok_or_else(|| format!("Invalid label... {}", lbl)),
+            Operand::Indirect(pointer) => pointer.get_value(symbols),
         }
     }
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Label {
     LBL(String),
     None,
@@ -120,71 +436,316 @@ pub fn parse(code: &str, debug_mode: bool) -> Result<Program, String> {
     let mut program: Program = vec![];
 
     for line in code.lines() {
-        let tokens: Vec<&str> = line.split_whitespace().collect();
-
         if debug_mode {
-            println!("{:?}", tokens);
+            println!("{:?}", line.split_whitespace().collect::<Vec<_>>());
         }
 
-        if !tokens.is_empty() && tokens[0].starts_with("//") {
-            continue;
+        if let Some(entry) = parse_line(line).map_err(|diagnostic| diagnostic.message)? {
+            program.push(entry);
         }
+    }
+
+    if debug_mode {
+        println!();
+    }
+
+    Ok(program)
+}
+
+fn parse_line(line: &str) -> Result<Option<(Label, Instruction)>, Diagnostic> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let spans = language::tokens(line);
+    let at = |i: usize| {
+        let columns = spans[i].clone();
+        move |diagnostic: Diagnostic| diagnostic.at_columns(columns)
+    };
 
-        match tokens.len() {
-            0 => continue,
-            1 => {
-                let instruction = Instruction::from_string(tokens[0], None)
-                    .ok_or_else(|| format!("Invalid opcode... {}", tokens[0]))?;
+    if !tokens.is_empty() && tokens[0].starts_with("//") {
+        return Ok(None);
+    }
+
+    let entry = match tokens.len() {
+        0 => return Ok(None),
+        1 => (
+            Label::None,
+            parse_instruction(tokens[0], None).map_err(at(0))?,
+        ),
+        2 => {
+            let operand = parse_operand(tokens[1]).map_err(at(1))?;
 
-                program.push((Label::None, instruction));
+            match Instruction::from_string(tokens[0], Some(operand)) {
+                Some(val) => (Label::None, val),
+                None => (
+                    Label::LBL(tokens[0].to_string()),
+                    parse_instruction(tokens[1], None).map_err(at(1))?,
+                ),
             }
-            2 => {
-                let operand = tokens[1].parse::<Operand>()?;
+        }
+        3 => {
+            let operand = parse_operand(tokens[2]).map_err(at(2))?;
+
+            (
+                Label::LBL(tokens[0].to_string()),
+                parse_instruction(tokens[1], Some(operand)).map_err(at(1))?,
+            )
+        }
+        _ => {
+            return Err(Diagnostic::error(
+                DiagnosticCode::InvalidLine,
+                format!("Error while reading line: {}", line),
+            ))
+        }
+    };
+
+    let indirect = matches!(entry.1.operand(), Some(Operand::Indirect(_)));
+    if indirect && !matches!(entry.1, Instruction::LDA(_) | Instruction::STA(_)) {
+        return Err(Diagnostic::error(
+            DiagnosticCode::IndirectNotSupported,
+            format!(
+                "Indirect addressing not supported... {}",
+                entry.1.mnemonic()
+            ),
+        )
+        .about(entry.1.mnemonic())
+        .at_columns(LineSpans::new(line, &entry.0).opcode));
+    }
 
-                match Instruction::from_string(tokens[0], Some(operand)) {
-                    Some(val) => program.push((Label::None, val)),
-                    None => {
-                        let instruction = Instruction::from_string(tokens[1], None)
-                            .ok_or_else(|| format!("Invalid opcode... {}", tokens[1]))?;
+    Ok(Some(entry))
+}
 
-                        program.push((Label::LBL(tokens[0].to_string()), instruction));
-                    }
-                }
+/// Where the parts of a line that parsed are, as byte offsets into the line.
+struct LineSpans {
+    label: Option<Range<usize>>,
+    opcode: Range<usize>,
+    /// Without the `@` of an indirect operand.
+    operand: Option<Range<usize>>,
+}
+
+impl LineSpans {
+    fn new(line: &str, label: &Label) -> Self {
+        let mut tokens = language::tokens(line).into_iter();
+        let label = match label {
+            Label::LBL(_) => tokens.next(),
+            Label::None => None,
+        };
+        let opcode = tokens.next().unwrap_or_default();
+        let operand = tokens.next().map(|operand| {
+            let value = line[operand.clone()].trim_start_matches('@');
+            operand.end - value.len()..operand.end
+        });
+
+        LineSpans {
+            label,
+            opcode,
+            operand,
+        }
+    }
+}
+
+fn parse_operand(operand: &str) -> Result<Operand, Diagnostic> {
+    operand.parse().map_err(|message| {
+        Diagnostic::error(DiagnosticCode::InvalidOperand, message).about(operand)
+    })
+}
+
+fn parse_instruction(opcode: &str, operand: Option<Operand>) -> Result<Instruction, Diagnostic> {
+    if operand.is_none() && takes_address(&opcode.to_uppercase()) {
+        return Err(Diagnostic::error(
+            DiagnosticCode::MissingOperand,
+            format!("Missing operand... {}", opcode),
+        )
+        .about(opcode));
+    }
+
+    Instruction::from_string(opcode, operand).ok_or_else(|| {
+        Diagnostic::error(
+            DiagnosticCode::InvalidOpcode,
+            format!("Invalid opcode... {}", opcode),
+        )
+        .about(opcode)
+    })
+}
+
+/// Parses, lints and assembles `source` in one go, collecting every problem found along the
+/// way (with its source line) rather than stopping at the first one.
+///
+/// The program can use as many mailboxes as `options.encoding` has, up to the 100 an
+/// `Assembly` holds. Use `assemble_sized` for programs for larger machines.
+pub fn build(source: &str, options: &BuildOptions) -> Result<Assembly, Diagnostics> {
+    let mut program: Program = vec![];
+    let mut lines = vec![];
+    let mut spans = vec![];
+    let mut diagnostics = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(entry)) => {
+                spans.push(LineSpans::new(line, &entry.0));
+                program.push(entry);
+                lines.push(i + 1);
             }
-            3 => {
-                let operand = tokens[2].parse::<Operand>()?;
+            Ok(None) => {}
+            Err(diagnostic) => diagnostics.push(diagnostic.at_line(i + 1)),
+        }
+    }
 
-                let instruction = Instruction::from_string(tokens[1], Some(operand))
-                    .ok_or_else(|| format!("Invalid opcode... {}", tokens[1]))?;
+    // an `Assembly` holds 100 mailboxes, so larger machines still stop there
+    let mailboxes = options.encoding.mailboxes().min(100);
+    if program.len() > mailboxes {
+        diagnostics.push(
+            Diagnostic::error(
+                DiagnosticCode::ProgramTooLarge,
+                format!("Program is too large... {} mailboxes", program.len()),
+            )
+            .at_line(lines[mailboxes]),
+        );
+    }
 
-                program.push((Label::LBL(tokens[0].to_string()), instruction));
+    for diagnostic in lint_with(&program, &options.lint) {
+        let line = diagnostic.address.map(|address| lines[address]);
+        let columns = diagnostic.address.and_then(|address| {
+            let spans = &spans[address];
+            match diagnostic.code {
+                DiagnosticCode::DuplicateLabel | DiagnosticCode::UnusedLabel => spans.label.clone(),
+                DiagnosticCode::BranchToData => spans.operand.clone(),
+                DiagnosticCode::UnreachableCode => Some(spans.opcode.clone()),
+                _ => None,
             }
-            _ => return Err(format!("Error while reading line: {}", line)),
+        });
+        diagnostics.push(Diagnostic {
+            line,
+            columns,
+            ..diagnostic
+        });
+    }
+
+    let mut symbols = options.symbols.clone();
+    for (name, address) in SymbolTable::from_program(&program).iter() {
+        if symbols.insert(name, address).is_err() && options.symbols.get(name).is_some() {
+            diagnostics.push(
+                Diagnostic::error(
+                    DiagnosticCode::LabelInSymbolFile,
+                    format!("Label already defined in symbol file... {}", name),
+                )
+                .about(name)
+                .at_line(lines[address as usize])
+                .at_columns(spans[address as usize].label.clone().unwrap_or_default()),
+            );
         }
     }
 
-    if debug_mode {
-        println!();
+    for (address, (_, instruction)) in program.iter().enumerate() {
+        if let Some(name) = instruction.operand().and_then(Operand::label) {
+            if symbols.get(name).is_none() {
+                diagnostics.push(
+                    Diagnostic::error(
+                        DiagnosticCode::InvalidLabel,
+                        format!("Invalid label... {}", name),
+                    )
+                    .about(name)
+                    .at_line(lines[address])
+                    .at_columns(spans[address].operand.clone().unwrap_or_default()),
+                );
+            }
+        }
     }
 
-    Ok(program)
+    let failed = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error || options.deny_warnings);
+
+    if failed {
+        return Err(Diagnostics(diagnostics));
+    }
+
+    let mut assembly = Assembly::assemble(program, options.encoding.clone(), &options.symbols)
+        .map_err(|diagnostic| Diagnostics(vec![diagnostic]))?;
+
+    for (address, line) in lines.into_iter().enumerate() {
+        assembly.source_lines[address] = Some(line);
+    }
+    assembly.warnings = diagnostics;
+
+    Ok(assembly)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    pub encoding: Encoding,
+    /// Predefined constants, e.g. a data layout fixed by a symbols file.
+    pub symbols: SymbolTable,
+    /// Fail the build if there are any warnings.
+    pub deny_warnings: bool,
+    /// Which lint warnings to report.
+    pub lint: LintRules,
 }
 
 pub fn assemble(program: Program) -> Result<[i16; 100], String> {
-    let mut ram = [0; 100];
+    assemble_with_encoding(program, &Encoding::default())
+}
+
+pub fn assemble_with_encoding(program: Program, encoding: &Encoding) -> Result<[i16; 100], String> {
+    assemble_image(&program, encoding, &SymbolTable::from_program(&program))
+        .map_err(|diagnostic| diagnostic.message)
+}
+
+/// Assembles for an extended machine with `N` mailboxes, to be run on an
+/// `ExecutionState<N>`. See `Encoding::for_size` for how instructions are encoded.
+pub fn assemble_sized<const N: usize>(program: Program) -> Result<[i16; N], String> {
+    assemble_image(
+        &program,
+        &Encoding::for_size(N),
+        &SymbolTable::from_program(&program),
+    )
+    .map_err(|diagnostic| diagnostic.message)
+}
+
+fn assemble_image<const N: usize>(
+    program: &Program,
+    encoding: &Encoding,
+    symbols: &SymbolTable,
+) -> Result<[i16; N], Diagnostic> {
+    if program.len() > N {
+        return Err(Diagnostic::error(
+            DiagnosticCode::ProgramTooLarge,
+            format!("Program is too large... {} mailboxes", program.len()),
+        ));
+    }
+    let value = |operand: &Operand| {
+        operand.get_value(symbols).map_err(|message| {
+            Diagnostic::error(DiagnosticCode::InvalidLabel, message).about(operand.to_string())
+        })
+    };
+
+    let mut ram = [0; N];
 
     for (i, (_, instruction)) in program.iter().enumerate() {
         ram[i] = match instruction {
-            Instruction::BRZ(operand) | Instruction::BRP(operand) | Instruction::BRA(operand) => {
-                instruction.get_base() + operand.get_value(&program)?
-            }
-            Instruction::DAT(operand) => operand.get_value(&program)?,
-            Instruction::LDA(operand)
-            | Instruction::STA(operand)
-            | Instruction::ADD(operand)
-            | Instruction::SUB(operand) => instruction.get_base() + operand.get_value(&program)?,
-            Instruction::INP | Instruction::OUT | Instruction::OTC | Instruction::HLT => {
-                instruction.get_base()
+            Instruction::DAT(operand) => value(operand)?,
+            _ => {
+                let base = encoding.base(instruction.mnemonic()).ok_or_else(|| {
+                    Diagnostic::error(
+                        DiagnosticCode::NoEncoding,
+                        format!("No encoding for... {}", instruction.mnemonic()),
+                    )
+                    .about(instruction.mnemonic())
+                })?;
+
+                match instruction.operand() {
+                    Some(Operand::Indirect(pointer)) if encoding.extensions.indirect => {
+                        -(base + value(pointer)?)
+                    }
+                    Some(Operand::Indirect(_)) => {
+                        return Err(Diagnostic::error(
+                            DiagnosticCode::IndirectNotEnabled,
+                            format!("Indirect addressing not enabled... {}", instruction),
+                        ))
+                    }
+                    // negative bases count downwards, e.g. CALL 5 is -605
+                    Some(operand) if base < 0 => base - value(operand)?,
+                    Some(operand) => base + value(operand)?,
+                    None => base,
+                }
             }
         }
     }
@@ -192,160 +753,1677 @@ pub fn assemble(program: Program) -> Result<[i16; 100], String> {
     Ok(ram)
 }
 
+/// Turns a memory image back into a program. Words that don't match any instruction in the
+/// encoding are shown as `DAT`.
+pub fn disassemble(ram: &[i16; 100], encoding: &Encoding) -> Program {
+    ram.iter()
+        .map(|&word| (Label::None, disassemble_word(word, encoding)))
+        .collect()
+}
+
+pub(crate) fn disassemble_word(word: i16, encoding: &Encoding) -> Instruction {
+    encoding
+        .decode_instruction(word)
+        .map(Instruction::from)
+        .unwrap_or(Instruction::DAT(Operand::Value(word)))
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
-pub struct ExecutionState {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellKind {
+    Code,
+    Data,
+    Unused,
+}
+
+/// An assembled program along with what each mailbox was assembled from.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    pub image: MemoryImage,
+    pub kinds: Vec<CellKind>,
+    pub encoding: Encoding,
+    /// Every label the program could refer to, including predefined ones.
+    pub symbols: SymbolTable,
+    /// The 1-based source line each mailbox came from, when built from source.
+    pub source_lines: Vec<Option<usize>>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Assembly {
+    pub fn new(program: Program, encoding: Encoding) -> Result<Self, String> {
+        Self::with_symbols(program, encoding, &SymbolTable::default())
+    }
+
+    /// Assembles `program`, resolving labels it doesn't define itself from `predefined`.
+    pub fn with_symbols(
+        program: Program,
+        encoding: Encoding,
+        predefined: &SymbolTable,
+    ) -> Result<Self, String> {
+        Self::assemble(program, encoding, predefined).map_err(|diagnostic| diagnostic.message)
+    }
+
+    /// `with_symbols`, keeping the kind of problem for `build`.
+    pub(crate) fn assemble(
+        program: Program,
+        encoding: Encoding,
+        predefined: &SymbolTable,
+    ) -> Result<Self, Diagnostic> {
+        let mut symbols = predefined.clone();
+        for (name, address) in SymbolTable::from_program(&program).iter() {
+            symbols.insert(name, address).map_err(|message| {
+                Diagnostic::error(DiagnosticCode::DuplicateLabel, message).about(name)
+            })?;
+        }
+
+        let kinds = (0..100)
+            .map(|i| match program.get(i) {
+                Some((_, Instruction::DAT(_))) => CellKind::Data,
+                Some(_) => CellKind::Code,
+                None => CellKind::Unused,
+            })
+            .collect();
+
+        let image = assemble_image(&program, &encoding, &symbols)?.into();
+
+        Ok(Assembly {
+            image,
+            kinds,
+            encoding,
+            symbols,
+            source_lines: vec![None; 100],
+            warnings: vec![],
+        })
+    }
+
+    /// Disassembles the used mailboxes, keeping data cells as `DAT` even when their value
+    /// happens to look like an instruction (e.g. `DAT 0` vs `HLT`).
+    pub fn disassemble(&self) -> Program {
+        self.image
+            .iter()
+            .zip(&self.kinds)
+            .take_while(|(_, kind)| **kind != CellKind::Unused)
+            .map(|(&word, kind)| match kind {
+                CellKind::Data => (Label::None, Instruction::DAT(Operand::Value(word))),
+                _ => (Label::None, disassemble_word(word, &self.encoding)),
+            })
+            .collect()
+    }
+
+    /// Renders one `address  word  instruction` line per used mailbox.
+    pub fn listing(&self) -> String {
+        self.disassemble()
+            .iter()
+            .enumerate()
+            .map(|(address, (_, instruction))| {
+                format!(
+                    "{:02}  {:03}  {}\n",
+                    address, self.image[address], instruction
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub address: usize,
+    pub expected: i16,
+    pub actual: i16,
+}
+
+/// Re-assembles `source` and reports every mailbox where `image` differs from the result.
+pub fn verify(source: &str, image: &[i16; 100]) -> Result<Vec<Mismatch>, String> {
+    let expected = assemble(parse(source, false)?)?;
+
+    Ok(expected
+        .iter()
+        .zip(image.iter())
+        .enumerate()
+        .filter(|(_, (expected, actual))| expected != actual)
+        .map(|(address, (&expected, &actual))| Mismatch {
+            address,
+            expected,
+            actual,
+        })
+        .collect())
+}
+
+/// The state of a machine with `N` mailboxes. Anything other than the standard 100 is an
+/// "extended" LMC, where words hold `10 * N - 1` at most and instructions are encoded as
+/// described in `Encoding::for_size`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ExecutionState<const N: usize = 100> {
     pub pc: i16,
     pub cir: i16,
     pub mar: i16,
     pub mdr: i16,
     pub acc: i16,
+    /// Stack pointer for the stack extension: the address of the top of the stack, or `N`
+    /// when it's empty.
+    pub sp: i16,
+    /// Where RTI will return to, while a timer interrupt is being handled.
+    pub interrupt_return: Option<i16>,
+    /// Set when a SUB (or MUL/DIV) result goes below zero, cleared whenever ACC is loaded or
+    /// added to.
+    pub negative: bool,
+    /// Set when the last ADD, SUB or MUL produced a result outside -999..=999 (or whatever
+    /// `MAX_VALUE` is).
+    pub overflow: bool,
     #[cfg_attr(feature = "serde", serde(with = "serde_arrays"))]
-    pub ram: [i16; 100],
+    pub ram: [i16; N],
+    /// What each mailbox was assembled as, if the state was made with `from_assembly`.
+    pub kinds: Vec<CellKind>,
+    /// Which mailboxes have been executed as instructions.
+    pub executed: Vec<bool>,
+    /// Which mailboxes hold a value from the program or a STA, if known (see `from_assembly`).
+    pub initialized: Vec<bool>,
+    pub config: Config,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Conditions checked after every step; execution pauses when one is non-zero.
+    pub breakpoints: Vec<Expr>,
+    /// Recording of every step executed, if tracing has been started.
+    pub trace: Option<Trace>,
+    /// Execution counts, if profiling has been started.
+    pub profile: Option<Profile>,
+    /// Memory read/write counts, if they're being tracked.
+    pub heatmap: Option<Heatmap>,
+    /// Undo records for `step_back`, if history is being recorded.
+    pub history: Option<Vec<Undo>>,
+    /// Inputs given back by `step_back` or queued by `provide_input`, consumed (last first)
+    /// before asking the IO handler.
+    pub replay_inputs: Vec<i16>,
+    /// Number of instructions executed so far.
+    pub steps_executed: u64,
+    /// Total cost of the instructions executed so far, according to `Config::cycle_costs`.
+    pub cycles: u64,
+    /// Number of values output so far.
+    pub outputs_produced: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    decoded: DecodeCache,
 }
 
-impl ExecutionState {
-    pub fn step<T: LMCIO>(&mut self, io_handler: &mut T) -> Result<(), String> {
-        self.mar = self.pc;
-        self.pc += 1;
-        self.mdr = self.ram[self.mar as usize];
-        self.cir = self.mdr;
-        // do instruction
-        match self.cir {
-            0 => self.pc = -1,
-            901 => {
-                let res = io_handler.get_input();
-                if !(-999..=999).contains(&res) {
-                    return Err("Number out of range".to_string());
-                }
-                self.acc = res;
-            }
-            902 => io_handler.print_output(Output::Int(self.acc)),
-            922 => io_handler.print_output(Output::Char(self.acc as u8 as char)),
-            100..=199 => {
-                self.mar = self.cir - 100;
-                self.acc += self.ram[self.mar as usize];
-                // handle overflow to -999
-                if self.acc > 999 {
-                    let diff = self.acc - 999;
-                    self.acc = -999 + diff - 1;
-                } else if self.acc < -999 {
-                    let diff = -999 - self.acc;
-                    self.acc = 999 - diff + 1;
-                }
-            }
-            200..=299 => {
-                self.mar = self.cir - 200;
-                self.acc -= self.ram[self.mar as usize];
-                // handle underflow to 999
-                if self.acc < -999 {
-                    let diff = -999 - self.acc;
-                    self.acc = 999 - diff + 1;
-                } else if self.acc > 999 {
-                    let diff = self.acc - 999;
-                    self.acc = -999 + diff - 1;
-                }
-            }
-            300..=399 => {
-                self.mar = self.cir - 300;
-                self.ram[self.mar as usize] = self.acc;
-            }
-            500..=599 => {
-                self.mar = self.cir - 500;
-                self.acc = self.ram[self.mar as usize];
-            }
-            600..=699 => {
-                self.mar = self.cir - 600;
-                self.pc = self.mar;
-            }
-            700..=799 => {
-                self.mar = self.cir - 700;
-                if self.acc == 0 {
-                    self.pc = self.mar;
-                }
-            }
-            800..=899 => {
-                self.mar = self.cir - 800;
-                if self.acc >= 0 {
-                    self.pc = self.mar;
-                }
-            }
-            _ => return Err(format!("Invalid instruction: {}", self.cir)),
-        };
+/// A saved copy of an `ExecutionState`, taken with `snapshot` and applied with `restore`.
+#[derive(Debug, Clone)]
+pub struct Snapshot<const N: usize = 100>(ExecutionState<N>);
 
-        Ok(())
-    }
+/// What a single step changed, so that it can be reversed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Undo {
+    pub pc: i16,
+    pub cir: i16,
+    pub mar: i16,
+    pub mdr: i16,
+    pub acc: i16,
+    pub sp: i16,
+    pub interrupt_return: Option<i16>,
+    pub negative: bool,
+    pub overflow: bool,
+    pub write: Option<MemoryWrite>,
+    pub input: Option<i16>,
+    pub output: Option<Output>,
+    /// Cycles the step cost.
+    pub cycles: u64,
+    /// The instruction that ran, to take back out of the profile.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub instruction: Option<DecodedInstruction>,
+    /// The mailbox read as data, to take back out of the heatmap.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub read: Option<i16>,
+    /// Whether this was the first time the mailbox at `pc` was executed (see `executed`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub first_execution: bool,
+    /// Whether the step wrote to a mailbox that wasn't initialized yet (see `initialized`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub first_write: bool,
+    /// Whether the profile, heatmap and trace counted the step. Each of them can be started
+    /// after the step ran.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub in_profile: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub in_heatmap: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub in_trace: bool,
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq)]
-pub enum Output {
-    Char(char),
-    Int(i16),
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
 }
 
-pub trait LMCIO {
-    fn get_input(&mut self) -> i16;
-    fn print_output(&mut self, val: Output);
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub address: i16,
+    pub on_read: bool,
+    pub on_write: bool,
 }
 
-pub struct DefaultIO;
-
-impl LMCIO for DefaultIO {
-    fn get_input(&mut self) -> i16 {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read line");
-
-        input.trim().parse::<i16>().unwrap()
-    }
+/// Details of the access that triggered a watchpoint.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchpointHit {
+    pub address: i16,
+    pub access: Access,
+    pub old: i16,
+    pub new: i16,
+    /// Address of the instruction that made the access.
+    pub pc: i16,
+    pub instruction: i16,
+}
 
-    fn print_output(&mut self, val: Output) {
-        match val {
-            Output::Char(c) => print!("{}", c),
-            Output::Int(i) => println!("{}", i),
+impl ExecutionState {
+    /// Loads an assembled program, keeping track of which mailboxes hold code and data.
+    pub fn from_assembly(assembly: &Assembly) -> Self {
+        ExecutionState {
+            kinds: assembly.kinds.clone(),
+            initialized: assembly
+                .kinds
+                .iter()
+                .map(|kind| *kind != CellKind::Unused)
+                .collect(),
+            ..Self::new(assembly.image.0)
         }
     }
 }
 
-pub fn run<T: LMCIO>(
-    program: [i16; 100],
-    io_handler: &mut T,
-    debug_mode: bool,
-) -> Result<(), String> {
-    let mut state = ExecutionState {
-        pc: 0,
-        cir: 0,
-        mar: 0,
-        mdr: 0,
-        acc: 0,
-        ram: program,
+impl<const N: usize> ExecutionState<N> {
+    /// The largest value a mailbox or the accumulator can hold (999 on a standard machine).
+    /// Words are `i16`s, so `N` can be at most 3276.
+    pub const MAX_VALUE: i16 = {
+        assert!(
+            N > 0 && 10 * N - 1 <= i16::MAX as usize,
+            "An ExecutionState needs between 1 and 3276 mailboxes"
+        );
+        (10 * N - 1) as i16
     };
 
-    loop {
-        state.step(io_handler)?;
+    pub fn new(ram: [i16; N]) -> Self {
+        Self::with_config(ram, Config::default())
+    }
 
-        if state.pc == -1 {
-            break;
+    pub fn with_config(ram: [i16; N], config: Config) -> Self {
+        // so an unsupported `N` fails to compile even if the machine never steps
+        let _ = Self::MAX_VALUE;
+        ExecutionState {
+            pc: 0,
+            cir: 0,
+            mar: 0,
+            mdr: 0,
+            acc: 0,
+            sp: N as i16,
+            interrupt_return: None,
+            negative: false,
+            overflow: false,
+            ram,
+            kinds: vec![],
+            executed: vec![false; N],
+            initialized: vec![],
+            config,
+            watchpoints: vec![],
+            breakpoints: vec![],
+            trace: None,
+            profile: None,
+            heatmap: None,
+            history: None,
+            replay_inputs: vec![],
+            steps_executed: 0,
+            outputs_produced: 0,
+            decoded: DecodeCache::default(),
+            cycles: 0,
         }
+    }
 
-        if debug_mode {
-            println!("PC: {}", state.pc);
-            println!("CIR: {}", state.cir);
-            println!("MAR: {}", state.mar);
-            println!("MDR: {}", state.mdr);
-            println!("ACC: {}", state.acc);
-            println!("RAM: {:?}", state.ram);
-            println!();
+    pub fn snapshot(&self) -> Snapshot<N> {
+        Snapshot(self.clone())
+    }
+
+    /// Returns the machine to exactly how it was when `snapshot` was taken.
+    pub fn restore(&mut self, snapshot: &Snapshot<N>) {
+        *self = snapshot.0.clone();
+    }
+
+    /// Stores a value in a mailbox from outside the program, e.g. from a debugger. Unlike
+    /// assigning to `ram`, this keeps `Config::predecode`'s decoded copy up to date.
+    pub fn set_mailbox(&mut self, address: usize, value: i16) {
+        self.ram[address] = value;
+        self.decoded.invalidate(address);
+    }
+
+    /// Starts recording undo information so that `step_back` can be used.
+    pub fn record_history(&mut self) {
+        self.history = Some(vec![]);
+    }
+
+    /// Reverses the most recent step, returning false if there's nothing to undo.
+    ///
+    /// Besides the registers and memory, the step is taken back out of the step and cycle
+    /// counts, `executed` and `initialized`, and the profile, heatmap and trace if they
+    /// counted it. Any input the step consumed is kept and handed back to the program the
+    /// next time it executes INP. Output can't be taken back.
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.history.as_mut().and_then(Vec::pop) else {
+            return false;
+        };
+
+        self.pc = undo.pc;
+        self.cir = undo.cir;
+        self.mar = undo.mar;
+        self.mdr = undo.mdr;
+        self.acc = undo.acc;
+        self.sp = undo.sp;
+        self.interrupt_return = undo.interrupt_return;
+        self.negative = undo.negative;
+        self.overflow = undo.overflow;
+        self.steps_executed -= 1;
+        self.cycles -= undo.cycles;
+
+        if let Some(write) = &undo.write {
+            self.set_mailbox(write.address as usize, write.old);
+        }
+        if let Some(input) = undo.input {
+            self.replay_inputs.push(input);
+        }
+        if undo.output.is_some() {
+            self.outputs_produced -= 1;
         }
 
-        if state.pc > 99 {
-            break;
+        if undo.first_execution {
+            self.executed[undo.pc as usize] = false;
+        }
+        if let (true, Some(write)) = (undo.first_write, &undo.write) {
+            self.initialized[write.address as usize] = false;
+        }
+        if let (true, Some(profile)) = (undo.in_profile, &mut self.profile) {
+            profile.forget(&undo);
+        }
+        if let (true, Some(heatmap)) = (undo.in_heatmap, &mut self.heatmap) {
+            heatmap.forget(&undo);
         }
+        if let (true, Some(trace)) = (undo.in_trace, &mut self.trace) {
+            trace.steps.pop();
+        }
+
+        true
+    }
+
+    /// Starts recording every step into a fresh `Trace`.
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Trace::default());
     }
 
-    Ok(())
+    /// Stops tracing, returning what was recorded.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    /// Starts counting how often each address and opcode is executed.
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Profile::new(N));
+    }
+
+    /// Stops profiling, returning the counts so far.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    /// Starts counting reads and writes of each mailbox.
+    pub fn start_heatmap(&mut self) {
+        self.heatmap = Some(Heatmap::new(N));
+    }
+
+    pub fn take_heatmap(&mut self) -> Option<Heatmap> {
+        self.heatmap.take()
+    }
+
+    /// Which mailboxes have been executed so far.
+    pub fn coverage(&self) -> Coverage {
+        Coverage {
+            executed: self.executed.clone(),
+        }
+    }
+
+    /// Pauses execution whenever `condition` (e.g. `RAM[42] > 10`) holds after a step.
+    pub fn break_when(&mut self, condition: &str) -> Result<(), String> {
+        self.breakpoints.push(condition.parse()?);
+        Ok(())
+    }
+
+    /// Pauses execution whenever `address` is read and/or written.
+    pub fn watch(&mut self, address: i16, on_read: bool, on_write: bool) {
+        self.watchpoints.push(Watchpoint {
+            address,
+            on_read,
+            on_write,
+        });
+    }
+
+    fn watchpoint_hit(&self, pc: i16, access: Access, old: i16) -> Option<WatchpointHit> {
+        self.watchpoints
+            .iter()
+            .find(|watchpoint| {
+                watchpoint.address == self.mar
+                    && match access {
+                        Access::Read => watchpoint.on_read,
+                        Access::Write => watchpoint.on_write,
+                    }
+            })
+            .map(|_| WatchpointHit {
+                address: self.mar,
+                access,
+                old,
+                new: self.ram[self.mar as usize],
+                pc,
+                instruction: self.cir,
+            })
+    }
+
+    /// Executes a single instruction and describes what it did. If the machine stopped (or
+    /// paused), the event's `halt_reason` says why.
+    ///
+    /// Errors if the program counter doesn't point at a mailbox, e.g. when stepping a
+    /// machine that has already run past the end of memory.
+    pub fn step<T: LMCIO>(&mut self, io_handler: &mut T) -> Result<StepEvent, RuntimeError> {
+        if !(0..N as i16).contains(&self.pc) {
+            return Err(RuntimeError::PcOutOfRange { pc: self.pc });
+        }
+
+        let registers = (
+            self.cir,
+            self.mar,
+            self.mdr,
+            self.acc,
+            self.sp,
+            self.interrupt_return,
+            self.negative,
+            self.overflow,
+        );
+        let mut event = StepEvent {
+            pc: self.pc,
+            word: self.ram[self.pc as usize],
+            instruction: None,
+            read: None,
+            write: None,
+            registers: vec![],
+            overflow: None,
+            input: None,
+            output: None,
+            branch: None,
+            interrupted: false,
+            warnings: vec![],
+            halt_reason: None,
+        };
+        let mut watchpoint_hit = None;
+
+        // what `step_back` needs to know about the state before the step
+        let first_execution = !self.executed[self.pc as usize];
+        let initialized = self.history.is_some().then(|| self.initialized.clone());
+
+        self.mar = self.pc;
+        self.pc += 1;
+        self.mdr = self.ram[self.mar as usize];
+        self.cir = self.mdr;
+        self.executed[self.mar as usize] = true;
+        event.instruction = if self.config.predecode {
+            self.decoded
+                .get(&self.ram, self.mar as usize, &self.config.extensions)
+        } else {
+            DecodedInstruction::decode_with(self.cir, N as i16, &self.config.extensions).ok()
+        };
+        let cycles = event
+            .instruction
+            .map_or(0, |instruction| self.config.cycle_costs.cost(&instruction));
+        self.steps_executed += 1;
+        self.cycles += cycles;
+
+        // indirect instructions behave like the direct one, on the address in the pointer
+        let instruction = match event.instruction {
+            Some(DecodedInstruction::LdaIndirect(pointer)) => self
+                .follow_pointer(pointer, &mut event)
+                .map(DecodedInstruction::Lda),
+            Some(DecodedInstruction::StaIndirect(pointer)) => self
+                .follow_pointer(pointer, &mut event)
+                .map(DecodedInstruction::Sta),
+            instruction => instruction,
+        };
+        if let Some(
+            DecodedInstruction::Lda(address)
+            | DecodedInstruction::Add(address)
+            | DecodedInstruction::Sub(address)
+            | DecodedInstruction::Mul(address)
+            | DecodedInstruction::Div(address),
+        ) = instruction
+        {
+            self.check_initialized(address, &mut event);
+            self.check_value(address, &mut event);
+        }
+        if instruction == Some(DecodedInstruction::Pop) && (self.sp as usize) < N {
+            self.check_value(self.sp, &mut event);
+        }
+
+        io_handler.set_pc(event.pc);
+
+        // do instruction
+        match instruction {
+            // stopped before doing anything, e.g. by an uninitialized read in strict mode
+            _ if event.halt_reason.is_some() => {}
+            Some(DecodedInstruction::Hlt) => event.halt_reason = Some(HaltReason::Halted),
+            Some(DecodedInstruction::Inp) => {
+                let res = match self.replay_inputs.pop() {
+                    Some(res) => Ok(InputResponse::Value(res)),
+                    None => io_handler.try_get_input(),
+                };
+                match res {
+                    Ok(InputResponse::Eof) => self.end_of_input(&mut event)?,
+                    Ok(InputResponse::Value(res)) => {
+                        event.input = Some(res);
+                        if (-Self::MAX_VALUE..=Self::MAX_VALUE).contains(&res) {
+                            self.acc = res;
+                            self.negative = false;
+                        } else {
+                            event.halt_reason = Some(HaltReason::InputOutOfRange {
+                                addr: event.pc,
+                                value: res,
+                            });
+                        }
+                    }
+                    Err(error) => event.halt_reason = Some(HaltReason::IoError(error.to_string())),
+                }
+            }
+            Some(DecodedInstruction::Ina) => {
+                let res = match self.replay_inputs.pop() {
+                    Some(res) => Ok(Some(res)),
+                    None => match io_handler.try_get_char_input() {
+                        Ok(InputResponse::Value(c)) => i16::try_from(c as u32)
+                            .ok()
+                            .filter(|code| *code <= Self::MAX_VALUE)
+                            .map(Some)
+                            .ok_or_else(|| format!("Character out of range: {}", c)),
+                        Ok(InputResponse::Eof) => Ok(None),
+                        Err(error) => Err(error.to_string()),
+                    },
+                };
+                match res {
+                    Ok(None) => self.end_of_input(&mut event)?,
+                    Ok(Some(res)) => {
+                        event.input = Some(res);
+                        self.acc = res;
+                        self.negative = false;
+                    }
+                    Err(message) => event.halt_reason = Some(HaltReason::IoError(message)),
+                }
+            }
+            Some(DecodedInstruction::Out) => event.output = Some(Output::Int(self.acc)),
+            Some(DecodedInstruction::Otc) => {
+                event.output = Some(Output::Char(self.acc as u8 as char))
+            }
+            Some(DecodedInstruction::Add(address)) => {
+                self.mar = address;
+                let read = self.read();
+                self.negative = false;
+                self.set_acc_checked(self.acc as i32 + read.value as i32, &mut event);
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, read.value);
+                event.read = Some(read);
+            }
+            Some(DecodedInstruction::Sub(address)) => {
+                self.mar = address;
+                let read = self.read();
+                let result = self.acc as i32 - read.value as i32;
+                self.negative = result < 0;
+                self.set_acc_checked(result, &mut event);
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, read.value);
+                event.read = Some(read);
+            }
+            Some(DecodedInstruction::Mul(address)) => {
+                self.mar = address;
+                let read = self.read();
+                let result = self.acc as i32 * read.value as i32;
+                self.negative = result < 0;
+                self.set_acc_checked(result, &mut event);
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, read.value);
+                event.read = Some(read);
+            }
+            Some(DecodedInstruction::Div(address)) => {
+                self.mar = address;
+                let read = self.read();
+                if read.value == 0 {
+                    event.halt_reason = Some(HaltReason::DivisionByZero { addr: event.pc });
+                } else {
+                    // in i32, since i16::MIN / -1 doesn't fit back into an i16
+                    let result = self.acc as i32 / read.value as i32;
+                    self.negative = result < 0;
+                    self.set_acc_checked(result, &mut event);
+                }
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, read.value);
+                event.read = Some(read);
+            }
+            Some(DecodedInstruction::Sta(address)) => {
+                self.mar = address;
+                watchpoint_hit = self.write(self.acc, &mut event);
+            }
+            Some(DecodedInstruction::Lda(address)) => {
+                self.mar = address;
+                event.read = Some(self.read());
+                self.acc = self.ram[self.mar as usize];
+                self.negative = false;
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, self.acc);
+            }
+            Some(DecodedInstruction::Bra(address)) => {
+                self.mar = address;
+                self.pc = self.mar;
+                event.branch = Some(true);
+            }
+            Some(DecodedInstruction::Brz(address)) => {
+                self.mar = address;
+                event.branch = Some(self.acc == 0);
+                if self.acc == 0 {
+                    self.pc = self.mar;
+                }
+            }
+            Some(DecodedInstruction::Brp(address)) => {
+                self.mar = address;
+                let positive = match self.config.brp_mode {
+                    BrpMode::NegativeFlag => !self.negative,
+                    BrpMode::SignedAccumulator => self.acc >= 0,
+                };
+                event.branch = Some(positive);
+                if positive {
+                    self.pc = self.mar;
+                }
+            }
+            Some(DecodedInstruction::Push) => {
+                if self.sp == 0 {
+                    event.halt_reason = Some(HaltReason::StackOverflow { addr: event.pc });
+                } else {
+                    self.sp -= 1;
+                    self.mar = self.sp;
+                    watchpoint_hit = self.write(self.acc, &mut event);
+                }
+            }
+            Some(DecodedInstruction::Call(address)) => {
+                if self.sp == 0 {
+                    event.halt_reason = Some(HaltReason::StackOverflow { addr: event.pc });
+                } else {
+                    self.sp -= 1;
+                    self.mar = self.sp;
+                    watchpoint_hit = self.write(self.pc, &mut event);
+                    self.pc = address;
+                    event.branch = Some(true);
+                }
+            }
+            Some(DecodedInstruction::Pop | DecodedInstruction::Ret) if self.sp as usize >= N => {
+                event.halt_reason = Some(HaltReason::StackUnderflow { addr: event.pc });
+            }
+            Some(DecodedInstruction::Pop) => {
+                self.mar = self.sp;
+                self.sp += 1;
+                let read = self.read();
+                self.acc = read.value;
+                self.negative = false;
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, read.value);
+                event.read = Some(read);
+            }
+            Some(DecodedInstruction::Ret) => {
+                self.mar = self.sp;
+                self.sp += 1;
+                let read = self.read();
+                self.pc = read.value;
+                event.branch = Some(true);
+                watchpoint_hit = self.watchpoint_hit(event.pc, Access::Read, read.value);
+                event.read = Some(read);
+            }
+            Some(DecodedInstruction::Rti) => match self.interrupt_return.take() {
+                Some(address) => {
+                    self.pc = address;
+                    event.branch = Some(true);
+                }
+                None => {
+                    event.halt_reason = Some(HaltReason::ReturnWithoutInterrupt { addr: event.pc })
+                }
+            },
+            Some(DecodedInstruction::LdaIndirect(_) | DecodedInstruction::StaIndirect(_)) => {
+                unreachable!("indirect instructions are resolved before this")
+            }
+            None => {
+                event.halt_reason = Some(HaltReason::InvalidInstruction {
+                    word: self.cir,
+                    addr: self.mar,
+                })
+            }
+        };
+
+        if let Some(output) = event.output {
+            if self
+                .config
+                .max_outputs
+                .is_some_and(|max_outputs| self.outputs_produced >= max_outputs)
+            {
+                event.output = None;
+                event.halt_reason = Some(HaltReason::OutputLimit);
+            } else {
+                self.outputs_produced += 1;
+                if let Err(error) = io_handler.try_print_output(output) {
+                    event.halt_reason = Some(HaltReason::IoError(error.to_string()));
+                }
+            }
+        }
+
+        if self.pc as usize >= N && self.config.pc_overflow == PcOverflow::Wrap {
+            self.pc = 0;
+        }
+
+        if let Some(timer) = &self.config.timer {
+            let due = timer.period > 0 && self.steps_executed.is_multiple_of(timer.period);
+            if due
+                && event.halt_reason.is_none()
+                && self.interrupt_return.is_none()
+                && (self.pc as usize) < N
+            {
+                self.interrupt_return = Some(self.pc);
+                self.pc = timer.vector;
+                event.interrupted = true;
+            }
+        }
+
+        let (cir, mar, mdr, acc, sp, interrupt_return, negative, overflow) = registers;
+        event.registers = [
+            (Register::Acc, acc, self.acc),
+            (Register::Pc, event.pc, self.pc),
+            (Register::Mar, mar, self.mar),
+            (Register::Mdr, mdr, self.mdr),
+            (Register::Cir, cir, self.cir),
+            (Register::Sp, sp, self.sp),
+        ]
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(register, old, new)| RegisterChange { register, old, new })
+        .collect();
+
+        if let Some(profile) = &mut self.profile {
+            profile.record(&event);
+        }
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.record(&event);
+        }
+
+        if let Some(history) = &mut self.history {
+            let first_write = event.write.as_ref().is_some_and(|write| {
+                initialized.is_some_and(|initialized| {
+                    initialized.get(write.address as usize) == Some(&false)
+                })
+            });
+            history.push(Undo {
+                pc: event.pc,
+                cir,
+                mar,
+                mdr,
+                acc,
+                sp,
+                interrupt_return,
+                negative,
+                overflow,
+                write: event.write.clone(),
+                input: event.input,
+                output: event.output,
+                cycles,
+                instruction: event.instruction,
+                read: event.read.as_ref().map(|read| read.address),
+                first_execution,
+                first_write,
+                in_profile: self.profile.is_some(),
+                in_heatmap: self.heatmap.is_some(),
+                in_trace: false,
+            });
+        }
+
+        if event.halt_reason.is_none() {
+            event.halt_reason = self.pause_reason(watchpoint_hit)?;
+        }
+
+        if self.trace.is_some() {
+            let step = TraceStep {
+                acc: self.acc,
+                mar: self.mar,
+                mdr: self.mdr,
+                next_pc: self.pc,
+                event: event.clone(),
+            };
+            if let Some(trace) = &mut self.trace {
+                trace.steps.push(step);
+            }
+            if let Some(undo) = self.history.as_mut().and_then(|history| history.last_mut()) {
+                undo.in_trace = true;
+            }
+        }
+
+        Ok(event)
+    }
+
+    /// Like `step`, but never asks an IO handler for anything. If the next instruction
+    /// wants input that hasn't been given with `provide_input`, nothing is executed and
+    /// `StepOutcome::NeedsInput` is returned. Output is only reported in the event.
+    pub fn step_nonblocking(&mut self) -> Result<StepOutcome, RuntimeError> {
+        if self.awaiting_input() {
+            return Ok(StepOutcome::NeedsInput);
+        }
+        self.step(&mut io::NoIO).map(StepOutcome::Stepped)
+    }
+
+    /// Queues a value for INP (or a character code for INA), after any already queued.
+    pub fn provide_input(&mut self, value: i16) {
+        self.replay_inputs.insert(0, value);
+    }
+
+    /// Whether the next instruction is INP or INA with no input queued for it.
+    pub fn awaiting_input(&self) -> bool {
+        self.replay_inputs.is_empty()
+            && self
+                .ram
+                .get(self.pc as usize)
+                .and_then(|word| {
+                    DecodedInstruction::decode_with(*word, N as i16, &self.config.extensions).ok()
+                })
+                .is_some_and(|instruction| {
+                    matches!(
+                        instruction,
+                        DecodedInstruction::Inp | DecodedInstruction::Ina
+                    )
+                })
+    }
+
+    /// Steps the machine lazily. The iterator ends after the event that halts (or pauses)
+    /// the machine, or after the first error.
+    pub fn steps<'a, T: LMCIO>(&'a mut self, io_handler: &'a mut T) -> Steps<'a, T, N> {
+        Steps {
+            state: self,
+            io_handler,
+            done: false,
+        }
+    }
+
+    /// Stores an ADD/SUB/MUL result in ACC, applying the overflow policy if it's out of range.
+    fn set_acc_checked(&mut self, result: i32, event: &mut StepEvent) {
+        let max = i32::from(Self::MAX_VALUE);
+        self.overflow = !(-max..=max).contains(&result);
+
+        if !self.overflow {
+            self.acc = result as i16;
+            return;
+        }
+
+        event.overflow = Some(result);
+        match self.config.overflow {
+            OverflowPolicy::Wrap => {
+                self.acc = ((result + max).rem_euclid(2 * max + 1) - max) as i16
+            }
+            OverflowPolicy::Clamp => self.acc = result.clamp(-max, max) as i16,
+            OverflowPolicy::Flag => self.acc = (result % (max + 1)) as i16,
+            OverflowPolicy::Error => {
+                event.halt_reason = Some(HaltReason::Overflow {
+                    addr: event.pc,
+                    result,
+                })
+            }
+        }
+    }
+
+    /// Reads the address stored at `pointer`, stopping the machine if it isn't a mailbox.
+    fn follow_pointer(&mut self, pointer: i16, event: &mut StepEvent) -> Option<i16> {
+        self.check_initialized(pointer, event);
+        self.mar = pointer;
+        self.mdr = self.ram[self.mar as usize];
+
+        if (0..N as i16).contains(&self.mdr) {
+            Some(self.mdr)
+        } else {
+            event.halt_reason = Some(HaltReason::InvalidAddress {
+                addr: event.pc,
+                address: self.mdr,
+            });
+            None
+        }
+    }
+
+    /// Applies `Config::uninitialized_reads` to a read of `address`.
+    fn check_initialized(&self, address: i16, event: &mut StepEvent) {
+        if self.initialized.get(address as usize) != Some(&false) {
+            return;
+        }
+
+        match self.config.uninitialized_reads {
+            UninitializedReads::Allow => {}
+            UninitializedReads::Warn => event.warnings.push(RuntimeWarning::UninitializedRead {
+                address,
+                pc: event.pc,
+            }),
+            UninitializedReads::Error => {
+                event.halt_reason = Some(HaltReason::UninitializedRead {
+                    addr: event.pc,
+                    address,
+                })
+            }
+        }
+    }
+
+    /// Under `Config::strict_values`, stops the machine if `address` holds a word that
+    /// isn't a valid value.
+    fn check_value(&self, address: i16, event: &mut StepEvent) {
+        let value = self.ram[address as usize];
+        if self.config.strict_values
+            && event.halt_reason.is_none()
+            && !(-Self::MAX_VALUE..=Self::MAX_VALUE).contains(&value)
+        {
+            event.halt_reason = Some(HaltReason::ValueOutOfRange {
+                addr: event.pc,
+                address,
+                value,
+            });
+        }
+    }
+
+    fn holds_code(&self, address: i16) -> bool {
+        let address = address as usize;
+        self.executed[address] || self.kinds.get(address) == Some(&CellKind::Code)
+    }
+
+    /// Stores `value` at MAR, noting the write in `event`.
+    fn write(&mut self, value: i16, event: &mut StepEvent) -> Option<WatchpointHit> {
+        let address = self.mar;
+        if self.holds_code(address) {
+            event.warnings.push(RuntimeWarning::SelfModifyingCode {
+                address,
+                pc: event.pc,
+            });
+        }
+
+        let old = self.ram[address as usize];
+        self.set_mailbox(address as usize, value);
+        if let Some(initialized) = self.initialized.get_mut(address as usize) {
+            *initialized = true;
+        }
+        event.write = Some(MemoryWrite {
+            address,
+            old,
+            new: value,
+        });
+
+        self.watchpoint_hit(event.pc, Access::Write, old)
+    }
+
+    fn read(&self) -> MemoryRead {
+        MemoryRead {
+            address: self.mar,
+            value: self.ram[self.mar as usize],
+        }
+    }
+
+    /// Applies `Config::end_of_input` when INP or INA finds there's no input left.
+    fn end_of_input(&self, event: &mut StepEvent) -> Result<(), RuntimeError> {
+        match self.config.end_of_input {
+            EndOfInput::Halt => {
+                event.halt_reason = Some(HaltReason::EndOfInput { addr: event.pc });
+                Ok(())
+            }
+            EndOfInput::Error => Err(RuntimeError::EndOfInput { pc: event.pc }),
+        }
+    }
+
+    /// Checks whether the machine should stop after an instruction that didn't halt it.
+    fn pause_reason(
+        &self,
+        watchpoint_hit: Option<WatchpointHit>,
+    ) -> Result<Option<HaltReason>, RuntimeError> {
+        if self.pc as usize >= N {
+            return match self.config.pc_overflow {
+                PcOverflow::Error => Err(RuntimeError::PcOutOfRange { pc: self.pc }),
+                _ => Ok(Some(HaltReason::PcOutOfRange)),
+            };
+        }
+
+        if let Some(watchpoint_hit) = watchpoint_hit {
+            return Ok(Some(HaltReason::Watchpoint(watchpoint_hit)));
+        }
+
+        for condition in &self.breakpoints {
+            let value = condition
+                .eval(self)
+                .map_err(|message| RuntimeError::Breakpoint {
+                    condition: condition.clone(),
+                    message,
+                })?;
+            if value != 0 {
+                return Ok(Some(HaltReason::Breakpoint(condition.clone())));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+pub struct Steps<'a, T: LMCIO, const N: usize = 100> {
+    state: &'a mut ExecutionState<N>,
+    io_handler: &'a mut T,
+    done: bool,
+}
+
+impl<T: LMCIO, const N: usize> Iterator for Steps<'_, T, N> {
+    type Item = Result<StepEvent, RuntimeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.state.step(self.io_handler);
+        self.done = match &result {
+            Ok(event) => event.halt_reason.is_some(),
+            Err(_) => true,
+        };
+
+        Some(result)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Char(char),
+    Int(i16),
+}
+
+pub trait LMCIO {
+    fn get_input(&mut self) -> i16;
+    fn print_output(&mut self, val: Output);
+
+    /// Reads a character for INA. By default this asks `get_input` for a character code.
+    fn get_char_input(&mut self) -> char {
+        self.get_input() as u8 as char
+    }
+
+    /// What the machine actually calls for INP. Handlers that can fail should override the
+    /// `try_` methods; an error stops the machine with `HaltReason::IoError`, and running
+    /// out of input is handled according to `Config::end_of_input`.
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        Ok(InputResponse::Value(self.get_input()))
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(InputResponse::Value(self.get_char_input()))
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        self.print_output(val);
+        Ok(())
+    }
+
+    /// Told the address of each instruction just before it's executed, for handlers that
+    /// keep track of where IO happened.
+    fn set_pc(&mut self, _pc: i16) {}
+}
+
+impl<T: LMCIO + ?Sized> LMCIO for Box<T> {
+    fn get_input(&mut self) -> i16 {
+        (**self).get_input()
+    }
+
+    fn print_output(&mut self, val: Output) {
+        (**self).print_output(val)
+    }
+
+    fn get_char_input(&mut self) -> char {
+        (**self).get_char_input()
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        (**self).try_get_input()
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        (**self).try_get_char_input()
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        (**self).try_print_output(val)
+    }
+
+    fn set_pc(&mut self, pc: i16) {
+        (**self).set_pc(pc)
+    }
+}
+
+/// Reads inputs from stdin after a `> ` prompt and prints outputs to stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultIO;
+
+/// Like `DefaultIO`, but without the `> ` prompt, so that stdout holds nothing but the
+/// program's outputs and can be compared against an expected-output file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuietIO;
+
+/// The handler behind `DefaultIO` and `QuietIO`, which can also read from and write to
+/// other streams than stdin and stdout.
+pub struct StreamIO {
+    prompt: Option<&'static str>,
+    /// Used instead of stdin and stdout, if set.
+    streams: Option<(Box<dyn BufRead + Send>, Box<dyn Write + Send>)>,
+}
+
+impl StreamIO {
+    /// Prompts on stdout and reads from stdin, like `DefaultIO`.
+    pub fn stdio() -> Self {
+        StreamIO {
+            prompt: Some("> "),
+            streams: None,
+        }
+    }
+
+    /// Reads from `input` and writes to `output` instead of stdin and stdout.
+    pub fn new(input: impl BufRead + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        StreamIO {
+            prompt: Some("> "),
+            streams: Some((Box::new(input), Box::new(output))),
+        }
+    }
+
+    /// Doesn't prompt for input.
+    pub fn quiet(self) -> Self {
+        StreamIO {
+            prompt: None,
+            ..self
+        }
+    }
+
+    fn write(
+        &mut self,
+        f: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    ) -> Result<(), IoError> {
+        match &mut self.streams {
+            Some((_, output)) => f(output)?,
+            None => f(&mut std::io::stdout())?,
+        }
+        Ok(())
+    }
+
+    /// Reads a line, after the prompt if there is one, or `None` once input is closed.
+    fn read_line(&mut self) -> Result<Option<String>, IoError> {
+        if let Some(prompt) = self.prompt {
+            self.write(|output| {
+                write!(output, "{}", prompt)?;
+                output.flush()
+            })?;
+        }
+        let mut input = String::new();
+        let read = match &mut self.streams {
+            Some((stream, _)) => stream.read_line(&mut input)?,
+            None => std::io::stdin().read_line(&mut input)?,
+        };
+        match read {
+            0 => Ok(None),
+            _ => Ok(Some(input)),
+        }
+    }
+}
+
+impl LMCIO for StreamIO {
+    fn get_input(&mut self) -> i16 {
+        match self.try_get_input() {
+            Ok(InputResponse::Value(input)) => input,
+            Ok(InputResponse::Eof) => panic!("Unexpected end of input"),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn get_char_input(&mut self) -> char {
+        match self.try_get_char_input() {
+            Ok(InputResponse::Value(c)) => c,
+            Ok(InputResponse::Eof) => panic!("Unexpected end of input"),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.try_print_output(val)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        let Some(input) = self.read_line()? else {
+            return Ok(InputResponse::Eof);
+        };
+        input
+            .trim()
+            .parse::<i16>()
+            .map(InputResponse::Value)
+            .map_err(|_| IoError::InvalidInput(input.trim().to_string()))
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        Ok(match self.read_line()? {
+            Some(input) => InputResponse::Value(input.chars().next().unwrap_or('\n')),
+            None => InputResponse::Eof,
+        })
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        self.write(|output| match val {
+            Output::Char(c) => write!(output, "{}", c),
+            Output::Int(i) => writeln!(output, "{}", i),
+        })
+    }
+}
+
+impl LMCIO for DefaultIO {
+    fn get_input(&mut self) -> i16 {
+        StreamIO::stdio().get_input()
+    }
+
+    fn get_char_input(&mut self) -> char {
+        StreamIO::stdio().get_char_input()
+    }
+
+    fn print_output(&mut self, val: Output) {
+        StreamIO::stdio().print_output(val)
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        StreamIO::stdio().try_get_input()
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        StreamIO::stdio().try_get_char_input()
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        StreamIO::stdio().try_print_output(val)
+    }
+}
+
+impl LMCIO for QuietIO {
+    fn get_input(&mut self) -> i16 {
+        StreamIO::stdio().quiet().get_input()
+    }
+
+    fn get_char_input(&mut self) -> char {
+        StreamIO::stdio().quiet().get_char_input()
+    }
+
+    fn print_output(&mut self, val: Output) {
+        StreamIO::stdio().quiet().print_output(val)
+    }
+
+    fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+        StreamIO::stdio().quiet().try_get_input()
+    }
+
+    fn try_get_char_input(&mut self) -> Result<InputResponse<char>, IoError> {
+        StreamIO::stdio().quiet().try_get_char_input()
+    }
+
+    fn try_print_output(&mut self, val: Output) -> Result<(), IoError> {
+        StreamIO::stdio().quiet().try_print_output(val)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum HaltReason {
+    /// The program executed HLT.
+    Halted,
+    /// The program counter ran past the last mailbox.
+    PcOutOfRange,
+    /// The run was stopped after `Config::max_steps` steps.
+    StepLimit,
+    /// The run was stopped after running for longer than `Config::timeout`.
+    Timeout,
+    /// An OUT or OTC would have gone over `Config::max_outputs`. The value wasn't output.
+    OutputLimit,
+    /// The IO handler failed, or gave the machine something it couldn't accept.
+    IoError(String),
+    /// INP at `addr` was given `value`, which is outside -999..=999.
+    InputOutOfRange { addr: i16, value: i16 },
+    /// The word at `addr` doesn't encode an instruction.
+    InvalidInstruction { word: i16, addr: i16 },
+    /// Paused after an instruction touched a watched mailbox. Execution can be resumed.
+    Watchpoint(WatchpointHit),
+    /// Paused because a breakpoint condition became true. Execution can be resumed.
+    Breakpoint(Expr),
+    /// An ADD, SUB or MUL at `addr` went out of range under `OverflowPolicy::Error`.
+    Overflow { addr: i16, result: i32 },
+    /// RTI at `addr` was executed outside of an interrupt handler.
+    ReturnWithoutInterrupt { addr: i16 },
+    /// DIV at `addr` tried to divide by zero.
+    DivisionByZero { addr: i16 },
+    /// PUSH or CALL at `addr` ran out of memory for the stack.
+    StackOverflow { addr: i16 },
+    /// POP or RET at `addr` found the stack empty.
+    StackUnderflow { addr: i16 },
+    /// The pointer used by an indirect instruction at `addr` held `address`, which isn't
+    /// a mailbox.
+    InvalidAddress { addr: i16, address: i16 },
+    /// The instruction at `addr` would have read an uninitialized mailbox, under
+    /// `UninitializedReads::Error`.
+    UninitializedRead { addr: i16, address: i16 },
+    /// INP or INA at `addr` found no input left, under `EndOfInput::Halt`.
+    EndOfInput { addr: i16 },
+    /// The instruction at `addr` would have read `value` from `address`, which is outside
+    /// the range of values the machine can hold, under `Config::strict_values`.
+    ValueOutOfRange { addr: i16, address: i16, value: i16 },
+}
+
+impl HaltReason {
+    /// Whether the machine only paused (at a watchpoint or breakpoint) and can carry on.
+    pub fn is_pause(&self) -> bool {
+        matches!(self, HaltReason::Watchpoint(_) | HaltReason::Breakpoint(_))
+    }
+}
+
+pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+/// How many steps pass between checks of `Config::timeout`, since reading the clock on
+/// every step would slow tight loops down.
+pub const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Maximum number of instructions to execute before giving up, or `None` for no limit.
+    pub max_steps: Option<u64>,
+    /// Maximum wall-clock time to run for, or `None` for no limit. Checked every
+    /// `TIMEOUT_CHECK_INTERVAL` steps, so a run can go slightly over. Not saved with the
+    /// rest of the state, since it describes the run rather than the machine.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub timeout: Option<Duration>,
+    /// Maximum number of values to output, or `None` for no limit.
+    pub max_outputs: Option<u64>,
+    pub brp_mode: BrpMode,
+    pub overflow: OverflowPolicy,
+    pub cycle_costs: CycleCosts,
+    /// What to do when LDA, ADD or SUB reads a mailbox that was never given a value. Only
+    /// checked for states made with `ExecutionState::from_assembly`.
+    pub uninitialized_reads: UninitializedReads,
+    /// What happens when execution runs past mailbox 99.
+    pub pc_overflow: PcOverflow,
+    /// Extension instructions the machine understands, on top of the standard set.
+    pub extensions: Extensions,
+    /// Interrupts the program periodically, if set.
+    pub timer: Option<Timer>,
+    /// What happens when the IO handler runs out of input.
+    pub end_of_input: EndOfInput,
+    /// Decode each mailbox once, the first time it's fetched, instead of on every fetch,
+    /// which speeds up tight loops. A mailbox is decoded again after the program, a
+    /// `StateDiff` or `ExecutionState::set_mailbox` writes to it, so memory shouldn't be
+    /// changed by assigning to `ExecutionState::ram` while this is on.
+    pub predecode: bool,
+    /// Stop with `HaltReason::ValueOutOfRange` rather than load a word outside -999..=999
+    /// (e.g. from a corrupted save) into the accumulator.
+    pub strict_values: bool,
+}
+
+/// A timer interrupt: after every `period` steps, the address of the next instruction is
+/// saved and execution jumps to `vector`. The handler returns with RTI (see
+/// `Extensions::interrupts`). Interrupts that fall due while the handler is running are
+/// dropped.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timer {
+    pub period: u64,
+    pub vector: i16,
+}
+
+impl Config {
+    /// Why a run that started at `start` should stop before its next step, if it has hit
+    /// `max_steps` or `timeout`.
+    pub(crate) fn limit_reached(&self, steps: u64, start: Instant) -> Option<HaltReason> {
+        if self.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+            return Some(HaltReason::StepLimit);
+        }
+        match self.timeout {
+            Some(timeout)
+                if steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && start.elapsed() >= timeout =>
+            {
+                Some(HaltReason::Timeout)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_steps: Some(DEFAULT_MAX_STEPS),
+            timeout: None,
+            max_outputs: None,
+            brp_mode: BrpMode::NegativeFlag,
+            overflow: OverflowPolicy::Wrap,
+            cycle_costs: CycleCosts::default(),
+            uninitialized_reads: UninitializedReads::Allow,
+            pc_overflow: PcOverflow::Halt,
+            extensions: Extensions::default(),
+            timer: None,
+            end_of_input: EndOfInput::Error,
+            strict_values: false,
+            predecode: false,
+        }
+    }
+}
+
+/// How many cycles each class of instruction costs. By default everything costs one, so
+/// `ExecutionState::cycles` is the same as the number of instructions executed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CycleCosts {
+    /// LDA and STA (direct or indirect), PUSH and POP.
+    pub memory: u64,
+    /// ADD, SUB, MUL and DIV.
+    pub arithmetic: u64,
+    /// BRA, BRZ and BRP, whether or not the branch is taken, and CALL, RET and RTI.
+    pub branch: u64,
+    /// INP, INA, OUT and OTC.
+    pub io: u64,
+    pub halt: u64,
+}
+
+impl Default for CycleCosts {
+    fn default() -> Self {
+        CycleCosts {
+            memory: 1,
+            arithmetic: 1,
+            branch: 1,
+            io: 1,
+            halt: 1,
+        }
+    }
+}
+
+impl CycleCosts {
+    pub fn cost(&self, instruction: &DecodedInstruction) -> u64 {
+        match instruction {
+            DecodedInstruction::Lda(_)
+            | DecodedInstruction::Sta(_)
+            | DecodedInstruction::LdaIndirect(_)
+            | DecodedInstruction::StaIndirect(_)
+            | DecodedInstruction::Push
+            | DecodedInstruction::Pop => self.memory,
+            DecodedInstruction::Add(_)
+            | DecodedInstruction::Sub(_)
+            | DecodedInstruction::Mul(_)
+            | DecodedInstruction::Div(_) => self.arithmetic,
+            DecodedInstruction::Bra(_)
+            | DecodedInstruction::Brz(_)
+            | DecodedInstruction::Brp(_)
+            | DecodedInstruction::Call(_)
+            | DecodedInstruction::Ret
+            | DecodedInstruction::Rti => self.branch,
+            DecodedInstruction::Inp
+            | DecodedInstruction::Ina
+            | DecodedInstruction::Out
+            | DecodedInstruction::Otc => self.io,
+            DecodedInstruction::Hlt => self.halt,
+        }
+    }
+}
+
+/// What ADD, SUB and MUL do when the result doesn't fit in -999..=999.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    /// Wrap around to the other end of the range, so 999 + 1 = -999.
+    Wrap,
+    /// Stick at -999 or 999.
+    Clamp,
+    /// Keep the last three digits, like a real three-digit register (999 + 2 = 1).
+    Flag,
+    /// Stop with `HaltReason::Overflow`.
+    Error,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UninitializedReads {
+    /// Read the mailbox as 0, like an unchecked LMC.
+    Allow,
+    /// Read it anyway, but add `RuntimeWarning::UninitializedRead` to the step's warnings.
+    Warn,
+    /// Stop with `HaltReason::UninitializedRead` before executing the instruction.
+    Error,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PcOverflow {
+    /// Stop with `HaltReason::PcOutOfRange`.
+    Halt,
+    /// Carry on from mailbox 0.
+    Wrap,
+    /// Fail the step (and the run) with an error.
+    Error,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndOfInput {
+    /// Stop with `HaltReason::EndOfInput`, e.g. for a filter reading piped input until EOF.
+    Halt,
+    /// Fail the step (and the run) with an error.
+    Error,
+}
+
+/// How BRP decides whether the accumulator is positive.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrpMode {
+    /// Branch unless the last SUB went negative, as on a real LMC.
+    NegativeFlag,
+    /// Branch if the (signed) accumulator is zero or more. This is how BRP behaved before
+    /// the negative flag was added.
+    SignedAccumulator,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug)]
+pub struct RunResult<const N: usize = 100> {
+    pub state: ExecutionState<N>,
+    pub halt_reason: HaltReason,
+    pub steps: u64,
+    /// What the run did, counting only the steps taken by this call.
+    pub stats: ExecutionStats,
+}
+
+pub fn run<T: LMCIO>(
+    program: [i16; 100],
+    io_handler: &mut T,
+    debug_mode: bool,
+) -> Result<RunResult, RuntimeError> {
+    run_with_config(program, io_handler, debug_mode, Config::default())
+}
+
+/// Runs a program on the given inputs (in order) and returns everything it output. Running
+/// out of inputs is an error, as with `BufferedIO`.
+pub fn run_capture(program: [i16; 100], inputs: &[i16]) -> Result<Vec<Output>, RuntimeError> {
+    let mut io_handler = io::BufferedIO::new(inputs.iter().copied());
+    run(program, &mut io_handler, false)?;
+    Ok(io_handler.take_outputs())
+}
+
+pub fn run_with_config<T: LMCIO>(
+    program: [i16; 100],
+    io_handler: &mut T,
+    debug_mode: bool,
+    config: Config,
+) -> Result<RunResult, RuntimeError> {
+    run_with_state(
+        ExecutionState::with_config(program, config),
+        io_handler,
+        debug_mode,
+    )
+}
+
+/// Runs from whatever point `state` is at, e.g. a session restored from a save or one that
+/// was paused at a breakpoint. `RunResult::steps` only counts the steps taken by this call.
+pub fn run_with_state<T: LMCIO, const N: usize>(
+    state: ExecutionState<N>,
+    io_handler: &mut T,
+    debug_mode: bool,
+) -> Result<RunResult<N>, RuntimeError> {
+    if debug_mode {
+        run_observed(state, io_handler, &mut observer::RegisterDump)
+    } else {
+        run_observed(state, io_handler, &mut ())
+    }
+}
+
+/// Like `run_with_state`, but calls `observer` around every step.
+pub fn run_observed<T: LMCIO, O: ExecutionObserver<N> + ?Sized, const N: usize>(
+    mut state: ExecutionState<N>,
+    io_handler: &mut T,
+    observer: &mut O,
+) -> Result<RunResult<N>, RuntimeError> {
+    let mut stats = ExecutionStats::new(N);
+    let (halt_reason, steps, elapsed) =
+        run_in_place(&mut state, io_handler, &mut (&mut stats, observer))?;
+    stats.elapsed = elapsed;
+
+    Ok(RunResult {
+        state,
+        halt_reason,
+        steps,
+        stats,
+    })
+}
+
+/// The run loop, returning why the machine stopped, how many steps it took and how long.
+fn run_in_place<T: LMCIO, O: ExecutionObserver<N> + ?Sized, const N: usize>(
+    state: &mut ExecutionState<N>,
+    io_handler: &mut T,
+    observer: &mut O,
+) -> Result<(HaltReason, u64, Duration), RuntimeError> {
+    let mut steps = 0;
+    let start = Instant::now();
+
+    let halt_reason = loop {
+        if let Some(halt_reason) = state.config.limit_reached(steps, start) {
+            break halt_reason;
+        }
+
+        observer.before_step(state);
+        let event = state.step(io_handler)?;
+        steps += 1;
+        observer.after_step(state, &event);
+        if let Some(write) = &event.write {
+            observer.on_write(write.address, write.old, write.new);
+        }
+
+        if let Some(halt_reason) = event.halt_reason {
+            break halt_reason;
+        }
+    };
+
+    Ok((halt_reason, steps, start.elapsed()))
 }
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Diagnostic, DiagnosticCode, Instruction, Label, Operand, Program, SymbolTable};
+
+/// Which warnings `lint_with` reports. Duplicate labels are always reported, since they're
+/// errors.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LintRules {
+    pub unused_labels: bool,
+    /// Instructions that can't be reached from mailbox 0 by falling through or branching.
+    pub unreachable_code: bool,
+    /// Branches (and CALLs) to a DAT.
+    pub branch_to_data: bool,
+    pub missing_hlt: bool,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        LintRules {
+            unused_labels: true,
+            unreachable_code: true,
+            branch_to_data: true,
+            missing_hlt: true,
+        }
+    }
+}
+
+/// Checks a parsed program for duplicate labels (errors) and likely mistakes (warnings).
+pub fn lint(program: &Program) -> Vec<Diagnostic> {
+    lint_with(program, &LintRules::default())
+}
+
+/// Like `lint`, but only with the warnings that are turned on in `rules`.
+pub fn lint_with(program: &Program, rules: &LintRules) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut definitions = HashSet::new();
+
+    for (address, (label, _)) in program.iter().enumerate() {
+        if let Label::LBL(name) = label {
+            if !definitions.insert(name) {
+                diagnostics.push(
+                    Diagnostic::error(
+                        DiagnosticCode::DuplicateLabel,
+                        format!("Duplicate label... {}", name),
+                    )
+                    .about(name)
+                    .at_address(address),
+                );
+            }
+        }
+    }
+
+    let used: Vec<&str> = program
+        .iter()
+        .filter_map(|(_, instruction)| instruction.operand().and_then(Operand::label))
+        .collect();
+
+    for (address, (label, _)) in program.iter().enumerate() {
+        if let Label::LBL(name) = label {
+            if rules.unused_labels && !used.contains(&name.as_str()) {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        DiagnosticCode::UnusedLabel,
+                        format!("Unused label... {}", name),
+                    )
+                    .about(name)
+                    .at_address(address),
+                );
+            }
+        }
+    }
+
+    let symbols = SymbolTable::from_program(program);
+    let target = |operand: &Operand| match operand {
+        Operand::Value(address) => Some(*address as usize),
+        Operand::Label(name) => symbols.get(name).map(|address| address as usize),
+        Operand::Indirect(_) => None,
+    };
+
+    if rules.branch_to_data {
+        for (address, (_, instruction)) in program.iter().enumerate() {
+            let Some(operand) = branch_operand(instruction) else {
+                continue;
+            };
+            let is_data = target(operand)
+                .and_then(|target| program.get(target))
+                .is_some_and(|(_, target)| matches!(target, Instruction::DAT(_)));
+            if is_data {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        DiagnosticCode::BranchToData,
+                        format!("Branch to data... {}", operand),
+                    )
+                    .about(operand.to_string())
+                    .at_address(address),
+                );
+            }
+        }
+    }
+
+    if rules.unreachable_code && !program.is_empty() {
+        let mut reachable = vec![false; program.len()];
+        let mut pending = vec![0];
+        while let Some(address) = pending.pop() {
+            match reachable.get_mut(address) {
+                Some(seen) if !*seen => *seen = true,
+                _ => continue,
+            }
+
+            let instruction = &program[address].1;
+            let falls_through = !matches!(
+                instruction,
+                Instruction::HLT
+                    | Instruction::BRA(_)
+                    | Instruction::RET
+                    | Instruction::RTI
+                    | Instruction::DAT(_)
+            );
+            if falls_through {
+                pending.push(address + 1);
+            }
+            pending.extend(branch_operand(instruction).and_then(target));
+        }
+
+        // one warning for each run of unreachable instructions
+        let unreachable = |address: usize| {
+            !reachable[address] && !matches!(program[address].1, Instruction::DAT(_))
+        };
+        for address in 0..program.len() {
+            if unreachable(address) && (address == 0 || !unreachable(address - 1)) {
+                diagnostics.push(
+                    Diagnostic::warning(DiagnosticCode::UnreachableCode, "Unreachable code")
+                        .at_address(address),
+                );
+            }
+        }
+    }
+
+    if rules.missing_hlt
+        && !program.is_empty()
+        && !program
+            .iter()
+            .any(|(_, instruction)| matches!(instruction, Instruction::HLT))
+    {
+        diagnostics.push(Diagnostic::warning(
+            DiagnosticCode::MissingHlt,
+            "Program has no HLT instruction",
+        ));
+    }
+
+    diagnostics
+}
+
+pub(crate) fn branch_operand(instruction: &Instruction) -> Option<&Operand> {
+    match instruction {
+        Instruction::BRA(operand)
+        | Instruction::BRZ(operand)
+        | Instruction::BRP(operand)
+        | Instruction::CALL(operand) => Some(operand),
+        _ => None,
+    }
+}
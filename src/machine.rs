@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use crate::{
+    run_in_place, BrpMode, Config, CycleCosts, DefaultIO, ExecutionObserver, ExecutionState,
+    Extensions, HaltReason, OverflowPolicy, PcOverflow, RuntimeError, StepEvent, Timer,
+    UninitializedReads, LMCIO,
+};
+
+/// A machine together with the IO handler it runs against, made with `LMC::builder()`.
+pub struct LMC<T: LMCIO = DefaultIO> {
+    pub state: ExecutionState,
+    pub io_handler: T,
+}
+
+impl LMC {
+    pub fn builder() -> LMCBuilder {
+        LMCBuilder::default()
+    }
+}
+
+impl<T: LMCIO> LMC<T> {
+    pub fn step(&mut self) -> Result<StepEvent, RuntimeError> {
+        self.state.step(&mut self.io_handler)
+    }
+
+    /// Runs until the machine stops, returning why.
+    pub fn run(&mut self) -> Result<HaltReason, RuntimeError> {
+        self.run_observed(&mut ())
+    }
+
+    pub fn run_observed<O: ExecutionObserver + ?Sized>(
+        &mut self,
+        observer: &mut O,
+    ) -> Result<HaltReason, RuntimeError> {
+        run_in_place(&mut self.state, &mut self.io_handler, observer)
+            .map(|(halt_reason, ..)| halt_reason)
+    }
+
+    pub fn into_parts(self) -> (ExecutionState, T) {
+        (self.state, self.io_handler)
+    }
+}
+
+/// Configures an `LMC`. Anything not set keeps its `Config::default()` value, memory
+/// starts zeroed and IO goes to the terminal.
+pub struct LMCBuilder<T: LMCIO = DefaultIO> {
+    memory: [i16; 100],
+    config: Config,
+    io_handler: T,
+}
+
+impl Default for LMCBuilder {
+    fn default() -> Self {
+        LMCBuilder {
+            memory: [0; 100],
+            config: Config::default(),
+            io_handler: DefaultIO,
+        }
+    }
+}
+
+impl<T: LMCIO> LMCBuilder<T> {
+    pub fn memory(mut self, image: [i16; 100]) -> Self {
+        self.memory = image;
+        self
+    }
+
+    /// Replaces every option set so far.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.config.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn unlimited_steps(mut self) -> Self {
+        self.config.max_steps = None;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_outputs(mut self, max_outputs: u64) -> Self {
+        self.config.max_outputs = Some(max_outputs);
+        self
+    }
+
+    pub fn brp_mode(mut self, brp_mode: BrpMode) -> Self {
+        self.config.brp_mode = brp_mode;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.config.overflow = overflow;
+        self
+    }
+
+    pub fn cycle_costs(mut self, cycle_costs: CycleCosts) -> Self {
+        self.config.cycle_costs = cycle_costs;
+        self
+    }
+
+    pub fn uninitialized_reads(mut self, uninitialized_reads: UninitializedReads) -> Self {
+        self.config.uninitialized_reads = uninitialized_reads;
+        self
+    }
+
+    pub fn pc_overflow(mut self, pc_overflow: PcOverflow) -> Self {
+        self.config.pc_overflow = pc_overflow;
+        self
+    }
+
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.config.extensions = extensions;
+        self
+    }
+
+    pub fn timer(mut self, timer: Timer) -> Self {
+        self.config.timer = Some(timer);
+        self
+    }
+
+    /// See `Config::predecode`.
+    pub fn predecode(mut self) -> Self {
+        self.config.predecode = true;
+        self
+    }
+
+    pub fn io<U: LMCIO>(self, io_handler: U) -> LMCBuilder<U> {
+        LMCBuilder {
+            memory: self.memory,
+            config: self.config,
+            io_handler,
+        }
+    }
+
+    pub fn build(self) -> LMC<T> {
+        LMC {
+            state: ExecutionState::with_config(self.memory, self.config),
+            io_handler: self.io_handler,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{ExecutionState, ExecutionStats, Heatmap, Profile, StepEvent};
+
+/// Watches a machine being run by `run_observed`, without having to drive the loop.
+///
+/// Both methods do nothing by default. Observers can be combined by passing a tuple.
+pub trait ExecutionObserver<const N: usize = 100> {
+    /// Called before each instruction is fetched.
+    fn before_step(&mut self, _state: &ExecutionState<N>) {}
+
+    /// Called after each instruction, including the one that halted the machine.
+    fn after_step(&mut self, _state: &ExecutionState<N>, _event: &StepEvent) {}
+
+    /// Called after `after_step` when the instruction stored to a mailbox, so a frontend can
+    /// redraw just that cell.
+    fn on_write(&mut self, _address: i16, _old: i16, _new: i16) {}
+}
+
+impl<const N: usize> ExecutionObserver<N> for () {}
+
+impl<A: ExecutionObserver<N>, B: ExecutionObserver<N>, const N: usize> ExecutionObserver<N>
+    for (A, B)
+{
+    fn before_step(&mut self, state: &ExecutionState<N>) {
+        self.0.before_step(state);
+        self.1.before_step(state);
+    }
+
+    fn after_step(&mut self, state: &ExecutionState<N>, event: &StepEvent) {
+        self.0.after_step(state, event);
+        self.1.after_step(state, event);
+    }
+
+    fn on_write(&mut self, address: i16, old: i16, new: i16) {
+        self.0.on_write(address, old, new);
+        self.1.on_write(address, old, new);
+    }
+}
+
+impl<O: ExecutionObserver<N> + ?Sized, const N: usize> ExecutionObserver<N> for &mut O {
+    fn before_step(&mut self, state: &ExecutionState<N>) {
+        (**self).before_step(state);
+    }
+
+    fn after_step(&mut self, state: &ExecutionState<N>, event: &StepEvent) {
+        (**self).after_step(state, event);
+    }
+
+    fn on_write(&mut self, address: i16, old: i16, new: i16) {
+        (**self).on_write(address, old, new);
+    }
+}
+
+/// An observer that only cares about memory writes, calling `F(address, old, new)`.
+pub struct OnWrite<F: FnMut(i16, i16, i16)>(pub F);
+
+impl<F: FnMut(i16, i16, i16), const N: usize> ExecutionObserver<N> for OnWrite<F> {
+    fn on_write(&mut self, address: i16, old: i16, new: i16) {
+        (self.0)(address, old, new);
+    }
+}
+
+impl<const N: usize> ExecutionObserver<N> for Profile {
+    fn after_step(&mut self, _state: &ExecutionState<N>, event: &StepEvent) {
+        self.record(event);
+    }
+}
+
+impl<const N: usize> ExecutionObserver<N> for ExecutionStats {
+    fn after_step(&mut self, _state: &ExecutionState<N>, event: &StepEvent) {
+        self.record(event);
+    }
+}
+
+impl<const N: usize> ExecutionObserver<N> for Heatmap {
+    fn after_step(&mut self, _state: &ExecutionState<N>, event: &StepEvent) {
+        self.record(event);
+    }
+}
+
+/// Prints the registers after every instruction that doesn't halt, for `debug_mode`.
+pub(crate) struct RegisterDump;
+
+impl<const N: usize> ExecutionObserver<N> for RegisterDump {
+    fn after_step(&mut self, state: &ExecutionState<N>, event: &StepEvent) {
+        if event.halt_reason.is_some() {
+            return;
+        }
+        println!("PC: {}", state.pc);
+        println!("CIR: {}", state.cir);
+        println!("MAR: {}", state.mar);
+        println!("MDR: {}", state.mdr);
+        println!("ACC: {}", state.acc);
+        println!("RAM: {:?}", state.ram);
+        println!();
+    }
+}
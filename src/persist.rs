@@ -0,0 +1,198 @@
+//! Saving `ExecutionState`s in a format that later versions of the crate can still read.
+
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::ExecutionState;
+
+/// The version `SavedState` writes.
+pub const STATE_VERSION: u32 = 1;
+
+/// An `ExecutionState` wrapped as `{"version": 1, "state": {...}}`, for frontends that
+/// persist sessions.
+///
+/// States saved by older versions are migrated when they're loaded. Version 0 is the
+/// unversioned layout of 0.1.x (just the registers and RAM), so saves from then can be
+/// loaded by wrapping them as `{"version": 0, "state": <old save>}`. Older versions can only be
+/// migrated if `version` comes before `state`, as it does when written by `SavedState`.
+#[derive(Debug, Clone)]
+pub struct SavedState<const N: usize = 100>(pub ExecutionState<N>);
+
+impl<const N: usize> From<ExecutionState<N>> for SavedState<N> {
+    fn from(state: ExecutionState<N>) -> Self {
+        SavedState(state)
+    }
+}
+
+impl<const N: usize> Serialize for SavedState<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut saved = serializer.serialize_struct("SavedState", 2)?;
+        saved.serialize_field("version", &STATE_VERSION)?;
+        saved.serialize_field("state", &self.0)?;
+        saved.end()
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for SavedState<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("SavedState", &["version", "state"], SavedStateVisitor)
+    }
+}
+
+/// The layout of `ExecutionState` before it was versioned.
+#[derive(serde_derive::Deserialize)]
+struct StateV0<const N: usize> {
+    pc: i16,
+    cir: i16,
+    mar: i16,
+    mdr: i16,
+    acc: i16,
+    #[serde(with = "serde_arrays")]
+    ram: [i16; N],
+}
+
+impl<const N: usize> From<StateV0<N>> for ExecutionState<N> {
+    fn from(old: StateV0<N>) -> Self {
+        ExecutionState {
+            pc: old.pc,
+            cir: old.cir,
+            mar: old.mar,
+            mdr: old.mdr,
+            acc: old.acc,
+            ..ExecutionState::new(old.ram)
+        }
+    }
+}
+
+/// Reads the state in the layout used by `version`.
+struct StateSeed<const N: usize> {
+    version: u32,
+}
+
+impl<'de, const N: usize> DeserializeSeed<'de> for StateSeed<N> {
+    type Value = ExecutionState<N>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        match self.version {
+            0 => StateV0::deserialize(deserializer).map(ExecutionState::from),
+            1 => ExecutionState::deserialize(deserializer),
+            version => Err(de::Error::custom(format!(
+                "Unsupported state version... {version} (the newest is {STATE_VERSION})"
+            ))),
+        }
+    }
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+    Version,
+    State,
+}
+
+struct SavedStateVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for SavedStateVisitor<N> {
+    type Value = SavedState<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a saved state with a version")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let version = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let state = seq
+            .next_element_seed(StateSeed { version })?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(SavedState(state))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut version = None;
+        let mut state = None;
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Version if version.is_some() => {
+                    return Err(de::Error::duplicate_field("version"))
+                }
+                Field::Version => version = Some(map.next_value()?),
+                Field::State if state.is_some() => return Err(de::Error::duplicate_field("state")),
+                Field::State => {
+                    // without a version yet, all we can do is hope it's the current one
+                    let version = version.unwrap_or(STATE_VERSION);
+                    state = Some((version, map.next_value_seed(StateSeed { version })?));
+                }
+            }
+        }
+
+        match (version, state) {
+            (Some(version), Some((read_as, state))) if version == read_as => Ok(SavedState(state)),
+            (Some(version), Some(_)) => Err(de::Error::custom(format!(
+                "Saved state is version {version}... it must come before the state to be migrated"
+            ))),
+            (None, _) => Err(de::Error::missing_field("version")),
+            (_, None) => Err(de::Error::missing_field("state")),
+        }
+    }
+}
+
+/// Compact binary encodings, for frontends that send the state somewhere after every step.
+/// States are wrapped in a `SavedState`, so they're versioned just like JSON saves.
+#[cfg(any(feature = "bincode", feature = "msgpack"))]
+impl<const N: usize> ExecutionState<N> {
+    #[cfg(feature = "bincode")]
+    pub fn to_bincode(&self) -> Result<Vec<u8>, String> {
+        bincode::serialize(&SavedState(self.clone()))
+            .map_err(|e| format!("Failed to encode state... {e}"))
+    }
+
+    #[cfg(feature = "bincode")]
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self, String> {
+        bincode::deserialize::<SavedState<N>>(bytes)
+            .map(|saved| saved.0)
+            .map_err(|e| format!("Failed to decode state... {e}"))
+    }
+
+    /// Encodes the state as MessagePack, with field names so it can be read without this crate.
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, String> {
+        rmp_serde::to_vec_named(&SavedState(self.clone()))
+            .map_err(|e| format!("Failed to encode state... {e}"))
+    }
+
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, String> {
+        rmp_serde::from_slice::<SavedState<N>>(bytes)
+            .map(|saved| saved.0)
+            .map_err(|e| format!("Failed to decode state... {e}"))
+    }
+}
+
+#[cfg(any(feature = "bincode", feature = "msgpack"))]
+impl crate::Assembly {
+    #[cfg(feature = "bincode")]
+    pub fn to_bincode(&self) -> Result<Vec<u8>, String> {
+        bincode::serialize(self).map_err(|e| format!("Failed to encode assembly... {e}"))
+    }
+
+    #[cfg(feature = "bincode")]
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self, String> {
+        bincode::deserialize(bytes).map_err(|e| format!("Failed to decode assembly... {e}"))
+    }
+
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, String> {
+        rmp_serde::to_vec_named(self).map_err(|e| format!("Failed to encode assembly... {e}"))
+    }
+
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, String> {
+        rmp_serde::from_slice(bytes).map_err(|e| format!("Failed to decode assembly... {e}"))
+    }
+}
@@ -0,0 +1,275 @@
+use std::{fmt, time::Duration};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Assembly, CellKind, StepEvent, Undo};
+
+/// How often each mailbox was executed, and how often each kind of instruction ran.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Number of times the instruction at each address was executed.
+    pub executions: Vec<u64>,
+    /// Mnemonic → number of times it ran, in the order they were first seen.
+    pub opcodes: Vec<(String, u64)>,
+}
+
+impl Profile {
+    pub fn new(mailboxes: usize) -> Self {
+        Profile {
+            executions: vec![0; mailboxes],
+            opcodes: vec![],
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &StepEvent) {
+        self.executions[event.pc as usize] += 1;
+
+        if let Some(instruction) = event.instruction {
+            count_mnemonic(&mut self.opcodes, instruction.mnemonic());
+        }
+    }
+
+    /// Takes a step that `step_back` undid back out of the counts.
+    pub(crate) fn forget(&mut self, undo: &Undo) {
+        self.executions[undo.pc as usize] -= 1;
+
+        if let Some(instruction) = undo.instruction {
+            let mnemonic = instruction.mnemonic();
+            if let Some(i) = self.opcodes.iter().position(|(name, _)| name == mnemonic) {
+                self.opcodes[i].1 -= 1;
+                // so the order is still the order they were first seen in
+                if self.opcodes[i].1 == 0 {
+                    self.opcodes.remove(i);
+                }
+            }
+        }
+    }
+
+    /// Number of times instructions with this mnemonic ran.
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.opcodes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(mnemonic))
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Executed addresses with their counts, most executed first.
+    pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+        let mut hot_spots: Vec<(usize, u64)> = self
+            .executions
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        hot_spots.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        hot_spots
+    }
+}
+
+fn count_mnemonic(opcodes: &mut Vec<(String, u64)>, mnemonic: &str) {
+    match opcodes.iter_mut().find(|(name, _)| name == mnemonic) {
+        Some((_, count)) => *count += 1,
+        None => opcodes.push((mnemonic.to_string(), 1)),
+    }
+}
+
+/// How often each mailbox was read or written as data. Instruction fetches aren't counted
+/// here; see `Profile::executions`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    pub reads: Vec<u64>,
+    pub writes: Vec<u64>,
+}
+
+impl Heatmap {
+    pub fn new(mailboxes: usize) -> Self {
+        Heatmap {
+            reads: vec![0; mailboxes],
+            writes: vec![0; mailboxes],
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &StepEvent) {
+        if let Some(read) = &event.read {
+            self.reads[read.address as usize] += 1;
+        }
+        if let Some(write) = &event.write {
+            self.writes[write.address as usize] += 1;
+        }
+    }
+
+    /// Takes a step that `step_back` undid back out of the counts.
+    pub(crate) fn forget(&mut self, undo: &Undo) {
+        if let Some(address) = undo.read {
+            self.reads[address as usize] -= 1;
+        }
+        if let Some(write) = &undo.write {
+            self.writes[write.address as usize] -= 1;
+        }
+    }
+
+    /// Total reads and writes of `address`.
+    pub fn accesses(&self, address: usize) -> u64 {
+        self.reads[address] + self.writes[address]
+    }
+}
+
+/// Totals for a whole run, as returned in `RunResult::stats`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionStats {
+    pub steps: u64,
+    /// Mnemonic → number of times it ran, in the order they were first seen.
+    pub instructions: Vec<(String, u64)>,
+    pub branches_taken: u64,
+    pub branches_not_taken: u64,
+    pub inputs: u64,
+    pub outputs: u64,
+    /// The highest address an instruction was executed from.
+    pub peak_pc: i16,
+    /// Whether each mailbox was read or written as data.
+    pub touched: Vec<bool>,
+    /// Wall-clock time the run took. Only set by the run functions, not by `record`.
+    pub elapsed: Duration,
+}
+
+impl ExecutionStats {
+    pub fn new(mailboxes: usize) -> Self {
+        ExecutionStats {
+            steps: 0,
+            instructions: vec![],
+            branches_taken: 0,
+            branches_not_taken: 0,
+            inputs: 0,
+            outputs: 0,
+            peak_pc: 0,
+            touched: vec![false; mailboxes],
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &StepEvent) {
+        self.steps += 1;
+        if let Some(instruction) = event.instruction {
+            count_mnemonic(&mut self.instructions, instruction.mnemonic());
+        }
+        match event.branch {
+            Some(true) => self.branches_taken += 1,
+            Some(false) => self.branches_not_taken += 1,
+            None => {}
+        }
+        self.inputs += u64::from(event.input.is_some());
+        self.outputs += u64::from(event.output.is_some());
+        self.peak_pc = self.peak_pc.max(event.pc);
+
+        let read = event.read.as_ref().map(|read| read.address);
+        let write = event.write.as_ref().map(|write| write.address);
+        for address in read.into_iter().chain(write) {
+            self.touched[address as usize] = true;
+        }
+    }
+
+    /// Number of times instructions with this mnemonic ran.
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.instructions
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(mnemonic))
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Number of distinct mailboxes read or written as data.
+    pub fn addresses_touched(&self) -> usize {
+        self.touched.iter().filter(|touched| **touched).count()
+    }
+}
+
+/// A summary for printing after a run.
+impl fmt::Display for ExecutionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instructions: Vec<String> = self
+            .instructions
+            .iter()
+            .map(|(mnemonic, count)| format!("{} {}", mnemonic, count))
+            .collect();
+
+        writeln!(f, "steps: {} in {:.3?}", self.steps, self.elapsed)?;
+        writeln!(f, "instructions: {}", instructions.join(", "))?;
+        writeln!(
+            f,
+            "branches: {} taken, {} not taken",
+            self.branches_taken, self.branches_not_taken
+        )?;
+        writeln!(f, "inputs: {}, outputs: {}", self.inputs, self.outputs)?;
+        writeln!(f, "peak pc: {}", self.peak_pc)?;
+        writeln!(f, "mailboxes touched: {}", self.addresses_touched())
+    }
+}
+
+/// Which mailboxes have been executed, in one run or several merged together.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub executed: Vec<bool>,
+}
+
+impl Coverage {
+    pub fn new(mailboxes: usize) -> Self {
+        Coverage {
+            executed: vec![false; mailboxes],
+        }
+    }
+
+    /// Adds the mailboxes executed in another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        if self.executed.len() < other.executed.len() {
+            self.executed.resize(other.executed.len(), false);
+        }
+        for (executed, other) in self.executed.iter_mut().zip(&other.executed) {
+            *executed |= *other;
+        }
+    }
+
+    /// The mailboxes assembled as instructions that never ran.
+    pub fn uncovered(&self, assembly: &Assembly) -> Vec<usize> {
+        (0..assembly.kinds.len())
+            .filter(|&address| {
+                assembly.kinds[address] == CellKind::Code && !self.is_executed(address)
+            })
+            .collect()
+    }
+
+    /// Each source line holding an instruction, and whether it ran.
+    pub fn lines(&self, assembly: &Assembly) -> Vec<(usize, bool)> {
+        assembly
+            .source_lines
+            .iter()
+            .zip(&assembly.kinds)
+            .enumerate()
+            .filter_map(|(address, (line, kind))| match (line, kind) {
+                (Some(line), CellKind::Code) => Some((*line, self.is_executed(address))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The fraction of instructions in `assembly` that ran, from 0 to 1.
+    pub fn ratio(&self, assembly: &Assembly) -> f64 {
+        let code = assembly
+            .kinds
+            .iter()
+            .filter(|kind| **kind == CellKind::Code)
+            .count();
+        match code {
+            0 => 1.0,
+            _ => 1.0 - self.uncovered(assembly).len() as f64 / code as f64,
+        }
+    }
+
+    fn is_executed(&self, address: usize) -> bool {
+        self.executed.get(address).copied().unwrap_or(false)
+    }
+}
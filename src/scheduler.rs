@@ -0,0 +1,139 @@
+use crate::{ExecutionState, HaltReason, RuntimeError, StepEvent, LMCIO};
+
+/// A machine owned by a `Scheduler`, with its own IO handler.
+pub struct Machine<T: LMCIO> {
+    pub state: ExecutionState,
+    pub io_handler: T,
+    /// Why the machine stopped, once it has.
+    pub halt_reason: Option<HaltReason>,
+    /// The error that stopped the machine, if stepping it failed.
+    pub error: Option<RuntimeError>,
+}
+
+impl<T: LMCIO> Machine<T> {
+    pub fn is_running(&self) -> bool {
+        self.halt_reason.is_none() && self.error.is_none()
+    }
+}
+
+/// Runs several machines in one thread, taking turns of `quantum` steps each.
+///
+/// Machines that pause (at a watchpoint or breakpoint) are left alone until `resume` is
+/// called for them.
+pub struct Scheduler<T: LMCIO> {
+    machines: Vec<Machine<T>>,
+    quantum: u64,
+    current: usize,
+    turn: u64,
+}
+
+impl<T: LMCIO> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: LMCIO> Scheduler<T> {
+    pub fn new() -> Self {
+        Self::with_quantum(1)
+    }
+
+    pub fn with_quantum(quantum: u64) -> Self {
+        Scheduler {
+            machines: vec![],
+            quantum: quantum.max(1),
+            current: 0,
+            turn: 0,
+        }
+    }
+
+    /// Adds a machine, returning its id.
+    pub fn spawn(&mut self, state: ExecutionState, io_handler: T) -> usize {
+        self.machines.push(Machine {
+            state,
+            io_handler,
+            halt_reason: None,
+            error: None,
+        });
+        self.machines.len() - 1
+    }
+
+    pub fn machine(&self, id: usize) -> Option<&Machine<T>> {
+        self.machines.get(id)
+    }
+
+    pub fn machine_mut(&mut self, id: usize) -> Option<&mut Machine<T>> {
+        self.machines.get_mut(id)
+    }
+
+    pub fn machines(&self) -> &[Machine<T>] {
+        &self.machines
+    }
+
+    pub fn into_machines(self) -> Vec<Machine<T>> {
+        self.machines
+    }
+
+    /// Lets a machine paused at a watchpoint or breakpoint run again, returning whether it
+    /// was paused. Machines that halted for good (on HLT, a limit or an error) stay stopped.
+    pub fn resume(&mut self, id: usize) -> bool {
+        match self.machines.get_mut(id) {
+            Some(machine)
+                if machine
+                    .halt_reason
+                    .as_ref()
+                    .is_some_and(HaltReason::is_pause) =>
+            {
+                machine.halt_reason = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.machines.iter().any(Machine::is_running)
+    }
+
+    /// Steps the next machine in turn, returning its id and what it did, or `None` once
+    /// every machine has stopped.
+    pub fn step(&mut self) -> Option<(usize, Result<StepEvent, RuntimeError>)> {
+        if self.is_finished() {
+            return None;
+        }
+
+        if self.turn >= self.quantum || !self.machines[self.current].is_running() {
+            self.turn = 0;
+            self.current = (self.current + 1..)
+                .map(|i| i % self.machines.len())
+                .find(|i| self.machines[*i].is_running())?;
+        }
+
+        let id = self.current;
+        let machine = &mut self.machines[id];
+        self.turn += 1;
+
+        if machine
+            .state
+            .config
+            .max_steps
+            .is_some_and(|max_steps| machine.state.steps_executed >= max_steps)
+        {
+            machine.halt_reason = Some(HaltReason::StepLimit);
+            return self.step();
+        }
+
+        let result = machine.state.step(&mut machine.io_handler);
+        match &result {
+            Ok(event) => machine.halt_reason.clone_from(&event.halt_reason),
+            Err(error) => machine.error = Some(error.clone()),
+        }
+
+        Some((id, result))
+    }
+
+    /// Steps machines in turn until every one of them has stopped.
+    pub fn run(&mut self) {
+        while self.step().is_some() {}
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{ExecutionState, HaltReason, Output, RuntimeError, StepEvent, StepOutcome};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionStatus {
+    /// `step_n` will execute instructions.
+    Running,
+    /// Stopped by `pause`, or by a watchpoint or breakpoint. `resume` carries on.
+    Paused,
+    /// The next instruction is INP or INA and there's no input queued for it.
+    NeedsInput,
+    /// The machine stopped for good.
+    Stopped(HaltReason),
+    /// Stepping failed, e.g. under `PcOverflow::Error`.
+    Failed(RuntimeError),
+}
+
+/// Drives a machine a few steps at a time, for frontends with their own event loop.
+///
+/// Instead of blocking on an IO handler, input is queued with `provide_input` ahead of
+/// time; when the program wants input that hasn't been given yet, the session waits in
+/// `SessionStatus::NeedsInput`. Output is collected until `take_outputs` is called.
+pub struct Session {
+    state: ExecutionState,
+    outputs: Vec<Output>,
+    status: SessionStatus,
+}
+
+impl Session {
+    pub fn new(state: ExecutionState) -> Self {
+        Session {
+            state,
+            outputs: vec![],
+            status: SessionStatus::Running,
+        }
+    }
+
+    pub fn status(&self) -> &SessionStatus {
+        &self.status
+    }
+
+    pub fn state(&self) -> &ExecutionState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut ExecutionState {
+        &mut self.state
+    }
+
+    /// Queues a value for INP (or a character code for INA).
+    pub fn provide_input(&mut self, value: i16) {
+        self.state.provide_input(value);
+        if self.status == SessionStatus::NeedsInput {
+            self.status = SessionStatus::Running;
+        }
+    }
+
+    /// Everything the program has output since the last call.
+    pub fn take_outputs(&mut self) -> Vec<Output> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    pub fn pause(&mut self) {
+        if self.status == SessionStatus::Running {
+            self.status = SessionStatus::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.status == SessionStatus::Paused {
+            self.status = SessionStatus::Running;
+        }
+    }
+
+    /// Executes one instruction, even if the session is paused. Returns `None` if it's
+    /// waiting for input or has stopped.
+    pub fn step(&mut self) -> Option<Result<StepEvent, RuntimeError>> {
+        if matches!(
+            self.status,
+            SessionStatus::Stopped(_) | SessionStatus::Failed(_)
+        ) {
+            return None;
+        }
+
+        let result = match self.state.step_nonblocking() {
+            Ok(StepOutcome::NeedsInput) => {
+                self.wait_for_input();
+                return None;
+            }
+            Ok(StepOutcome::Stepped(event)) => {
+                self.outputs.extend(event.output);
+                self.status = match &event.halt_reason {
+                    None if self.status == SessionStatus::Paused => SessionStatus::Paused,
+                    None => SessionStatus::Running,
+                    Some(halt_reason) if halt_reason.is_pause() => SessionStatus::Paused,
+                    Some(halt_reason) => SessionStatus::Stopped(halt_reason.clone()),
+                };
+                Ok(event)
+            }
+            Err(error) => {
+                self.status = SessionStatus::Failed(error.clone());
+                Err(error)
+            }
+        };
+        if self.state.awaiting_input() {
+            self.wait_for_input();
+        }
+
+        Some(result)
+    }
+
+    /// Executes up to `k` instructions, stopping early if the session pauses, needs input
+    /// or stops.
+    pub fn step_n(&mut self, k: usize) -> Result<Vec<StepEvent>, RuntimeError> {
+        let mut events = vec![];
+
+        while events.len() < k && self.status == SessionStatus::Running {
+            match self.step() {
+                Some(result) => events.push(result?),
+                None => break,
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn wait_for_input(&mut self) {
+        if self.status == SessionStatus::Running {
+            self.status = SessionStatus::NeedsInput;
+        }
+    }
+}
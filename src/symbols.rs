@@ -0,0 +1,85 @@
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Label, Program};
+
+/// Label → value pairs, in definition order.
+///
+/// As a file, each non-empty line is `LABEL VALUE`, and lines starting with `//` are comments.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable(pub Vec<(String, i16)>);
+
+impl SymbolTable {
+    /// Collects the address of every label defined in `program`.
+    pub fn from_program(program: &Program) -> Self {
+        SymbolTable(
+            program
+                .iter()
+                .enumerate()
+                .filter_map(|(address, (label, _))| match label {
+                    Label::LBL(name) => Some((name.clone(), address as i16)),
+                    Label::None => None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<i16> {
+        self.0
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn insert(&mut self, name: &str, value: i16) -> Result<(), String> {
+        if self.get(name).is_some() {
+            return Err(format!("Duplicate label... {}", name));
+        }
+        self.0.push((name.to_string(), value));
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i16)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), *value))
+    }
+}
+
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.iter() {
+            writeln!(f, "{} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SymbolTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut symbols = SymbolTable::default();
+
+        for line in s.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            if tokens.is_empty() || tokens[0].starts_with("//") {
+                continue;
+            }
+
+            match tokens[..] {
+                [name, value] => {
+                    let value = value
+                        .parse::<i16>()
+                        .map_err(|_| format!("Invalid symbol value... {}", value))?;
+                    symbols.insert(name, value)?;
+                }
+                _ => return Err(format!("Error while reading symbol: {}", line)),
+            }
+        }
+
+        Ok(symbols)
+    }
+}
@@ -0,0 +1,246 @@
+//! Running `.lmc` programs against test cases: inputs and the outputs they should produce.
+//!
+//! With the `json` feature, cases can be kept in a spec file next to the program, e.g.
+//! `countdown.spec.json` for `countdown.lmc`:
+//!
+//! ```text
+//! {
+//!   "cases": [
+//!     { "name": "from three", "inputs": [3], "outputs": [3, 2, 1, 0] },
+//!     { "name": "from one", "inputs": [1], "outputs": [1, 0], "max_steps": 100 }
+//!   ]
+//! }
+//! ```
+//!
+//! For example suites and regression tests there are also `assert_program_output` and
+//! snapshot files checked by `assert_output_snapshot`.
+
+use std::{fmt, path::Path};
+
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    build, io::BufferedIO, run_with_state, Assembly, BuildOptions, Diagnostics, ExecutionState,
+    ExecutionStats, HaltReason, Output, DEFAULT_MAX_STEPS,
+};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spec {
+    pub cases: Vec<Case>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub inputs: Vec<i16>,
+    /// Everything the program should output, with OTC output given as character codes.
+    pub outputs: Vec<i16>,
+    /// Overrides `DEFAULT_MAX_STEPS`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_steps: Option<u64>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// The program halted, but output the wrong thing.
+    Failed {
+        expected: Vec<i16>,
+        actual: Vec<i16>,
+    },
+    /// The program stopped without halting, e.g. at the step limit.
+    Stopped(HaltReason),
+    /// The program couldn't be run to the end, e.g. it wanted more input than the case gave.
+    Error(String),
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+impl fmt::Display for CaseResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Passed => write!(f, "{}: passed", self.name),
+            Outcome::Failed { expected, actual } => {
+                write!(f, "{}: failed", self.name)?;
+                let first_difference = expected
+                    .iter()
+                    .zip(actual)
+                    .position(|(expected, actual)| expected != actual)
+                    .unwrap_or(expected.len().min(actual.len()));
+                write!(f, "... output {} differs", first_difference + 1)?;
+                write!(f, "\n  expected: {:?}\n  actual:   {:?}", expected, actual)
+            }
+            Outcome::Stopped(halt_reason) => {
+                write!(f, "{}: stopped... {:?}", self.name, halt_reason)
+            }
+            Outcome::Error(message) => write!(f, "{}: error... {}", self.name, message),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Spec {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid spec... {e}"))
+    }
+
+    pub fn to_json(&self) -> String {
+        // a spec is only ever numbers and strings, so this can't fail
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Set this environment variable (to anything) to make `assert_output_snapshot` rewrite
+/// snapshot files instead of checking them.
+pub const UPDATE_SNAPSHOTS: &str = "LMC_UPDATE_SNAPSHOTS";
+
+/// Runs the program at `path` on `inputs`, panicking unless it halts after outputting
+/// exactly `expected`.
+#[track_caller]
+pub fn assert_program_output(path: impl AsRef<Path>, inputs: &[i16], expected: &[Output]) {
+    let path = path.as_ref();
+    let outputs = run_file(path, inputs);
+    assert_eq!(outputs, expected, "wrong output from {}", path.display());
+}
+
+/// Runs the program at `path` on `inputs` and compares its output, as `render_outputs`
+/// shows it, with the snapshot file next to it: `countdown.three.out` for
+/// `countdown.lmc` and the name `three`. The snapshot is written instead if it doesn't
+/// exist yet or `UPDATE_SNAPSHOTS` is set.
+#[track_caller]
+pub fn assert_output_snapshot(path: impl AsRef<Path>, name: &str, inputs: &[i16]) {
+    let path = path.as_ref();
+    let snapshot_path = path.with_extension(format!("{name}.out"));
+    let actual = render_outputs(&run_file(path, inputs));
+
+    let expected = match std::env::var_os(UPDATE_SNAPSHOTS) {
+        Some(_) => None,
+        None => std::fs::read_to_string(&snapshot_path).ok(),
+    };
+    match expected {
+        Some(expected) => assert!(
+            expected == actual,
+            "output from {} doesn't match {} (set {} to update it)\n  expected: {:?}\n  actual:   {:?}",
+            path.display(),
+            snapshot_path.display(),
+            UPDATE_SNAPSHOTS,
+            expected,
+            actual
+        ),
+        None => std::fs::write(&snapshot_path, actual).unwrap_or_else(|e| {
+            panic!("Failed to write {}... {e}", snapshot_path.display())
+        }),
+    }
+}
+
+/// Outputs as a console shows them: OUT values one per line, and OTC characters as they are.
+pub fn render_outputs(outputs: &[Output]) -> String {
+    outputs
+        .iter()
+        .map(|output| match output {
+            Output::Int(value) => format!("{}\n", value),
+            Output::Char(c) => c.to_string(),
+        })
+        .collect()
+}
+
+/// Runs the program at `path`, panicking if it doesn't build or halt.
+#[track_caller]
+fn run_file(path: &Path, inputs: &[i16]) -> Vec<Output> {
+    let source = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}... {e}", path.display()));
+    let assembly = build(&source, &BuildOptions::default())
+        .unwrap_or_else(|diagnostics| panic!("{} doesn't build:\n{diagnostics}", path.display()));
+
+    let mut io_handler = BufferedIO::new(inputs.iter().copied());
+    match run_with_state(
+        ExecutionState::from_assembly(&assembly),
+        &mut io_handler,
+        false,
+    ) {
+        Ok(result) if result.halt_reason == HaltReason::Halted => io_handler.take_outputs(),
+        Ok(result) => panic!("{} stopped... {:?}", path.display(), result.halt_reason),
+        Err(error) => panic!("{} failed... {error}", path.display()),
+    }
+}
+
+/// Builds `source` and runs it once for each case.
+pub fn run_spec(source: &str, spec: &Spec) -> Result<Vec<CaseResult>, Diagnostics> {
+    let assembly = build(source, &BuildOptions::default())?;
+
+    Ok(spec
+        .cases
+        .iter()
+        .map(|case| CaseResult {
+            name: case.name.clone(),
+            outcome: run_case(&assembly, case).0,
+        })
+        .collect())
+}
+
+/// Runs one case, returning the stats for the run if it got to the end.
+pub(crate) fn run_case(assembly: &Assembly, case: &Case) -> (Outcome, Option<ExecutionStats>) {
+    let mut state = ExecutionState::from_assembly(assembly);
+    state.config.max_steps = Some(case.max_steps.unwrap_or(DEFAULT_MAX_STEPS));
+    let mut io_handler = BufferedIO::new(case.inputs.iter().copied());
+
+    match run_with_state(state, &mut io_handler, false) {
+        Ok(result) if result.halt_reason == HaltReason::Halted => {
+            let actual: Vec<i16> = io_handler
+                .take_outputs()
+                .into_iter()
+                .map(|output| match output {
+                    Output::Int(value) => value,
+                    Output::Char(c) => c as i16,
+                })
+                .collect();
+            let outcome = match actual == case.outputs {
+                true => Outcome::Passed,
+                false => Outcome::Failed {
+                    expected: case.outputs.clone(),
+                    actual,
+                },
+            };
+            (outcome, Some(result.stats))
+        }
+        Ok(result) => (Outcome::Stopped(result.halt_reason), Some(result.stats)),
+        Err(error) => (Outcome::Error(error.to_string()), None),
+    }
+}
+
+/// Runs the program at `path` against the spec file next to it (`path` with the extension
+/// `.spec.json`).
+#[cfg(feature = "json")]
+pub fn run_spec_file(path: impl AsRef<std::path::Path>) -> Result<Vec<CaseResult>, String> {
+    let path = path.as_ref();
+    let spec_path = path.with_extension("spec.json");
+
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}... {e}", path.display()))?;
+    let spec = std::fs::read_to_string(&spec_path)
+        .map_err(|e| format!("Failed to read {}... {e}", spec_path.display()))?;
+
+    run_spec(&source, &Spec::from_json(&spec)?).map_err(|diagnostics| {
+        diagnostics
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
@@ -0,0 +1,106 @@
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{DecodedInstruction, HaltReason, Output, Register};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryRead {
+    pub address: i16,
+    pub value: i16,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub address: i16,
+    pub old: i16,
+    pub new: i16,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterChange {
+    pub register: Register,
+    pub old: i16,
+    pub new: i16,
+}
+
+/// What a single call to `ExecutionState::step` did.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepEvent {
+    /// Address of the instruction that was executed.
+    pub pc: i16,
+    pub word: i16,
+    /// The decoded word, or `None` if it wasn't a valid instruction.
+    pub instruction: Option<DecodedInstruction>,
+    pub read: Option<MemoryRead>,
+    pub write: Option<MemoryWrite>,
+    /// The registers the step changed, in the order ACC, PC, MAR, MDR, CIR, SP.
+    pub registers: Vec<RegisterChange>,
+    /// The out-of-range result of an ADD, SUB or MUL, before the overflow policy was applied.
+    pub overflow: Option<i32>,
+    pub input: Option<i16>,
+    pub output: Option<Output>,
+    /// Whether the branch was taken, for branch instructions.
+    pub branch: Option<bool>,
+    /// Whether a timer interrupt fired after the instruction, so the next one is the handler.
+    pub interrupted: bool,
+    /// Suspicious things the instruction did that didn't stop the machine.
+    pub warnings: Vec<RuntimeWarning>,
+    pub halt_reason: Option<HaltReason>,
+}
+
+/// What `ExecutionState::step_nonblocking` did.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutcome {
+    Stepped(StepEvent),
+    /// The next instruction wants input; nothing was executed.
+    NeedsInput,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeWarning {
+    /// The instruction at `pc` overwrote `address`, which holds code: either it has been
+    /// executed or it was assembled from an instruction.
+    SelfModifyingCode { address: i16, pc: i16 },
+    /// The instruction at `pc` read `address`, which wasn't part of the program and hasn't
+    /// been written since.
+    UninitializedRead { address: i16, pc: i16 },
+}
+
+/// One executed instruction, with the registers as they were after it ran.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep {
+    pub event: StepEvent,
+    pub acc: i16,
+    pub mar: i16,
+    pub mdr: i16,
+    pub next_pc: i16,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    pub fn inputs(&self) -> impl Iterator<Item = i16> + '_ {
+        self.steps.iter().filter_map(|step| step.event.input)
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = Output> + '_ {
+        self.steps.iter().filter_map(|step| step.event.output)
+    }
+
+    pub fn writes(&self) -> impl Iterator<Item = &MemoryWrite> {
+        self.steps
+            .iter()
+            .filter_map(|step| step.event.write.as_ref())
+    }
+}
@@ -0,0 +1,595 @@
+use lmc_assembly::{
+    self, Assembly, BuildOptions, CellKind, DiagnosticCode, Encoding, LintRules, MemoryImage,
+    Mismatch, SymbolTable,
+};
+
+fn get_program(path: &str) -> [i16; 100] {
+    let code = std::fs::read_to_string(path).unwrap();
+    let program = lmc_assembly::parse(&code, false).unwrap();
+    lmc_assembly::assemble(program).unwrap()
+}
+
+#[test]
+fn test_verify_matching_image() {
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let image = get_program("./examples/sum.lmc");
+
+    assert_eq!(lmc_assembly::verify(&code, &image).unwrap(), vec![]);
+}
+
+#[test]
+fn test_verify_reports_mismatches() {
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let mut image = get_program("./examples/sum.lmc");
+
+    // hand-assembled STA to the wrong mailbox
+    image[1] = 307;
+
+    assert_eq!(
+        lmc_assembly::verify(&code, &image).unwrap(),
+        vec![Mismatch {
+            address: 1,
+            expected: 306,
+            actual: 307,
+        }]
+    );
+}
+
+#[test]
+fn test_custom_encoding_round_trip() {
+    let code = std::fs::read_to_string("./examples/ascii.lmc").unwrap();
+    let encoding = Encoding::default().with("OTC", 903);
+
+    let program = lmc_assembly::parse(&code, false).unwrap();
+    let image = lmc_assembly::assemble_with_encoding(program, &encoding).unwrap();
+    assert_eq!(image[3], 903);
+
+    let disassembled = lmc_assembly::disassemble(&image, &encoding);
+    assert_eq!(disassembled[3].1.to_string(), "OTC");
+    assert_eq!(disassembled[2].1.to_string(), "LDA 13");
+    assert_eq!(disassembled[10].1.to_string(), "DAT 32");
+}
+
+#[test]
+fn test_memory_image_encoding_round_trip() {
+    let image = MemoryImage::from(get_program("./examples/fibonacci.lmc"));
+    let encoded = image.encode().unwrap();
+
+    assert!(encoded.len() < 100);
+    assert_eq!(MemoryImage::decode(&encoded).unwrap(), image);
+    assert_eq!(MemoryImage::decode("").unwrap(), MemoryImage([0; 100]));
+    assert!(MemoryImage::decode("not base64!").is_err());
+
+    let mut ram = [0; 100];
+    ram[3] = i16::MAX;
+    assert_eq!(
+        MemoryImage(ram).encode(),
+        Err(format!("Invalid word in memory image... {}", i16::MAX))
+    );
+    ram[3] = -1000;
+    assert!(MemoryImage(ram).encode().is_err());
+}
+
+#[test]
+fn test_assembly_distinguishes_hlt_from_dat_zero() {
+    let code = std::fs::read_to_string("./examples/fibonacci.lmc").unwrap();
+    let program = lmc_assembly::parse(&code, false).unwrap();
+    let assembly = Assembly::new(program, Encoding::default()).unwrap();
+
+    assert_eq!(assembly.kinds[15], CellKind::Code);
+    assert_eq!(assembly.kinds[16], CellKind::Data);
+    assert_eq!(assembly.kinds[20], CellKind::Unused);
+
+    let listing = assembly.listing();
+    assert!(listing.contains("15  000  HLT\n"));
+    assert!(listing.contains("16  000  DAT 0\n"));
+    assert_eq!(listing.lines().count(), 20);
+}
+
+#[test]
+fn test_build_example() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &BuildOptions::default()).unwrap();
+
+    assert_eq!(*assembly.image, get_program("./examples/countdown.lmc"));
+    assert_eq!(assembly.source_lines[2], Some(3));
+    assert!(assembly.warnings.is_empty());
+}
+
+#[test]
+fn test_build_collects_diagnostics() {
+    let code = "        INP\nSTART   BRN END\nLOOP    LDA\n        STA NOWHERE\nEND     HLT\n";
+    let diagnostics = lmc_assembly::build(code, &BuildOptions::default()).unwrap_err();
+
+    let errors: Vec<_> = diagnostics
+        .errors()
+        .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (Some(2), "Invalid opcode... BRN"),
+            (Some(3), "Missing operand... LDA"),
+            (Some(4), "Invalid label... NOWHERE"),
+        ]
+    );
+}
+
+#[test]
+fn test_diagnostics_to_json() {
+    let code = "        INP\nSTART   BRN END\nLOOP    LDA\n        STA NOWHERE\nEND     HLT\n";
+    let diagnostics = lmc_assembly::build(code, &BuildOptions::default()).unwrap_err();
+
+    let lines: Vec<_> = diagnostics
+        .errors()
+        .map(|diagnostic| diagnostic.to_json("code.lmc", code))
+        .collect();
+    assert_eq!(
+        lines[0],
+        r#"{"file":"code.lmc","line":2,"column":9,"code":"invalid-opcode","severity":"error","message":"Invalid opcode... BRN"}"#
+    );
+    assert!(lines[1].contains(r#""line":3,"column":9,"code":"missing-operand""#));
+    assert!(lines[2].contains(r#""line":4,"column":13,"code":"invalid-label""#));
+    let codes: Vec<_> = diagnostics
+        .errors()
+        .map(|diagnostic| diagnostic.code)
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            DiagnosticCode::InvalidOpcode,
+            DiagnosticCode::MissingOperand,
+            DiagnosticCode::InvalidLabel
+        ]
+    );
+    for line in &lines {
+        assert!(serde_json::from_str::<serde_json::Value>(line).is_ok());
+    }
+
+    let diagnostic =
+        lmc_assembly::Diagnostic::error(DiagnosticCode::ProgramTooLarge, "Program is too large");
+    assert_eq!(
+        diagnostic.to_json("code.lmc", code),
+        r#"{"file":"code.lmc","line":null,"column":null,"code":"program-too-large","severity":"error","message":"Program is too large"}"#
+    );
+}
+
+#[test]
+fn test_render_diagnostics() {
+    let code = "        INP\nSTART   BRN END\nLOOP    LDA\n        STA NOWHRE\nNOWHERE HLT\n";
+    let diagnostics = lmc_assembly::build(code, &BuildOptions::default()).unwrap_err();
+    let errors: Vec<_> = diagnostics.errors().collect();
+
+    assert_eq!(
+        errors[0].render("code.lmc", code),
+        "error: Invalid opcode... BRN\n \
+         --> code.lmc:2:9\n  \
+         |\n\
+         2 | START   BRN END\n  \
+         |         ^^^\n  \
+         = help: did you mean BRZ, BRP or BRA?\n"
+    );
+    assert_eq!(errors[1].help(code), None);
+    assert_eq!(
+        errors[2].help(code),
+        Some("did you mean NOWHERE?".to_string())
+    );
+    assert!(errors[2]
+        .render("code.lmc", code)
+        .contains("\n  |             ^^^^^^\n"));
+
+    // hints follow the code and subject rather than the wording
+    assert_eq!(errors[0].subject.as_deref(), Some("BRN"));
+    let reworded =
+        lmc_assembly::Diagnostic::error(DiagnosticCode::InvalidOpcode, "BRN isn't an instruction")
+            .at_line(2)
+            .about("BRN");
+    assert_eq!(reworded.help(code), errors[0].help(code));
+    let unknown =
+        lmc_assembly::Diagnostic::error(DiagnosticCode::InvalidOpcode, "Invalid opcode... BRN");
+    assert_eq!(unknown.help(code), None);
+
+    let rendered = diagnostics.render("code.lmc", code);
+    assert_eq!(rendered.matches("error: ").count(), 3);
+
+    // columns come from the parser, so the label isn't mistaken for the opcode it spells
+    let code = "BRN     BRN 5\n        HLT\n";
+    let diagnostics = lmc_assembly::build(code, &BuildOptions::default()).unwrap_err();
+    let error = diagnostics.errors().next().unwrap();
+    assert_eq!(error.columns, Some(8..11));
+    let reworded = lmc_assembly::Diagnostic {
+        message: "Not an instruction".to_string(),
+        ..error.clone()
+    };
+    assert!(reworded
+        .render("code.lmc", code)
+        .contains("\n  |         ^^^\n"));
+
+    let diagnostic =
+        lmc_assembly::Diagnostic::error(DiagnosticCode::ProgramTooLarge, "Program is too large");
+    assert_eq!(
+        diagnostic.render("code.lmc", code),
+        "error: Program is too large\n --> code.lmc\n"
+    );
+}
+
+#[test]
+fn test_build_size_limit() {
+    let code = "        HLT\n".repeat(60);
+    let small = BuildOptions {
+        encoding: Encoding::for_size(50),
+        ..BuildOptions::default()
+    };
+    let diagnostics = lmc_assembly::build(&code, &small).unwrap_err();
+    let error = diagnostics.errors().next().unwrap();
+    assert_eq!(error.code, DiagnosticCode::ProgramTooLarge);
+    assert_eq!(error.line, Some(51));
+
+    assert!(lmc_assembly::build(&code, &BuildOptions::default()).is_ok());
+    let code = "        HLT\n".repeat(101);
+    let diagnostics = lmc_assembly::build(&code, &BuildOptions::default()).unwrap_err();
+    assert_eq!(diagnostics.errors().next().unwrap().line, Some(101));
+}
+
+#[test]
+fn test_symbol_file_round_trip() {
+    let teacher = "        HLT\nFIRST   DAT 4\nSECOND  DAT 5\n";
+    let assembly = lmc_assembly::build(teacher, &BuildOptions::default()).unwrap();
+    let symbols_file = assembly.symbols.to_string();
+    assert_eq!(symbols_file, "FIRST 1\nSECOND 2\n");
+
+    let student = "        LDA FIRST\n        ADD SECOND\n        OUT\n        HLT\n";
+    let options = BuildOptions {
+        symbols: symbols_file.parse::<SymbolTable>().unwrap(),
+        ..BuildOptions::default()
+    };
+    let assembly = lmc_assembly::build(student, &options).unwrap();
+
+    assert_eq!(assembly.image[..4], [501, 102, 902, 0]);
+}
+
+#[test]
+fn test_rust_codegen() {
+    let image = get_program("./examples/countdown.lmc");
+    let code = lmc_assembly::codegen::to_rust(&image);
+
+    assert!(code.contains("pub trait Io {"));
+    assert!(code.contains("pub fn run<T: Io>(io: &mut T) -> Result<(), usize> {"));
+    assert!(code.contains(
+        "            // BRZ 8\n            6 => {\n                pc = if acc == 0 { 8 } else { 7 };\n            }\n"
+    ));
+    // one arm per mailbox up to the last one in use
+    assert!(code.contains("            13 => {"));
+    assert!(!code.contains("            14 => {"));
+}
+
+#[test]
+fn test_rust_codegen_runs() {
+    use lmc_assembly::Output;
+    use std::process::Command;
+
+    // takes the inputs as arguments and prints one output per line
+    const MAIN: &str = "
+struct Args(Vec<i16>);
+
+impl Io for Args {
+    fn input(&mut self) -> i16 {
+        self.0.remove(0)
+    }
+    fn input_char(&mut self) -> char {
+        self.input() as u8 as char
+    }
+    fn output(&mut self, value: i16) {
+        println!(\"{}\", value);
+    }
+    fn output_char(&mut self, c: char) {
+        println!(\"{}\", c);
+    }
+}
+
+fn main() {
+    let inputs = std::env::args().skip(1).map(|arg| arg.parse().unwrap()).collect();
+    run(&mut Args(inputs)).unwrap();
+}
+";
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+
+    for (name, inputs) in [("countdown", &[5][..]), ("multiplication", &[6, 7])] {
+        let image = get_program(&format!("./examples/{}.lmc", name));
+        let source = dir.join(format!("{}.rs", name));
+        let binary = dir.join(format!("{}-codegen", name));
+        std::fs::write(&source, lmc_assembly::codegen::to_rust(&image) + MAIN).unwrap();
+
+        let status = Command::new(&rustc)
+            .args(["--edition", "2021", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} didn't compile", name);
+
+        let output = Command::new(&binary)
+            .args(inputs.iter().map(i16::to_string))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let expected: String = lmc_assembly::run_capture(image, inputs)
+            .unwrap()
+            .into_iter()
+            .map(|output| match output {
+                Output::Int(value) => format!("{}\n", value),
+                Output::Char(c) => format!("{}\n", c),
+            })
+            .collect();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_wasm_codegen() {
+    let image = get_program("./examples/countdown.lmc");
+    let module = lmc_assembly::codegen::to_wasm(&image);
+
+    assert_eq!(&module[..8], b"\0asm\x01\0\0\0");
+    let contains = |needle: &[u8]| module.windows(needle.len()).any(|window| window == needle);
+    assert!(contains(b"\x05input"));
+    assert!(contains(b"\x06output"));
+    assert!(contains(b"\x03run"));
+    // the image is stored as little-endian i32s, starting with INP (901) and STA 12 (312)
+    assert!(contains(&[0x85, 0x03, 0, 0, 0x38, 0x01, 0, 0]));
+}
+
+#[test]
+fn test_wasm_codegen_runs() {
+    use lmc_assembly::Output;
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    struct Host {
+        inputs: Vec<i16>,
+        outputs: Vec<Output>,
+    }
+
+    for (name, inputs) in [("countdown", &[5][..]), ("multiplication", &[6, 7])] {
+        let image = get_program(&format!("./examples/{}.lmc", name));
+        let engine = Engine::default();
+        // fails if the module isn't valid
+        let module = Module::new(&engine, &lmc_assembly::codegen::to_wasm(&image)[..]).unwrap();
+
+        let host = Host {
+            inputs: inputs.to_vec(),
+            outputs: vec![],
+        };
+        let mut store = Store::new(&engine, host);
+        let mut linker = <Linker<Host>>::new(&engine);
+        linker
+            .func_wrap("env", "input", |mut caller: Caller<'_, Host>| {
+                i32::from(caller.data_mut().inputs.remove(0))
+            })
+            .unwrap()
+            .func_wrap("env", "input_char", |mut caller: Caller<'_, Host>| {
+                i32::from(caller.data_mut().inputs.remove(0))
+            })
+            .unwrap()
+            .func_wrap(
+                "env",
+                "output",
+                |mut caller: Caller<'_, Host>, value: i32| {
+                    caller.data_mut().outputs.push(Output::Int(value as i16))
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                "env",
+                "output_char",
+                |mut caller: Caller<'_, Host>, c: i32| {
+                    caller
+                        .data_mut()
+                        .outputs
+                        .push(Output::Char(c as u8 as char))
+                },
+            )
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+
+        let run = instance.get_typed_func::<(), i32>(&store, "run").unwrap();
+        assert_eq!(
+            run.call(&mut store, ()).unwrap(),
+            -1,
+            "{} didn't halt",
+            name
+        );
+        assert_eq!(
+            store.data().outputs,
+            lmc_assembly::run_capture(image, inputs).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_language_support() {
+    use lmc_assembly::language::{self, Hover, Span};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+
+    // `A` in "        STA A" on line 2
+    let span = |line, columns| Span { line, columns };
+    assert_eq!(language::definition(&code, 2, 12), Some(span(13, 0..1)));
+    assert_eq!(language::definition(&code, 2, 4), None);
+    assert_eq!(language::references(&code, 13, 0).len(), 6);
+
+    assert_eq!(
+        language::hover(&code, 2),
+        Some(Hover {
+            address: 1,
+            word: 312
+        })
+    );
+    assert_eq!(
+        language::hover(&code, 14),
+        Some(Hover {
+            address: 13,
+            word: 1
+        })
+    );
+
+    let edits = language::rename(&code, 3, 2, "AGAIN").unwrap();
+    let renamed = language::apply_edits(&code, &edits);
+    assert!(renamed.contains("AGAIN    LDA A\n"));
+    assert!(renamed.contains("        BRA AGAIN\n"));
+    assert_eq!(
+        lmc_assembly::assemble(lmc_assembly::parse(&renamed, false).unwrap()),
+        lmc_assembly::assemble(lmc_assembly::parse(&code, false).unwrap())
+    );
+    assert!(language::rename(&code, 3, 2, "ONE").is_err());
+    assert!(language::rename(&code, 3, 2, "LDA").is_err());
+
+    let diagnostics = language::diagnostics("LDA MISSING\nHLT");
+    assert_eq!(diagnostics[0].message, "Invalid label... MISSING");
+    assert_eq!(diagnostics[0].line, Some(1));
+}
+
+#[test]
+fn test_format() {
+    let messy = "// adds two numbers\n  inp\nsta FIRST\n\nINP\n   add   FIRST\nOUT\n   hlt\nFIRST dat\nTWO DAT 2\nthis line is wrong\n";
+    assert_eq!(
+        lmc_assembly::format(messy),
+        "// adds two numbers\n      INP\n      STA FIRST\n\n      INP\n      ADD FIRST\n      OUT\n      HLT\nFIRST DAT\nTWO   DAT 2\nthis line is wrong\n"
+    );
+
+    // the examples are already laid out like this
+    for example in ["countdown", "multiplication"] {
+        let code = std::fs::read_to_string(format!("./examples/{example}.lmc")).unwrap();
+        assert_eq!(lmc_assembly::format(&code).trim_end(), code.trim_end());
+    }
+}
+
+#[test]
+fn test_lint_rules() {
+    let code = "        BRA START\n        OUT\n        OUT\nSTART   BRZ DATA\n        BRA START\nDATA    DAT 5\nUNUSED  DAT\n";
+    let program = lmc_assembly::parse(code, false).unwrap();
+
+    let messages = |rules| -> Vec<(String, Option<usize>)> {
+        lmc_assembly::lint_with(&program, &rules)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, diagnostic.address))
+            .collect()
+    };
+
+    assert_eq!(
+        messages(LintRules::default()),
+        vec![
+            ("Unused label... UNUSED".to_string(), Some(6)),
+            ("Branch to data... DATA".to_string(), Some(3)),
+            ("Unreachable code".to_string(), Some(1)),
+            ("Program has no HLT instruction".to_string(), None),
+        ]
+    );
+    assert!(messages(LintRules {
+        unused_labels: false,
+        unreachable_code: false,
+        branch_to_data: false,
+        missing_hlt: false,
+    })
+    .is_empty());
+
+    let options = BuildOptions {
+        lint: LintRules {
+            missing_hlt: false,
+            ..LintRules::default()
+        },
+        deny_warnings: true,
+        ..BuildOptions::default()
+    };
+    let diagnostics = lmc_assembly::build(code, &options).unwrap_err();
+    assert_eq!(diagnostics.0.len(), 3);
+    assert_eq!(diagnostics.0[2].line, Some(2));
+}
+
+#[test]
+fn test_semantic_tokens() {
+    use lmc_assembly::language::{self, TokenKind};
+
+    let code = "// count down\nLOOP    LDA @PTR\n        BRA LOOP\n\nPTR     DAT 5\nnot an instruction at all\n";
+    let tokens: Vec<_> = language::semantic_tokens(code)
+        .into_iter()
+        .map(|token| (token.span.line, token.span.columns, token.kind))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            (1, 0..13, TokenKind::Comment),
+            (2, 0..4, TokenKind::LabelDefinition),
+            (2, 8..11, TokenKind::Opcode),
+            (2, 13..16, TokenKind::LabelReference),
+            (3, 8..11, TokenKind::Opcode),
+            (3, 12..16, TokenKind::LabelReference),
+            (5, 0..3, TokenKind::LabelDefinition),
+            (5, 8..11, TokenKind::Opcode),
+            (5, 12..13, TokenKind::Number),
+        ]
+    );
+}
+
+#[test]
+fn test_control_flow_graph() {
+    use lmc_assembly::analysis::{self, Edge, EdgeKind};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let graph = analysis::control_flow_graph(&lmc_assembly::parse(&code, false).unwrap());
+
+    let blocks: Vec<_> = graph
+        .nodes
+        .iter()
+        .map(|node| node.addresses.clone())
+        .collect();
+    assert_eq!(blocks, vec![0..2, 2..7, 7..8, 8..12]);
+    assert_eq!(graph.nodes[1].lines[0], "LOOP LDA A");
+
+    let edge = |from, to, kind| Edge { from, to, kind };
+    assert_eq!(
+        graph.edges,
+        vec![
+            edge(0, 1, EdgeKind::Next),
+            edge(1, 2, EdgeKind::Next),
+            edge(1, 3, EdgeKind::Branch),
+            edge(2, 1, EdgeKind::Branch),
+        ]
+    );
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph program {\n"));
+    assert!(dot.contains("    n0 [label=\"00  INP\\l01  STA A\\l\"];\n"));
+    assert!(dot.contains("    n2 -> n1 [label=\"branch\"];\n"));
+    assert!(dot.ends_with("}\n"));
+}
+
+#[test]
+fn test_memory_image_exports() {
+    let mut image = MemoryImage::from(get_program("./examples/countdown.lmc"));
+    image[99] = -1;
+
+    let hex = image.to_hex();
+    assert_eq!(hex.lines().count(), 10);
+    assert!(hex.starts_with("0385 0138 0200 0386 00d5 0138 02c4 025a 0200 00d4\n"));
+    assert!(hex.ends_with(" 0000 ffff\n"));
+
+    let csv = image.to_csv();
+    assert_eq!(csv.lines().count(), 101);
+    assert!(csv.starts_with("address,word\n0,901\n1,312\n"));
+    assert!(csv.ends_with("99,-1\n"));
+
+    let json: Vec<i16> = serde_json::from_str(&image.to_json()).unwrap();
+    assert_eq!(json, image.0);
+
+    let bytes = image.to_bytes();
+    assert_eq!(bytes.len(), 200);
+    assert_eq!(MemoryImage::from_bytes(&bytes).unwrap(), image);
+    assert!(MemoryImage::from_bytes(&bytes[..10]).is_err());
+    assert!(MemoryImage::from_bytes(&[0x7f; 200]).is_err());
+}
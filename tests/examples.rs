@@ -197,3 +197,190 @@ fn test_multiplication_2() {
     // check the output
     assert_eq!(io_handler.output_buffer, vec![Output::Int(35)]);
 }
+
+#[test]
+fn test_countdown_assertions() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+
+    let report = lmc_assembly::check_assertions(&code, &[3]).unwrap();
+    assert!(report.is_success());
+    // ACC > 0 at LOOP three times, then RAM[A] == 0 at HLT
+    assert_eq!(report.passed, 4);
+    assert_eq!(
+        report.outputs,
+        vec![
+            Output::Int(3),
+            Output::Int(2),
+            Output::Int(1),
+            Output::Int(0)
+        ]
+    );
+
+    let broken = code.replace("RAM[A] == 0", "RAM[A] == 1");
+    let report = lmc_assembly::check_assertions(&broken, &[3]).unwrap();
+    assert!(!report.is_success());
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].assertion.line, 16);
+    assert_eq!(report.failures[0].value, Ok(0));
+
+    let diagnostics =
+        lmc_assembly::check_assertions("        HLT\n// ASSERT NOWHERE: ACC == 0\n", &[])
+            .unwrap_err();
+    assert_eq!(
+        diagnostics.0[0].message,
+        "Invalid assertion... Invalid label... NOWHERE"
+    );
+    assert_eq!(diagnostics.0[0].line, Some(2));
+}
+
+#[test]
+fn test_run_spec() {
+    use lmc_assembly::testing::{self, Case, Outcome, Spec};
+
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let case = |name: &str, inputs: Vec<i16>, outputs: Vec<i16>| Case {
+        name: name.to_string(),
+        inputs,
+        outputs,
+        max_steps: None,
+    };
+    let spec = Spec {
+        cases: vec![
+            case("right", vec![1, 2], vec![3]),
+            case("wrong", vec![1, 2], vec![3, 4]),
+            case("no input", vec![1], vec![1]),
+        ],
+    };
+
+    let results = testing::run_spec(&code, &spec).unwrap();
+    assert!(results[0].passed());
+    assert_eq!(
+        results[1].outcome,
+        Outcome::Failed {
+            expected: vec![3, 4],
+            actual: vec![3],
+        }
+    );
+    assert_eq!(
+        results[1].to_string(),
+        "wrong: failed... output 2 differs\n  expected: [3, 4]\n  actual:   [3]"
+    );
+    assert!(matches!(results[2].outcome, Outcome::Error(_)));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_example_specs() {
+    for example in ["sum", "countdown", "fibonacci"] {
+        let results =
+            lmc_assembly::testing::run_spec_file(format!("./examples/{example}.lmc")).unwrap();
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert!(result.passed(), "{example}: {result}");
+        }
+    }
+
+    assert!(lmc_assembly::testing::run_spec_file("./examples/ascii.lmc").is_err());
+}
+
+#[test]
+fn test_program_output_helpers() {
+    use lmc_assembly::testing;
+
+    testing::assert_program_output("./examples/sum.lmc", &[4, 5], &[Output::Int(9)]);
+    testing::assert_output_snapshot("./examples/countdown.lmc", "three", &[3]);
+    testing::assert_output_snapshot("./examples/ascii.lmc", "all", &[]);
+
+    assert_eq!(
+        testing::render_outputs(&[Output::Int(7), Output::Char('h'), Output::Char('i')]),
+        "7\nhi"
+    );
+}
+
+#[test]
+fn test_output_snapshot_mismatch() {
+    use lmc_assembly::testing;
+
+    let dir = std::env::temp_dir().join(format!("lmc-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("sum.lmc");
+    std::fs::copy("./examples/sum.lmc", &program).unwrap();
+    let snapshot = dir.join("sum.small.out");
+    let _ = std::fs::remove_file(&snapshot);
+
+    // the first run writes the snapshot
+    testing::assert_output_snapshot(&program, "small", &[1, 2]);
+    assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), "3\n");
+    testing::assert_output_snapshot(&program, "small", &[1, 2]);
+
+    let mismatch =
+        std::panic::catch_unwind(|| testing::assert_output_snapshot(&program, "small", &[2, 2]));
+    assert!(mismatch.is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_grader() {
+    use lmc_assembly::{
+        grader::{self, Rubric},
+        testing::{Case, Outcome},
+    };
+
+    let case = |name: &str, inputs: Vec<i16>, outputs: Vec<i16>| Case {
+        name: name.to_string(),
+        inputs,
+        outputs,
+        max_steps: None,
+    };
+    let rubric = Rubric {
+        cases: vec![
+            case("small", vec![1, 2], vec![3]),
+            case("big", vec![400, 500], vec![900]),
+        ],
+        step_threshold: Some(6),
+        size_threshold: Some(7),
+    };
+
+    let sum = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let slow = sum.replace("OUT", "OUT\nOUT");
+    let wrong = sum.replace("ADD", "SUB");
+    let grades = grader::grade_all(&[sum.as_str(), &slow, &wrong, "LDA NOWHERE"], &rubric);
+
+    assert_eq!(grades[0].score(), 1.0);
+    assert!(grades[0].small_enough);
+    assert!(grades[0].cases.iter().all(|case| case.efficient));
+    assert_eq!(grades[0].cases[0].stats.as_ref().unwrap().steps, 6);
+
+    assert_eq!(grades[1].passed(), 0);
+    assert!(!grades[1].small_enough);
+    assert!(!grades[1].cases[0].efficient);
+
+    assert_eq!(
+        grades[2].cases[1].outcome,
+        Outcome::Failed {
+            expected: vec![900],
+            actual: vec![100],
+        }
+    );
+
+    assert!(!grades[3].builds());
+    assert!(grades[3].cases.is_empty());
+    assert_eq!(grades[3].score(), 0.0);
+
+    let grades = grader::grade_files(&["./examples/sum.lmc", "./examples/missing.lmc"], &rubric);
+    // compare everything but the timings
+    let graded = grader::grade(&sum, &rubric);
+    let outcomes = |grade: &grader::Grade| -> Vec<_> {
+        grade
+            .cases
+            .iter()
+            .map(|case| case.outcome.clone())
+            .collect()
+    };
+    assert_eq!(outcomes(&grades[0]), outcomes(&graded));
+    assert_eq!(grades[0].score(), graded.score());
+    assert!(grades[1].diagnostics[0]
+        .message
+        .starts_with("Failed to read"));
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "arbitrary")]
+
+use arbitrary::{Arbitrary, Unstructured};
+use lmc_assembly::{fuzz, HaltReason, Label, MemoryImage, Program};
+
+/// Bytes that look random enough to drive the generators, without a dev-dependency on rand.
+fn bytes(seed: u32, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(2_654_435_761) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_arbitrary_programs_assemble_or_fail_cleanly() {
+    for seed in 0..200 {
+        let data = bytes(seed, 512);
+        let program = Program::arbitrary(&mut Unstructured::new(&data)).unwrap();
+
+        let source: String = program
+            .iter()
+            .map(|(label, instruction)| match label {
+                Label::LBL(name) => format!("{name} {instruction}\n"),
+                Label::None => format!("        {instruction}\n"),
+            })
+            .collect();
+        fuzz::parse_text(source.as_bytes());
+
+        // generated programs only use known mnemonics, so whatever fails is a label,
+        // indirect addressing, extension or size problem
+        if let Err(diagnostics) = lmc_assembly::build(&source, &Default::default()) {
+            for diagnostic in diagnostics.errors() {
+                assert!(
+                    [
+                        "Invalid label",
+                        "Duplicate label",
+                        "Indirect",
+                        "No encoding",
+                        "Program is too large",
+                    ]
+                    .iter()
+                    .any(|prefix| diagnostic.message.starts_with(prefix)),
+                    "{diagnostic}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_arbitrary_images_run_within_the_step_limit() {
+    for seed in 0..200 {
+        let data = bytes(seed, 256);
+        let image = MemoryImage::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        assert!(image.iter().all(|word| (-999..=999).contains(word)));
+
+        if let Ok(result) = fuzz::run_image(&image, &[1, 2, 3], 1000) {
+            assert!(result.steps <= 1000);
+            if result.halt_reason == HaltReason::StepLimit {
+                assert_eq!(result.steps, 1000);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_text_survives_garbage() {
+    for text in [
+        "",
+        "\0\0\0",
+        "LDA",
+        "LOOP LDA @",
+        "@@@@",
+        "DAT 99999",
+        "// ASSERT",
+        "A B C D",
+    ] {
+        fuzz::parse_text(text.as_bytes());
+    }
+    for seed in 0..50 {
+        fuzz::parse_text(&bytes(seed, 200));
+    }
+}
@@ -0,0 +1,410 @@
+use lmc_assembly::{
+    self,
+    io::{BufferedIO, ChannelIO, FnIO, Input, IoEvent, RandomIO, ReplayIO, StringIO, TracingIO},
+    HaltReason, Output, RuntimeError, LMCIO,
+};
+
+struct TestIO {
+    input_buffer: Vec<i16>,
+    output_buffer: Vec<Output>,
+}
+
+impl LMCIO for TestIO {
+    fn get_input(&mut self) -> i16 {
+        self.input_buffer.pop().unwrap()
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.output_buffer.push(val);
+    }
+}
+
+fn get_program(path: &str) -> [i16; 100] {
+    let code = std::fs::read_to_string(path).unwrap();
+    let program = lmc_assembly::parse(&code, false).unwrap();
+    lmc_assembly::assemble(program).unwrap()
+}
+
+#[test]
+fn test_replay_io_reproduces_run() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut recorder = ReplayIO::new(TestIO {
+        input_buffer: vec![7, 6],
+        output_buffer: vec![],
+    });
+    lmc_assembly::run(assembled, &mut recorder, false).unwrap();
+    assert_eq!(recorder.recorded(), &[Input::Int(6), Input::Int(7)]);
+
+    let script = recorder.recorded().to_vec();
+    let mut replayer = ReplayIO::with_script(
+        TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        },
+        script,
+    );
+    lmc_assembly::run(assembled, &mut replayer, false).unwrap();
+
+    assert_eq!(replayer.into_inner().output_buffer, vec![Output::Int(42)]);
+}
+
+#[test]
+fn test_replay_io_char_input() {
+    let program = lmc_assembly::parse("INA\nOTC\nINP\nOUT\nINA\nHLT", false);
+    let assembled = lmc_assembly::assemble(program.unwrap()).unwrap();
+
+    // the inner handler's end of input is passed on, rather than panicking
+    let mut recorder = ReplayIO::new(StringIO::new("72\n5"));
+    let result = lmc_assembly::run(assembled, &mut recorder, false);
+    assert_eq!(result.unwrap_err(), RuntimeError::EndOfInput { pc: 4 });
+    assert_eq!(recorder.recorded(), &[Input::Char('H'), Input::Int(5)]);
+
+    let mut recorder = ReplayIO::new(BufferedIO::new([72, 5]));
+    let result = lmc_assembly::run(assembled, &mut recorder, false);
+    assert_eq!(result.unwrap_err(), RuntimeError::EndOfInput { pc: 4 });
+
+    let script = recorder.recorded().to_vec();
+    let mut replayer = ReplayIO::with_script(BufferedIO::default(), script);
+    lmc_assembly::run(assembled, &mut replayer, false).unwrap_err();
+    assert_eq!(
+        replayer.into_inner().outputs(),
+        [Output::Char('H'), Output::Int(5)]
+    );
+}
+
+#[test]
+fn test_buffered_io_new() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut io_handler = BufferedIO::new([6, 7]);
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(io_handler.outputs(), &[Output::Int(42)]);
+
+    // the second INP is at 2
+    let mut io_handler = BufferedIO::new([6]);
+    assert_eq!(
+        lmc_assembly::run(assembled, &mut io_handler, false).unwrap_err(),
+        RuntimeError::EndOfInput { pc: 2 }
+    );
+}
+
+#[test]
+fn test_buffered_io() {
+    // outputs the second input minus the first
+    let program = lmc_assembly::parse("INP\nSTA A\nINP\nSUB A\nOUT\nHLT\nA DAT", false);
+    let assembled = lmc_assembly::assemble(program.unwrap()).unwrap();
+
+    let mut io_handler = BufferedIO::default();
+    io_handler.inputs.extend([2, 7, 9]);
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+
+    assert_eq!(io_handler.inputs, [9]);
+    assert_eq!(io_handler.outputs, vec![Output::Int(5)]);
+}
+
+#[test]
+fn test_fn_io() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut outputs = vec![];
+    lmc_assembly::run(
+        assembled,
+        &mut FnIO::new(6.., |output| outputs.push(output)),
+        false,
+    )
+    .unwrap();
+    assert_eq!(outputs, vec![Output::Int(42)]);
+
+    let result = lmc_assembly::run(assembled, &mut FnIO::new([6], |_| {}), false);
+    assert_eq!(result.unwrap_err(), RuntimeError::EndOfInput { pc: 2 });
+}
+
+#[test]
+fn test_string_io() {
+    let program = lmc_assembly::parse("INA\nOTC\nINA\nOTC\nHLT", false);
+    let assembled = lmc_assembly::assemble(program.unwrap()).unwrap();
+
+    let mut io_handler = StringIO::new("72\n105");
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(io_handler.text(), "Hi");
+
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut io_handler = StringIO::new(" 6\n\n7\n");
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(io_handler.lines(), ["42"]);
+
+    let mut io_handler = StringIO::new("6 seven");
+    let result = lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::IoError("Invalid input... seven".to_string())
+    );
+}
+
+#[test]
+fn test_channel_io() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let (mut io_handler, inputs, outputs) = ChannelIO::channel();
+    let worker = std::thread::spawn(move || {
+        lmc_assembly::run(assembled, &mut io_handler, false).map(|result| result.halt_reason)
+    });
+
+    inputs.send(6).unwrap();
+    inputs.send(7).unwrap();
+    assert_eq!(outputs.recv(), Ok(Output::Int(42)));
+    assert_eq!(worker.join().unwrap(), Ok(HaltReason::Halted));
+
+    // dropping the sender ends the input
+    let (mut io_handler, inputs, _outputs) = ChannelIO::channel();
+    inputs.send(6).unwrap();
+    drop(inputs);
+    assert_eq!(
+        lmc_assembly::run(assembled, &mut io_handler, false).unwrap_err(),
+        RuntimeError::EndOfInput { pc: 2 }
+    );
+}
+
+#[test]
+fn test_tracing_io() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut io_handler = TracingIO::new(BufferedIO::new([6, 7]));
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+
+    let log: Vec<_> = io_handler
+        .log()
+        .iter()
+        .map(|entry| (entry.pc, entry.event.clone()))
+        .collect();
+    assert_eq!(
+        log,
+        vec![
+            (0, IoEvent::Input(6)),
+            (2, IoEvent::Input(7)),
+            (13, IoEvent::Output(Output::Int(42))),
+        ]
+    );
+    assert!(io_handler
+        .log()
+        .windows(2)
+        .all(|pair| pair[0].time <= pair[1].time));
+    assert_eq!(io_handler.transcript().lines().count(), 3);
+    assert!(io_handler.transcript().ends_with("13: > 42\n"));
+    assert_eq!(io_handler.into_inner().outputs, vec![Output::Int(42)]);
+}
+
+#[test]
+fn test_stream_io() {
+    use lmc_assembly::{DefaultIO, QuietIO, StreamIO};
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    /// Output that can still be read after the handler has taken it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let assembled = get_program("./examples/multiplication.lmc");
+    let text = |output: &Shared| String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+    let output = Shared::default();
+    let mut io_handler = StreamIO::new(Cursor::new("6\n7\n"), output.clone());
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(text(&output), "> > 42\n");
+
+    // quiet mode writes exactly the program's outputs
+    let output = Shared::default();
+    let mut io_handler = StreamIO::new(Cursor::new(" 6\n7"), output.clone()).quiet();
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(text(&output), "42\n");
+
+    let mut io_handler = StreamIO::new(Cursor::new("6\n"), Shared::default()).quiet();
+    assert_eq!(
+        lmc_assembly::run(assembled, &mut io_handler, false).unwrap_err(),
+        RuntimeError::EndOfInput { pc: 2 }
+    );
+    let mut io_handler = StreamIO::new(Cursor::new("six\n"), Shared::default()).quiet();
+    assert_eq!(
+        lmc_assembly::run(assembled, &mut io_handler, false)
+            .unwrap()
+            .halt_reason,
+        HaltReason::IoError("Invalid input... six".to_string())
+    );
+
+    // the stdio handlers are still unit structs, and every handler can be sent to a thread
+    fn assert_send<T: LMCIO + Send>(_: &T) {}
+    assert_send(&DefaultIO);
+    assert_send(&QuietIO);
+    assert_send(&StreamIO::stdio());
+}
+
+#[test]
+fn test_run_capture() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    assert_eq!(
+        lmc_assembly::run_capture(assembled, &[6, 7]),
+        Ok(vec![Output::Int(42)])
+    );
+    assert_eq!(
+        lmc_assembly::run_capture(assembled, &[6]),
+        Err(RuntimeError::EndOfInput { pc: 2 })
+    );
+}
+
+#[test]
+fn test_random_io() {
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut io_handler = RandomIO::with_range(42, 1..=20);
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    let inputs = io_handler.inputs().to_vec();
+    assert_eq!(inputs.len(), 2);
+    assert!(inputs.iter().all(|input| (1..=20).contains(input)));
+    assert_eq!(io_handler.outputs(), &[Output::Int(inputs[0] * inputs[1])]);
+
+    // the same seed gives the same inputs
+    let mut again = RandomIO::with_range(42, 1..=20);
+    lmc_assembly::run(assembled, &mut again, false).unwrap();
+    assert_eq!(again.inputs(), inputs);
+}
+
+#[test]
+fn test_run_batch() {
+    let assembled = get_program("./examples/multiplication.lmc");
+    let inputs: Vec<Vec<i16>> = (1..=20).map(|n| vec![n, n + 1]).collect();
+
+    let results = lmc_assembly::run_batch(assembled, inputs);
+
+    assert_eq!(results.len(), 20);
+    for (n, result) in (1..=20).zip(results) {
+        let (_, outputs) = result.unwrap();
+        assert_eq!(outputs, vec![Output::Int(n * (n + 1))]);
+    }
+
+    // an input set that runs out stops that run only
+    let results = lmc_assembly::run_batch(assembled, vec![vec![2, 3], vec![2]]);
+    assert!(results[0].is_ok());
+    assert_eq!(
+        results[1].as_ref().unwrap_err(),
+        &RuntimeError::EndOfInput { pc: 2 }
+    );
+}
+
+#[test]
+fn test_vm_types_are_send() {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    assert_send::<lmc_assembly::ExecutionState>();
+    assert_sync::<lmc_assembly::ExecutionState>();
+    assert_send::<lmc_assembly::RunResult>();
+    assert_send::<BufferedIO>();
+    assert_send::<RandomIO>();
+    assert_send::<ChannelIO>();
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_run_async() {
+    use lmc_assembly::{io::InputResponse, AsyncLMCIO, IoError};
+
+    struct AsyncIO {
+        inputs: Vec<i16>,
+        outputs: Vec<Output>,
+    }
+
+    impl AsyncLMCIO for AsyncIO {
+        async fn get_input(&mut self) -> Result<InputResponse, IoError> {
+            Ok(match self.inputs.pop() {
+                Some(input) => InputResponse::Value(input),
+                None => InputResponse::Eof,
+            })
+        }
+
+        async fn print_output(&mut self, val: Output) -> Result<(), IoError> {
+            self.outputs.push(val);
+            Ok(())
+        }
+    }
+
+    let assembled = get_program("./examples/multiplication.lmc");
+
+    let mut io_handler = AsyncIO {
+        inputs: vec![7, 6],
+        outputs: vec![],
+    };
+    let result =
+        futures_executor::block_on(lmc_assembly::run_async(assembled, &mut io_handler)).unwrap();
+    assert_eq!(result.halt_reason, HaltReason::Halted);
+    assert_eq!(io_handler.outputs, vec![Output::Int(42)]);
+    assert_eq!(result.stats.inputs, 2);
+
+    let mut io_handler = AsyncIO {
+        inputs: vec![6],
+        outputs: vec![],
+    };
+    assert_eq!(
+        futures_executor::block_on(lmc_assembly::run_async(assembled, &mut io_handler))
+            .unwrap_err(),
+        RuntimeError::EndOfInput { pc: 2 }
+    );
+}
+
+#[test]
+fn test_parse_inputs() {
+    use lmc_assembly::io::parse_inputs;
+
+    assert_eq!(parse_inputs("3,4"), Ok(vec![3, 4]));
+    assert_eq!(
+        parse_inputs("// inputs for sum.lmc\n3\n-4, 5\n\n  6 7\n"),
+        Ok(vec![3, -4, 5, 6, 7])
+    );
+    assert_eq!(parse_inputs(""), Ok(vec![]));
+    assert_eq!(parse_inputs("3,x"), Err("Invalid input... x".to_string()));
+    assert_eq!(
+        parse_inputs("1000"),
+        Err("Invalid input... 1000".to_string())
+    );
+
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let program = lmc_assembly::assemble(lmc_assembly::parse(&code, false).unwrap()).unwrap();
+    let mut io_handler = BufferedIO::parse("3,4").unwrap();
+    lmc_assembly::run(program, &mut io_handler, false).unwrap();
+    assert_eq!(io_handler.outputs(), [Output::Int(7)]);
+
+    // running out is an error rather than a blocked prompt
+    let mut io_handler = BufferedIO::parse("3").unwrap();
+    assert!(lmc_assembly::run(program, &mut io_handler, false).is_err());
+}
+
+#[test]
+fn test_outputs_to_json() {
+    let outputs = [
+        Output::Int(72),
+        Output::Char('"'),
+        Output::Char('\n'),
+        Output::Int(-3),
+    ];
+    let json = lmc_assembly::outputs_to_json(&outputs);
+    assert_eq!(
+        json,
+        r#"[{"int":72},{"char":"\""},{"char":"\n"},{"int":-3}]"#
+    );
+    assert!(serde_json::from_str::<serde_json::Value>(&json).is_ok());
+    assert_eq!(lmc_assembly::outputs_to_json(&[]), "[]");
+}
@@ -0,0 +1,140 @@
+#![cfg(feature = "serde")]
+
+use lmc_assembly::{self, Config, ExecutionState, Output, SavedState, LMCIO, STATE_VERSION};
+
+struct TestIO {
+    input_buffer: Vec<i16>,
+    output_buffer: Vec<Output>,
+}
+
+impl LMCIO for TestIO {
+    fn get_input(&mut self) -> i16 {
+        self.input_buffer.pop().unwrap()
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.output_buffer.push(val);
+    }
+}
+
+fn get_program(path: &str) -> [i16; 100] {
+    let code = std::fs::read_to_string(path).unwrap();
+    let program = lmc_assembly::parse(&code, false).unwrap();
+    lmc_assembly::assemble(program).unwrap()
+}
+
+#[test]
+fn test_saved_state_round_trip() {
+    let mut state = ExecutionState::with_config(
+        get_program("./examples/multiplication.lmc"),
+        Config {
+            max_outputs: Some(3),
+            ..Config::default()
+        },
+    );
+    let mut io_handler = TestIO {
+        input_buffer: vec![7, 6],
+        output_buffer: vec![],
+    };
+    for _ in 0..10 {
+        state.step(&mut io_handler).unwrap();
+    }
+
+    let json = serde_json::to_value(SavedState::from(state.clone())).unwrap();
+    assert_eq!(json["version"], STATE_VERSION);
+
+    let SavedState(loaded) = serde_json::from_value::<SavedState>(json).unwrap();
+    assert_eq!(loaded.ram, state.ram);
+    assert_eq!((loaded.pc, loaded.acc), (state.pc, state.acc));
+    assert_eq!(loaded.steps_executed, 10);
+    assert_eq!(loaded.config, state.config);
+}
+
+#[test]
+fn test_saved_state_migration() {
+    let mut ram = vec![0; 100];
+    ram[0] = 901;
+    ram[1] = 902;
+    let old = format!(
+        r#"{{"version": 0, "state": {{"pc": 1, "cir": 901, "mar": 0, "mdr": 901, "acc": 42, "ram": {ram:?}}}}}"#
+    );
+
+    let SavedState(mut state) = serde_json::from_str::<SavedState>(&old).unwrap();
+    assert_eq!((state.pc, state.cir, state.acc), (1, 901, 42));
+    assert_eq!(state.config, Config::default());
+
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    state.step(&mut io_handler).unwrap();
+    assert_eq!(io_handler.output_buffer, vec![Output::Int(42)]);
+}
+
+#[test]
+fn test_saved_state_errors() {
+    let state = serde_json::to_value(ExecutionState::new([0; 100])).unwrap();
+
+    let newer = format!(r#"{{"version": {}, "state": {state}}}"#, STATE_VERSION + 1);
+    let error = serde_json::from_str::<SavedState>(&newer).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Unsupported state version... 2"));
+
+    let unversioned = serde_json::json!({ "state": state });
+    assert!(serde_json::from_value::<SavedState>(unversioned).is_err());
+
+    // an old state can't be migrated once it's been read as the current layout
+    let out_of_order = format!(r#"{{"state": {state}, "version": 0}}"#);
+    let error = serde_json::from_str::<SavedState>(&out_of_order).unwrap_err();
+    assert!(error.to_string().starts_with("Saved state is version 0..."));
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn test_bincode_snapshots() {
+    let code = std::fs::read_to_string("./examples/fibonacci.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut state = ExecutionState::from_assembly(&assembly);
+    state.replay_inputs = vec![5, 4];
+
+    let bytes = state.to_bincode().unwrap();
+    assert!(bytes.len() < serde_json::to_vec(&state).unwrap().len());
+    let loaded = ExecutionState::from_bincode(&bytes).unwrap();
+    assert_eq!(loaded.ram, state.ram);
+    assert_eq!(loaded.replay_inputs, state.replay_inputs);
+
+    let loaded = lmc_assembly::Assembly::from_bincode(&assembly.to_bincode().unwrap()).unwrap();
+    assert_eq!(loaded, assembly);
+
+    assert!(ExecutionState::<100>::from_bincode(&bytes[..10]).is_err());
+
+    // the timeout isn't machine state, so setting it leaves the snapshot layout alone
+    let mut timed = state.clone();
+    timed.config.timeout = Some(std::time::Duration::from_secs(1));
+    assert_eq!(timed.to_bincode().unwrap(), bytes);
+    assert_eq!(
+        ExecutionState::<100>::from_bincode(&bytes)
+            .unwrap()
+            .config
+            .timeout,
+        None
+    );
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn test_msgpack_snapshots() {
+    let code = std::fs::read_to_string("./examples/fibonacci.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let state = ExecutionState::from_assembly(&assembly);
+
+    let bytes = state.to_msgpack().unwrap();
+    assert!(bytes.len() < serde_json::to_vec(&state).unwrap().len());
+    let loaded = ExecutionState::from_msgpack(&bytes).unwrap();
+    assert_eq!(loaded.ram, state.ram);
+    assert_eq!(loaded.kinds, state.kinds);
+
+    let loaded = lmc_assembly::Assembly::from_msgpack(&assembly.to_msgpack().unwrap()).unwrap();
+    assert_eq!(loaded, assembly);
+}
@@ -0,0 +1,2207 @@
+use lmc_assembly::{
+    self, Access, BrpMode, CompiledProgram, Config, CycleCosts, DecodeError, DecodedInstruction,
+    EndOfInput, ExecutionObserver, ExecutionState, Expr, Extensions, HaltReason, InputResponse,
+    IoError, MemoryRead, MemoryWrite, OnWrite, Output, OverflowPolicy, PcOverflow, Profile,
+    Register, RegisterChange, RuntimeError, RuntimeWarning, Scheduler, Session, SessionStatus,
+    StateDiff, StepEvent, StepOutcome, Timer, UninitializedReads, WatchpointHit, LMC, LMCIO,
+};
+
+struct TestIO {
+    input_buffer: Vec<i16>,
+    output_buffer: Vec<Output>,
+}
+
+impl LMCIO for TestIO {
+    fn get_input(&mut self) -> i16 {
+        self.input_buffer.pop().unwrap()
+    }
+
+    fn print_output(&mut self, val: Output) {
+        self.output_buffer.push(val);
+    }
+}
+
+fn assemble(code: &str) -> [i16; 100] {
+    let program = lmc_assembly::parse(code, false).unwrap();
+    lmc_assembly::assemble(program).unwrap()
+}
+
+#[test]
+fn test_run_result_final_state() {
+    let assembled = assemble("INP\nSTA 9\nINP\nADD 9\nSTA 9\nHLT");
+    let mut io_handler = TestIO {
+        input_buffer: vec![4, 5],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::Halted);
+    assert_eq!(result.steps, 6);
+    assert_eq!(result.state.acc, 9);
+    assert_eq!(result.state.ram[9], 9);
+}
+
+#[test]
+fn test_run_past_last_mailbox() {
+    let mut assembled = [0; 100];
+    assembled[..2].copy_from_slice(&[600 + 99, 0]);
+    assembled[99] = 902;
+
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::PcOutOfRange);
+    assert_eq!(io_handler.output_buffer, vec![Output::Int(0)]);
+}
+
+#[test]
+fn test_pc_overflow_policies() {
+    let mut assembled = [0; 100];
+    assembled[..3].copy_from_slice(&[600 + 99, 0, 0]);
+    assembled[99] = 902;
+
+    let run_with = |pc_overflow| {
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        let config = Config {
+            max_steps: Some(10),
+            pc_overflow,
+            ..Config::default()
+        };
+        let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config);
+        (result, io_handler.output_buffer)
+    };
+
+    let (result, _) = run_with(PcOverflow::Halt);
+    assert_eq!(result.unwrap().halt_reason, HaltReason::PcOutOfRange);
+
+    // wraps round to the BRA at 0, which goes straight back to the OUT at 99
+    let (result, outputs) = run_with(PcOverflow::Wrap);
+    let result = result.unwrap();
+    assert_eq!(result.halt_reason, HaltReason::StepLimit);
+    assert_eq!(outputs.len(), 5);
+
+    let (result, outputs) = run_with(PcOverflow::Error);
+    assert_eq!(result.unwrap_err(), RuntimeError::PcOutOfRange { pc: 100 });
+    assert_eq!(outputs, vec![Output::Int(0)]);
+}
+
+#[test]
+fn test_step_limit() {
+    let assembled = assemble("LOOP BRA LOOP");
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    let config = Config {
+        max_steps: Some(500),
+        ..Config::default()
+    };
+
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::StepLimit);
+    assert_eq!(result.steps, 500);
+}
+
+#[test]
+fn test_timeout() {
+    let assembled = assemble("LOOP BRA LOOP");
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    let config = Config {
+        max_steps: None,
+        timeout: Some(std::time::Duration::from_millis(20)),
+        ..Config::default()
+    };
+
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::Timeout);
+    assert_eq!(result.steps % lmc_assembly::TIMEOUT_CHECK_INTERVAL, 0);
+
+    // the clock is read before the first step
+    let config = Config {
+        max_steps: Some(500),
+        timeout: Some(std::time::Duration::ZERO),
+        ..Config::default()
+    };
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+    assert_eq!(result.halt_reason, HaltReason::Timeout);
+    assert_eq!(result.steps, 0);
+}
+
+#[test]
+fn test_invalid_instruction_halt_reason() {
+    let assembled = assemble("INP\nDAT 950");
+    let mut io_handler = TestIO {
+        input_buffer: vec![7],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::InvalidInstruction { word: 950, addr: 1 }
+    );
+}
+
+#[test]
+fn test_step_over_most_negative_word() {
+    let all = Extensions {
+        indirect: true,
+        stack: true,
+        mul_div: true,
+        interrupts: true,
+    };
+
+    for extensions in [Extensions::default(), all] {
+        let mut state = ExecutionState::new([0; 100]);
+        state.ram[0] = i16::MIN;
+        state.config.extensions = extensions;
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+
+        assert_eq!(
+            state.step(&mut io_handler).unwrap().halt_reason,
+            Some(HaltReason::InvalidInstruction {
+                word: i16::MIN,
+                addr: 0
+            })
+        );
+        assert_eq!(
+            DecodedInstruction::decode_with(i16::MIN, 100, &extensions),
+            Err(DecodeError { word: i16::MIN })
+        );
+        let encoding = lmc_assembly::Encoding::default().with_extensions(&extensions);
+        assert_eq!(
+            encoding.decode_instruction(i16::MIN),
+            Err(DecodeError { word: i16::MIN })
+        );
+    }
+}
+
+#[test]
+fn test_input_out_of_range_halt_reason() {
+    let assembled = assemble("INP\nHLT");
+    let mut io_handler = TestIO {
+        input_buffer: vec![1000],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::InputOutOfRange {
+            addr: 0,
+            value: 1000
+        }
+    );
+}
+
+#[test]
+fn test_watchpoint_pauses_on_write() {
+    let mut state = ExecutionState::new(assemble("INP\nSTA 9\nLDA 9\nHLT"));
+    state.watch(9, false, true);
+    let mut io_handler = TestIO {
+        input_buffer: vec![42],
+        output_buffer: vec![],
+    };
+
+    assert_eq!(state.step(&mut io_handler).unwrap().halt_reason, None);
+    assert_eq!(
+        state.step(&mut io_handler).unwrap().halt_reason,
+        Some(HaltReason::Watchpoint(WatchpointHit {
+            address: 9,
+            access: Access::Write,
+            old: 0,
+            new: 42,
+            pc: 1,
+            instruction: 309,
+        }))
+    );
+
+    // reads aren't watched, so execution carries on to the HLT
+    assert_eq!(state.step(&mut io_handler).unwrap().halt_reason, None);
+    assert_eq!(
+        state.step(&mut io_handler).unwrap().halt_reason,
+        Some(HaltReason::Halted)
+    );
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    // stop as soon as the counter has been decremented from 4 to 3, before it's stored
+    state.break_when("acc == 3 && RAM[12] > 3").unwrap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![5],
+        output_buffer: vec![],
+    };
+
+    let halt_reason = loop {
+        if let Some(halt_reason) = state.step(&mut io_handler).unwrap().halt_reason {
+            break halt_reason;
+        }
+    };
+
+    assert_eq!(
+        halt_reason,
+        HaltReason::Breakpoint("ACC == 3 && RAM[12] > 3".parse().unwrap())
+    );
+    assert_eq!(
+        io_handler.output_buffer,
+        vec![Output::Int(5), Output::Int(4)]
+    );
+}
+
+#[test]
+fn test_breakpoint_error() {
+    let mut state = ExecutionState::new(assemble("LDA 0\nHLT"));
+    state.break_when("RAM[ACC] == 0").unwrap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let error = state.step(&mut io_handler).unwrap_err();
+    assert_eq!(
+        error,
+        RuntimeError::Breakpoint {
+            condition: "RAM[ACC] == 0".parse().unwrap(),
+            message: "Address out of range: 500".to_string(),
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "Invalid breakpoint RAM[ACC] == 0... Address out of range: 500"
+    );
+}
+
+#[test]
+fn test_expression_parsing() {
+    let expr: Expr = "-(ACC + 1) <= RAM[PC - 1]".parse().unwrap();
+    assert_eq!(expr.to_string(), "-(ACC + 1) <= RAM[PC - 1]");
+
+    assert!("ACC ==".parse::<Expr>().is_err());
+    assert!("FOO > 1".parse::<Expr>().is_err());
+    assert!("ACC = 1".parse::<Expr>().is_err());
+
+    // deep nesting is an error rather than a stack overflow
+    let nested = format!("{}ACC{}", "(".repeat(100_000), ")".repeat(100_000));
+    assert_eq!(
+        nested.parse::<Expr>(),
+        Err("Expression is nested too deeply... more than 64 levels".to_string())
+    );
+    assert!("-".repeat(100_000).parse::<Expr>().is_err());
+    let nested = format!("{}ACC{}", "(".repeat(60), ")".repeat(60));
+    assert_eq!(nested.parse::<Expr>(), Ok(Expr::Register(Register::Acc)));
+}
+
+#[test]
+fn test_expression_overflow() {
+    let mut state = ExecutionState::new([0; 100]);
+    state.acc = 1;
+
+    let expr: Expr = "ACC + 2147483647".parse().unwrap();
+    assert_eq!(
+        expr.eval(&state),
+        Err("Overflow in expression... ACC + 2147483647".to_string())
+    );
+    let expr: Expr = "-(-2147483647 - 1)".parse().unwrap();
+    assert!(expr.eval(&state).unwrap_err().starts_with("Overflow"));
+    let expr: Expr = "-2147483647 - ACC - ACC".parse().unwrap();
+    assert!(expr.eval(&state).is_err());
+
+    let expr: Expr = "ACC + 2147483646".parse().unwrap();
+    assert_eq!(expr.eval(&state), Ok(i32::MAX));
+}
+
+#[test]
+fn test_trace_recording() {
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    state.start_trace();
+    let mut io_handler = TestIO {
+        input_buffer: vec![2, 1],
+        output_buffer: vec![],
+    };
+
+    while state.step(&mut io_handler).unwrap().halt_reason.is_none() {}
+
+    let trace = state.take_trace().unwrap();
+    let instructions: Vec<_> = trace
+        .steps
+        .iter()
+        .map(|step| step.event.instruction.unwrap().to_string())
+        .collect();
+
+    assert_eq!(
+        instructions,
+        vec!["INP", "STA 6", "INP", "ADD 6", "OUT", "HLT"]
+    );
+    assert_eq!(trace.inputs().collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(trace.outputs().collect::<Vec<_>>(), vec![Output::Int(3)]);
+    assert_eq!(
+        trace.writes().collect::<Vec<_>>(),
+        vec![&MemoryWrite {
+            address: 6,
+            old: 0,
+            new: 1
+        }]
+    );
+    assert_eq!(trace.steps[3].acc, 3);
+    assert!(state.trace.is_none());
+}
+
+#[test]
+fn test_step_back() {
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    state.record_history();
+    let mut io_handler = TestIO {
+        input_buffer: vec![20, 10],
+        output_buffer: vec![],
+    };
+
+    for _ in 0..4 {
+        state.step(&mut io_handler).unwrap();
+    }
+    assert_eq!(state.acc, 30);
+
+    // undo ADD, INP and STA
+    assert!(state.step_back());
+    assert!(state.step_back());
+    assert!(state.step_back());
+    assert_eq!((state.pc, state.acc, state.ram[6]), (1, 10, 0));
+
+    // the inputs that were un-consumed are replayed rather than requested again
+    while state.step(&mut io_handler).unwrap().halt_reason.is_none() {}
+    assert_eq!(io_handler.output_buffer, vec![Output::Int(30)]);
+
+    while state.step_back() {}
+    assert_eq!((state.pc, state.acc), (0, 0));
+    assert_eq!(state.replay_inputs, vec![20, 10]);
+}
+
+#[test]
+fn test_step_back_counts() {
+    let code = "        INP\n        STA 20\n        LDA 20\n        OUT\n        HLT\n";
+    let assembly = lmc_assembly::build(code, &lmc_assembly::BuildOptions::default()).unwrap();
+    let mut state = ExecutionState::from_assembly(&assembly);
+    state.record_history();
+    state.start_profile();
+    state.start_heatmap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![7],
+        output_buffer: vec![],
+    };
+
+    state.step(&mut io_handler).unwrap();
+    state.step(&mut io_handler).unwrap();
+    // steps from before the trace was started aren't taken out of it
+    state.start_trace();
+    while state.step(&mut io_handler).unwrap().halt_reason.is_none() {}
+    assert!(state.initialized[20]);
+
+    assert!(state.step_back());
+    let profile = state.profile.as_ref().unwrap();
+    assert_eq!((profile.count("HLT"), profile.count("OUT")), (0, 1));
+    assert_eq!(state.trace.as_ref().unwrap().steps.len(), 2);
+
+    while state.step_back() {}
+    let profile = state.profile.as_ref().unwrap();
+    assert!(profile.executions.iter().all(|count| *count == 0));
+    assert!(profile.opcodes.is_empty());
+    let heatmap = state.heatmap.as_ref().unwrap();
+    assert_eq!((heatmap.reads[20], heatmap.writes[20]), (0, 0));
+    assert!(state.trace.as_ref().unwrap().steps.is_empty());
+    assert!(!state.executed.iter().any(|executed| *executed));
+    assert!(!state.initialized[20]);
+    assert_eq!((state.steps_executed, state.cycles), (0, 0));
+}
+
+#[test]
+fn test_snapshot_and_restore() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    let mut io_handler = TestIO {
+        input_buffer: vec![3],
+        output_buffer: vec![],
+    };
+
+    for _ in 0..2 {
+        state.step(&mut io_handler).unwrap();
+    }
+    let snapshot = state.snapshot();
+
+    while state.step(&mut io_handler).unwrap().halt_reason.is_none() {}
+    assert_eq!(state.ram[12], 0);
+
+    state.restore(&snapshot);
+    assert_eq!((state.pc, state.acc, state.ram[12]), (2, 3, 3));
+}
+
+#[test]
+fn test_step_events() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    let mut io_handler = TestIO {
+        input_buffer: vec![1],
+        output_buffer: vec![],
+    };
+
+    let events: Vec<StepEvent> = (0..7)
+        .map(|_| state.step(&mut io_handler).unwrap())
+        .collect();
+
+    assert_eq!(events[0].input, Some(1));
+    assert_eq!(
+        events[1].write,
+        Some(MemoryWrite {
+            address: 12,
+            old: 0,
+            new: 1
+        })
+    );
+    assert_eq!(
+        events[2].read,
+        Some(MemoryRead {
+            address: 12,
+            value: 1
+        })
+    );
+    assert_eq!(events[3].output, Some(Output::Int(1)));
+    // ACC is 0 after the SUB, so BRZ is taken
+    assert_eq!((events[6].pc, events[6].branch), (6, Some(true)));
+    assert_eq!(state.pc, 8);
+}
+
+#[test]
+fn test_decode_instruction() {
+    assert_eq!(
+        DecodedInstruction::decode(512),
+        Ok(DecodedInstruction::Lda(12))
+    );
+    assert_eq!(DecodedInstruction::decode(922), Ok(DecodedInstruction::Otc));
+    assert_eq!(
+        DecodedInstruction::decode(450),
+        Err(DecodeError { word: 450 })
+    );
+    assert_eq!(
+        DecodedInstruction::decode(-1),
+        Err(DecodeError { word: -1 })
+    );
+}
+
+#[test]
+fn test_brp_negative_flag() {
+    let code = "        LDA NEG\n        BRP POS\n        OUT\nPOS     HLT\nNEG     DAT -5\n";
+
+    let run_with = |brp_mode| {
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        let config = Config {
+            brp_mode,
+            ..Config::default()
+        };
+        lmc_assembly::run_with_config(assemble(code), &mut io_handler, false, config).unwrap();
+        io_handler.output_buffer
+    };
+
+    // LDA clears the negative flag, so BRP branches even though ACC holds -5
+    assert_eq!(run_with(BrpMode::NegativeFlag), vec![]);
+    assert_eq!(run_with(BrpMode::SignedAccumulator), vec![Output::Int(-5)]);
+}
+
+#[test]
+fn test_overflow_policies() {
+    let code = "LDA 4\nADD 5\nOUT\nHLT\nDAT 999\nDAT 2";
+
+    let run_with = |overflow| {
+        let mut state = ExecutionState::with_config(
+            assemble(code),
+            Config {
+                overflow,
+                ..Config::default()
+            },
+        );
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        state.step(&mut io_handler).unwrap();
+        let event = state.step(&mut io_handler).unwrap();
+        (state.acc, state.overflow, event)
+    };
+
+    let (acc, overflow, event) = run_with(OverflowPolicy::Wrap);
+    assert_eq!((acc, overflow, event.overflow), (-998, true, Some(1001)));
+    assert_eq!(run_with(OverflowPolicy::Clamp).0, 999);
+    assert_eq!(run_with(OverflowPolicy::Flag).0, 1);
+
+    let (acc, _, event) = run_with(OverflowPolicy::Error);
+    assert_eq!(acc, 999);
+    assert_eq!(
+        event.halt_reason,
+        Some(HaltReason::Overflow {
+            addr: 1,
+            result: 1001
+        })
+    );
+}
+
+#[test]
+fn test_resume_with_state() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    state.break_when("RAM[12] == 2").unwrap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![4],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run_with_state(state, &mut io_handler, false).unwrap();
+    assert!(matches!(result.halt_reason, HaltReason::Breakpoint(_)));
+
+    let mut state = result.state;
+    state.breakpoints.clear();
+    let result = lmc_assembly::run_with_state(state, &mut io_handler, false).unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::Halted);
+    assert_eq!(
+        io_handler.output_buffer,
+        (0..=4).rev().map(Output::Int).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_steps_iterator() {
+    let code = std::fs::read_to_string("./examples/fibonacci.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    let mut io_handler = TestIO {
+        input_buffer: vec![10],
+        output_buffer: vec![],
+    };
+
+    let fourth_output = state
+        .steps(&mut io_handler)
+        .map(Result::unwrap)
+        .filter_map(|event| event.output)
+        .nth(3);
+    assert_eq!(fourth_output, Some(Output::Int(2)));
+
+    // the iterator stops at the HLT
+    let remaining: Vec<_> = state.steps(&mut io_handler).map(Result::unwrap).collect();
+    assert_eq!(
+        remaining.last().unwrap().halt_reason,
+        Some(HaltReason::Halted)
+    );
+}
+
+#[test]
+fn test_cycle_accounting() {
+    // two passes of LDA, SUB, STA, BRZ, BRA, then LDA, SUB, STA, BRZ, HLT
+    let code = "LOOP    LDA N\n        SUB ONE\n        STA N\n        BRZ END\n        BRA LOOP\nEND     HLT\nN       DAT 3\nONE     DAT 1";
+    let config = Config {
+        cycle_costs: CycleCosts {
+            memory: 2,
+            arithmetic: 3,
+            branch: 1,
+            io: 1,
+            halt: 0,
+        },
+        ..Config::default()
+    };
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let result =
+        lmc_assembly::run_with_config(assemble(code), &mut io_handler, false, config).unwrap();
+
+    assert_eq!(result.state.steps_executed, 15);
+    assert_eq!(
+        result.state.cycles,
+        2 * (2 + 2 + 3 + 1 + 1) + (2 + 2 + 3 + 1)
+    );
+
+    let mut state = result.state;
+    state.pc = 0;
+    state.ram[6] = 1;
+    state.record_history();
+    state.step(&mut io_handler).unwrap();
+    assert_eq!((state.steps_executed, state.cycles), (16, 28));
+    state.step_back();
+    assert_eq!((state.steps_executed, state.cycles), (15, 26));
+}
+
+#[test]
+fn test_profile() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    let mut io_handler = TestIO {
+        input_buffer: vec![3],
+        output_buffer: vec![],
+    };
+    state.start_profile();
+
+    state.steps(&mut io_handler).for_each(|event| {
+        event.unwrap();
+    });
+    let profile = state.take_profile().unwrap();
+
+    assert_eq!(profile.count("INP"), 1);
+    assert_eq!(profile.count("out"), 4);
+    assert_eq!(profile.count("MUL"), 0);
+    assert_eq!(profile.executions.iter().sum::<u64>(), state.steps_executed);
+    assert_eq!(profile.hot_spots()[0], (2, 3));
+}
+
+#[test]
+fn test_heatmap() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    let mut io_handler = TestIO {
+        input_buffer: vec![3],
+        output_buffer: vec![],
+    };
+    state.start_heatmap();
+
+    state.steps(&mut io_handler).for_each(|event| {
+        event.unwrap();
+    });
+    let heatmap = state.take_heatmap().unwrap();
+
+    // A (12) is stored after INP and on each pass, read on each pass and twice at the end
+    assert_eq!(heatmap.writes[12], 4);
+    assert_eq!(heatmap.reads[12], 3 + 2);
+    assert_eq!(heatmap.reads[13], 3);
+    assert_eq!(heatmap.accesses(12), 9);
+    assert_eq!(heatmap.accesses(0), 0);
+}
+
+#[test]
+fn test_self_modifying_code_warning() {
+    // overwrites the HLT at 3 before it has run, then the data at 6
+    let code = "        LDA 5\n        STA 3\n        STA 6\n        HLT\n        HLT\n        DAT 0\n        DAT 0";
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let assembly = lmc_assembly::Assembly::new(program, lmc_assembly::Encoding::default()).unwrap();
+    let mut state = ExecutionState::from_assembly(&assembly);
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let warnings: Vec<_> = state
+        .steps(&mut io_handler)
+        .flat_map(|event| event.unwrap().warnings)
+        .collect();
+
+    assert_eq!(
+        warnings,
+        vec![RuntimeWarning::SelfModifyingCode { address: 3, pc: 1 }]
+    );
+
+    // without assembler information, only writes to executed mailboxes are flagged
+    let mut state = ExecutionState::new(assemble("LDA 5\nSTA 0\nSTA 4\nHLT\nHLT\nDAT 0"));
+    let warnings: Vec<_> = state
+        .steps(&mut io_handler)
+        .flat_map(|event| event.unwrap().warnings)
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![RuntimeWarning::SelfModifyingCode { address: 0, pc: 1 }]
+    );
+}
+
+#[test]
+fn test_uninitialized_reads() {
+    // 6 is never written before it's read; 7 is written first
+    let code = "LDA 5\nSTA 7\nADD 7\nADD 6\nHLT\nDAT 1";
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let assembly = lmc_assembly::Assembly::new(program, lmc_assembly::Encoding::default()).unwrap();
+
+    let run_with = |uninitialized_reads| {
+        let mut state = ExecutionState::from_assembly(&assembly);
+        state.config.uninitialized_reads = uninitialized_reads;
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        let events: Vec<StepEvent> = state.steps(&mut io_handler).map(Result::unwrap).collect();
+        (state, events)
+    };
+
+    let (_, events) = run_with(UninitializedReads::Allow);
+    assert!(events.iter().all(|event| event.warnings.is_empty()));
+
+    let (_, events) = run_with(UninitializedReads::Warn);
+    assert_eq!(
+        events[3].warnings,
+        vec![RuntimeWarning::UninitializedRead { address: 6, pc: 3 }]
+    );
+    assert_eq!(events.last().unwrap().halt_reason, Some(HaltReason::Halted));
+
+    let (state, events) = run_with(UninitializedReads::Error);
+    assert_eq!(
+        events.last().unwrap().halt_reason,
+        Some(HaltReason::UninitializedRead {
+            addr: 3,
+            address: 6
+        })
+    );
+    assert_eq!(state.acc, 2);
+}
+
+#[test]
+fn test_extended_machine() {
+    let program = lmc_assembly::parse("INP\nSTA 500\nADD 500\nOUT\nHLT", false).unwrap();
+    let assembled = lmc_assembly::assemble_sized::<1000>(program).unwrap();
+    assert_eq!(assembled[..5], [9001, 3500, 1500, 9002, 0]);
+
+    let mut io_handler = TestIO {
+        input_buffer: vec![4000],
+        output_buffer: vec![],
+    };
+    let result =
+        lmc_assembly::run_with_state(ExecutionState::new(assembled), &mut io_handler, false)
+            .unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::Halted);
+    assert_eq!(result.state.ram[500], 4000);
+    assert_eq!(io_handler.output_buffer, vec![Output::Int(8000)]);
+    assert_eq!(ExecutionState::<1000>::MAX_VALUE, 9999);
+    assert_eq!(
+        DecodedInstruction::decode_sized(5123, 1000),
+        Ok(DecodedInstruction::Lda(123))
+    );
+    assert_eq!(
+        lmc_assembly::Encoding::for_size(1000).decode(5123),
+        Some(("LDA", Some(123)))
+    );
+}
+
+#[test]
+fn test_character_input() {
+    struct CharIO(Vec<char>, Vec<Output>);
+
+    impl LMCIO for CharIO {
+        fn get_input(&mut self) -> i16 {
+            unreachable!()
+        }
+
+        fn print_output(&mut self, val: Output) {
+            self.1.push(val);
+        }
+
+        fn get_char_input(&mut self) -> char {
+            self.0.pop().unwrap()
+        }
+    }
+
+    let assembled = assemble("INA\nOUT\nINA\nOTC\nHLT");
+    assert_eq!(assembled[..2], [912, 902]);
+
+    let mut io_handler = CharIO(vec!['i', 'A'], vec![]);
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(io_handler.1, vec![Output::Int(65), Output::Char('i')]);
+
+    // handlers that don't read characters fall back to character codes from get_input
+    let mut io_handler = TestIO {
+        input_buffer: vec![33, 72],
+        output_buffer: vec![],
+    };
+    lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(
+        io_handler.output_buffer,
+        vec![Output::Int(72), Output::Char('!')]
+    );
+}
+
+#[test]
+fn test_indirect_addressing() {
+    let code = "LOOP    LDA @PTR
+        ADD SUM
+        STA SUM
+        LDA PTR
+        ADD ONE
+        STA PTR
+        SUB LAST
+        BRZ DONE
+        BRA LOOP
+DONE    LDA SUM
+        STA @RESPTR
+        HLT
+PTR     DAT ARR
+LAST    DAT RES
+RESPTR  DAT RES
+ONE     DAT 1
+SUM     DAT 0
+ARR     DAT 3
+        DAT 4
+        DAT 5
+RES     DAT 0";
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let extensions = Extensions {
+        indirect: true,
+        ..Extensions::default()
+    };
+
+    assert_eq!(
+        lmc_assembly::assemble(program.clone()).unwrap_err(),
+        "Indirect addressing not enabled... LDA @PTR"
+    );
+    let encoding = lmc_assembly::Encoding::default().with_extensions(&extensions);
+    let assembled = lmc_assembly::assemble_with_encoding(program, &encoding).unwrap();
+    assert_eq!(assembled[0], -512);
+    assert_eq!(
+        encoding.decode_instruction(-512).unwrap().to_string(),
+        "LDA @12"
+    );
+
+    let config = Config {
+        extensions,
+        ..Config::default()
+    };
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+    assert_eq!(result.halt_reason, HaltReason::Halted);
+    assert_eq!(result.state.ram[20], 12);
+
+    // the standard machine doesn't know about indirect instructions
+    let result = lmc_assembly::run(assembled, &mut io_handler, false).unwrap();
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::InvalidInstruction {
+            word: -512,
+            addr: 0
+        }
+    );
+}
+
+#[test]
+fn test_indirect_through_invalid_pointer() {
+    let config = Config {
+        extensions: Extensions {
+            indirect: true,
+            ..Extensions::default()
+        },
+        ..Config::default()
+    };
+    let mut assembled = [0; 100];
+    assembled[..2].copy_from_slice(&[-301, 150]);
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::InvalidAddress {
+            addr: 0,
+            address: 150
+        }
+    );
+}
+
+#[test]
+fn test_stack_extension() {
+    let code = "        LDA A
+        PUSH
+        LDA B
+        CALL DOUBLE
+        OUT
+        POP
+        OUT
+        HLT
+DOUBLE  STA TMP
+        ADD TMP
+        RET
+A       DAT 7
+B       DAT 20
+TMP     DAT 0";
+    let extensions = Extensions {
+        stack: true,
+        ..Extensions::default()
+    };
+    let encoding = lmc_assembly::Encoding::default().with_extensions(&extensions);
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let assembled = lmc_assembly::assemble_with_encoding(program, &encoding).unwrap();
+    assert_eq!(assembled[..4], [511, 930, 512, -608]);
+    assert_eq!(
+        encoding.decode_instruction(-608),
+        Ok(DecodedInstruction::Call(8))
+    );
+
+    let mut state = ExecutionState::with_config(
+        assembled,
+        Config {
+            extensions,
+            ..Config::default()
+        },
+    );
+    state.record_history();
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    for _ in 0..4 {
+        state.step(&mut io_handler).unwrap();
+    }
+    // 7 and the return address are on the stack
+    assert_eq!((state.sp, state.ram[98], state.ram[99]), (98, 4, 7));
+    state.step_back();
+    assert_eq!(state.sp, 99);
+
+    let events: Vec<StepEvent> = state.steps(&mut io_handler).map(Result::unwrap).collect();
+    assert_eq!(events.last().unwrap().halt_reason, Some(HaltReason::Halted));
+    assert_eq!(
+        io_handler.output_buffer,
+        vec![Output::Int(40), Output::Int(7)]
+    );
+    assert_eq!(state.sp, 100);
+}
+
+#[test]
+fn test_stack_underflow() {
+    let config = Config {
+        extensions: Extensions {
+            stack: true,
+            ..Extensions::default()
+        },
+        ..Config::default()
+    };
+    let mut assembled = [0; 100];
+    assembled[0] = 932;
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+
+    assert_eq!(result.halt_reason, HaltReason::StackUnderflow { addr: 0 });
+}
+
+#[test]
+fn test_mul_div_extension() {
+    let code =
+        "LDA A\nMUL B\nOUT\nDIV C\nOUT\nDIV ZERO\nHLT\nA DAT 12\nB DAT -7\nC DAT 5\nZERO DAT 0";
+    let extensions = Extensions {
+        mul_div: true,
+        ..Extensions::default()
+    };
+    let encoding = lmc_assembly::Encoding::default().with_extensions(&extensions);
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let assembled = lmc_assembly::assemble_with_encoding(program, &encoding).unwrap();
+    assert_eq!(assembled[..4], [507, 408, 902, -409]);
+    assert_eq!(
+        lmc_assembly::disassemble(&assembled, &encoding)[3]
+            .1
+            .to_string(),
+        "DIV 9"
+    );
+
+    let config = Config {
+        extensions,
+        ..Config::default()
+    };
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+
+    assert_eq!(
+        io_handler.output_buffer,
+        vec![Output::Int(-84), Output::Int(-16)]
+    );
+    assert_eq!(result.halt_reason, HaltReason::DivisionByZero { addr: 5 });
+    assert!(result.state.negative);
+}
+
+#[test]
+fn test_div_overflow() {
+    let extensions = Extensions {
+        mul_div: true,
+        ..Extensions::default()
+    };
+    let mut assembled = [0; 100];
+    // LDA 10, DIV 11, HLT
+    assembled[..3].copy_from_slice(&[510, -411, 0]);
+    assembled[10] = i16::MIN;
+    assembled[11] = -1;
+    let run = |overflow| {
+        let config = Config {
+            extensions,
+            overflow,
+            ..Config::default()
+        };
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap()
+    };
+
+    let result = run(OverflowPolicy::Error);
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::Overflow {
+            addr: 1,
+            result: 32768
+        }
+    );
+
+    let result = run(OverflowPolicy::Clamp);
+    assert_eq!(result.halt_reason, HaltReason::Halted);
+    assert_eq!(result.state.acc, 999);
+    assert!(result.state.overflow);
+}
+
+#[test]
+fn test_mul_overflow_policy() {
+    let code = "LDA A\nMUL A\nHLT\nA DAT 40";
+    let extensions = Extensions {
+        mul_div: true,
+        ..Extensions::default()
+    };
+    let encoding = lmc_assembly::Encoding::default().with_extensions(&extensions);
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let assembled = lmc_assembly::assemble_with_encoding(program, &encoding).unwrap();
+
+    let mut state = ExecutionState::with_config(
+        assembled,
+        Config {
+            extensions,
+            overflow: OverflowPolicy::Error,
+            ..Config::default()
+        },
+    );
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    state.step(&mut io_handler).unwrap();
+    let event = state.step(&mut io_handler).unwrap();
+
+    assert_eq!(event.overflow, Some(1600));
+    assert_eq!(
+        event.halt_reason,
+        Some(HaltReason::Overflow {
+            addr: 1,
+            result: 1600
+        })
+    );
+}
+
+#[test]
+fn test_timer_interrupt() {
+    let code = "LOOP    LDA N
+        SUB ONE
+        STA N
+        BRZ DONE
+        BRA LOOP
+DONE    LDA TICKS
+        OUT
+        HLT
+TICK    STA SAVE
+        LDA TICKS
+        ADD ONE
+        STA TICKS
+        LDA SAVE
+        RTI
+N       DAT 10
+ONE     DAT 1
+TICKS   DAT 0
+SAVE    DAT 0";
+    let extensions = Extensions {
+        interrupts: true,
+        ..Extensions::default()
+    };
+    let encoding = lmc_assembly::Encoding::default().with_extensions(&extensions);
+    let program = lmc_assembly::parse(code, false).unwrap();
+    let assembled = lmc_assembly::assemble_with_encoding(program, &encoding).unwrap();
+    assert_eq!(assembled[13], 940);
+
+    let mut state = ExecutionState::with_config(
+        assembled,
+        Config {
+            extensions,
+            timer: Some(Timer {
+                period: 10,
+                vector: 8,
+            }),
+            ..Config::default()
+        },
+    );
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let events: Vec<StepEvent> = state.steps(&mut io_handler).map(Result::unwrap).collect();
+    let interrupts: Vec<usize> = (0..events.len())
+        .filter(|i| events[*i].interrupted)
+        .collect();
+
+    assert!(interrupts.len() > 1);
+    for i in &interrupts {
+        assert_eq!(events[i + 1].pc, 8);
+    }
+    assert_eq!(events.last().unwrap().halt_reason, Some(HaltReason::Halted));
+    // the loop still ran to completion, and the handler counted every tick
+    assert_eq!(state.ram[14], 0);
+    assert_eq!(state.ram[16] as usize, interrupts.len());
+}
+
+#[test]
+fn test_return_without_interrupt() {
+    let config = Config {
+        extensions: Extensions {
+            interrupts: true,
+            ..Extensions::default()
+        },
+        ..Config::default()
+    };
+    let mut assembled = [0; 100];
+    assembled[0] = 940;
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run_with_config(assembled, &mut io_handler, false, config).unwrap();
+
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::ReturnWithoutInterrupt { addr: 0 }
+    );
+}
+
+#[test]
+fn test_scheduler_round_robin() {
+    let mut scheduler: Scheduler<Box<dyn LMCIO>> = Scheduler::with_quantum(2);
+    let countdown = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    for input in [3, 5] {
+        scheduler.spawn(
+            ExecutionState::new(assemble(&countdown)),
+            Box::new(TestIO {
+                input_buffer: vec![input],
+                output_buffer: vec![],
+            }),
+        );
+    }
+    scheduler.spawn(
+        ExecutionState::new(assemble("LOOP BRA LOOP")),
+        Box::new(TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        }),
+    );
+    scheduler.machine_mut(2).unwrap().state.config.max_steps = Some(10);
+
+    let order: Vec<usize> = (0..6).map(|_| scheduler.step().unwrap().0).collect();
+    assert_eq!(order, vec![0, 0, 1, 1, 2, 2]);
+
+    scheduler.run();
+    assert!(scheduler.is_finished());
+
+    let reasons: Vec<_> = scheduler
+        .machines()
+        .iter()
+        .map(|machine| machine.halt_reason.clone())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            Some(HaltReason::Halted),
+            Some(HaltReason::Halted),
+            Some(HaltReason::StepLimit)
+        ]
+    );
+    assert_eq!(scheduler.machine(1).unwrap().state.steps_executed, 35);
+
+    // halts are final, so resuming does nothing
+    assert!(!scheduler.resume(0));
+    assert!(!scheduler.resume(2));
+    assert!(!scheduler.resume(7));
+    assert!(scheduler.is_finished());
+    assert_eq!(scheduler.step().map(|(id, _)| id), None);
+    assert_eq!(scheduler.machine(1).unwrap().state.steps_executed, 35);
+
+    // a machine paused at a breakpoint picks up where it left off
+    let mut scheduler: Scheduler<Box<dyn LMCIO>> = Scheduler::new();
+    let mut state = ExecutionState::new(assemble(&countdown));
+    state.break_when("PC == 8").unwrap();
+    scheduler.spawn(
+        state,
+        Box::new(TestIO {
+            input_buffer: vec![3],
+            output_buffer: vec![],
+        }),
+    );
+    scheduler.run();
+    assert!(matches!(
+        scheduler.machine(0).unwrap().halt_reason,
+        Some(HaltReason::Breakpoint(_))
+    ));
+    assert!(scheduler.resume(0));
+    scheduler.run();
+    assert_eq!(
+        scheduler.machine(0).unwrap().halt_reason,
+        Some(HaltReason::Halted)
+    );
+}
+
+#[test]
+fn test_session() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut session = Session::new(ExecutionState::new(assemble(&code)));
+
+    assert_eq!(session.step_n(10).unwrap(), vec![]);
+    assert_eq!(session.status(), &SessionStatus::NeedsInput);
+
+    session.provide_input(3);
+    assert_eq!(session.step_n(5).unwrap().len(), 5);
+    assert_eq!(session.take_outputs(), vec![Output::Int(3)]);
+
+    session.pause();
+    assert_eq!(session.step_n(5).unwrap(), vec![]);
+    // single steps still work while paused
+    assert!(session.step().unwrap().is_ok());
+    assert_eq!(session.status(), &SessionStatus::Paused);
+
+    session.state_mut().break_when("ACC == 1").unwrap();
+    session.resume();
+    session.step_n(100).unwrap();
+    assert_eq!(session.status(), &SessionStatus::Paused);
+    assert_eq!(session.state().acc, 1);
+
+    session.state_mut().breakpoints.clear();
+    session.resume();
+    session.step_n(100).unwrap();
+    assert_eq!(
+        session.status(),
+        &SessionStatus::Stopped(HaltReason::Halted)
+    );
+    assert_eq!(
+        session.take_outputs(),
+        vec![Output::Int(2), Output::Int(1), Output::Int(0)]
+    );
+}
+
+#[test]
+fn test_execution_observer() {
+    struct MaxAcc {
+        before: u64,
+        max_acc: i16,
+    }
+
+    impl ExecutionObserver for MaxAcc {
+        fn before_step(&mut self, _state: &ExecutionState) {
+            self.before += 1;
+        }
+
+        fn after_step(&mut self, state: &ExecutionState, _event: &StepEvent) {
+            self.max_acc = self.max_acc.max(state.acc);
+        }
+    }
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![3],
+        output_buffer: vec![],
+    };
+    let mut observer = (
+        MaxAcc {
+            before: 0,
+            max_acc: 0,
+        },
+        Profile::new(100),
+    );
+
+    let result = lmc_assembly::run_observed(
+        ExecutionState::new(assemble(&code)),
+        &mut io_handler,
+        &mut observer,
+    )
+    .unwrap();
+
+    let (max_acc, profile) = observer;
+    assert_eq!(max_acc.before, result.steps);
+    assert_eq!(max_acc.max_acc, 3);
+    assert_eq!(profile.count("OUT"), 4);
+    assert_eq!(profile.executions.iter().sum::<u64>(), result.steps);
+}
+
+#[test]
+fn test_on_write() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![2],
+        output_buffer: vec![],
+    };
+    let mut writes = vec![];
+
+    lmc_assembly::run_observed(
+        ExecutionState::new(assemble(&code)),
+        &mut io_handler,
+        &mut OnWrite(|address, old, new| writes.push((address, old, new))),
+    )
+    .unwrap();
+
+    assert_eq!(writes, vec![(12, 0, 2), (12, 2, 1), (12, 1, 0)]);
+}
+
+#[test]
+fn test_register_changes() {
+    let mut state = ExecutionState::new(assemble("LDA FIVE\nHLT\nFIVE DAT 5"));
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+
+    let event = state.step(&mut io_handler).unwrap();
+    assert_eq!(
+        event.registers,
+        vec![
+            RegisterChange {
+                register: Register::Acc,
+                old: 0,
+                new: 5,
+            },
+            RegisterChange {
+                register: Register::Pc,
+                old: 0,
+                new: 1,
+            },
+            RegisterChange {
+                register: Register::Mar,
+                old: 0,
+                new: 2,
+            },
+            RegisterChange {
+                register: Register::Mdr,
+                old: 0,
+                new: 502,
+            },
+            RegisterChange {
+                register: Register::Cir,
+                old: 0,
+                new: 502,
+            },
+        ]
+    );
+
+    // HLT leaves the accumulator alone
+    let event = state.step(&mut io_handler).unwrap();
+    assert!(event
+        .registers
+        .iter()
+        .all(|change| change.register != Register::Acc));
+}
+
+#[test]
+fn test_builder() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut lmc = LMC::builder()
+        .memory(assemble(&code))
+        .max_steps(10)
+        .overflow(OverflowPolicy::Clamp)
+        .io(TestIO {
+            input_buffer: vec![5],
+            output_buffer: vec![],
+        })
+        .build();
+
+    assert_eq!(lmc.state.config.overflow, OverflowPolicy::Clamp);
+    assert_eq!(lmc.run(), Ok(HaltReason::StepLimit));
+    assert_eq!(lmc.state.steps_executed, 10);
+
+    let (_, io_handler) = lmc.into_parts();
+    assert_eq!(
+        io_handler.output_buffer,
+        vec![Output::Int(5), Output::Int(4)]
+    );
+}
+
+#[test]
+fn test_fallible_io() {
+    struct ClosedIO;
+
+    impl LMCIO for ClosedIO {
+        fn get_input(&mut self) -> i16 {
+            unreachable!()
+        }
+
+        fn print_output(&mut self, _val: Output) {}
+
+        fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+            Err(IoError::InvalidInput("abc".to_string()))
+        }
+    }
+
+    let result = lmc_assembly::run(assemble("INP\nOUT\nHLT"), &mut ClosedIO, false).unwrap();
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::IoError("Invalid input... abc".to_string())
+    );
+    assert_eq!(result.steps, 1);
+}
+
+#[test]
+fn test_end_of_input() {
+    struct PipedIO(Vec<i16>);
+
+    impl LMCIO for PipedIO {
+        fn get_input(&mut self) -> i16 {
+            unreachable!()
+        }
+
+        fn print_output(&mut self, _val: Output) {}
+
+        fn try_get_input(&mut self) -> Result<InputResponse, IoError> {
+            Ok(match self.0.pop() {
+                Some(input) => InputResponse::Value(input),
+                None => InputResponse::Eof,
+            })
+        }
+    }
+
+    // echoes input until there's none left
+    let program = assemble("LOOP INP\nOUT\nBRA LOOP");
+    let run_with = |end_of_input| {
+        let config = Config {
+            end_of_input,
+            ..Config::default()
+        };
+        lmc_assembly::run_with_config(program, &mut PipedIO(vec![2, 1]), false, config)
+    };
+
+    let result = run_with(EndOfInput::Halt).unwrap();
+    assert_eq!(result.halt_reason, HaltReason::EndOfInput { addr: 0 });
+    assert_eq!(result.steps, 7);
+
+    assert_eq!(
+        run_with(EndOfInput::Error).unwrap_err(),
+        RuntimeError::EndOfInput { pc: 0 }
+    );
+}
+
+#[test]
+fn test_step_nonblocking() {
+    let mut state = ExecutionState::new(assemble("INP\nOUT\nHLT"));
+
+    assert_eq!(state.step_nonblocking(), Ok(StepOutcome::NeedsInput));
+    assert_eq!(state.pc, 0);
+
+    state.provide_input(7);
+    let Ok(StepOutcome::Stepped(event)) = state.step_nonblocking() else {
+        panic!("expected a step");
+    };
+    assert_eq!(event.input, Some(7));
+
+    let Ok(StepOutcome::Stepped(event)) = state.step_nonblocking() else {
+        panic!("expected a step");
+    };
+    assert_eq!(event.output, Some(Output::Int(7)));
+}
+
+#[test]
+fn test_strict_values() {
+    let mut program = assemble("LDA BAD\nOUT\nHLT\nBAD DAT 0");
+    program[3] = 1500;
+    let run_with = |strict_values| {
+        let config = Config {
+            strict_values,
+            ..Config::default()
+        };
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        lmc_assembly::run_with_config(program, &mut io_handler, false, config).unwrap()
+    };
+
+    assert_eq!(run_with(false).halt_reason, HaltReason::Halted);
+
+    let result = run_with(true);
+    assert_eq!(
+        result.halt_reason,
+        HaltReason::ValueOutOfRange {
+            addr: 0,
+            address: 3,
+            value: 1500,
+        }
+    );
+    assert_eq!(result.state.acc, 0);
+}
+
+#[test]
+fn test_execution_stats() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let mut io_handler = TestIO {
+        input_buffer: vec![3],
+        output_buffer: vec![],
+    };
+
+    let result = lmc_assembly::run(assemble(&code), &mut io_handler, false).unwrap();
+    let stats = result.stats;
+
+    assert_eq!(stats.steps, result.steps);
+    assert_eq!(stats.count("OUT"), 4);
+    assert_eq!(stats.count("BRA"), 2);
+    // BRZ falls through twice, then BRZ and both BRAs jump
+    assert_eq!((stats.branches_taken, stats.branches_not_taken), (3, 2));
+    assert_eq!((stats.inputs, stats.outputs), (1, 4));
+    assert_eq!(stats.peak_pc, 11);
+    assert_eq!(stats.addresses_touched(), 2);
+
+    assert!(stats.elapsed > std::time::Duration::ZERO);
+
+    let summary = stats.to_string();
+    assert!(summary.starts_with(&format!(
+        "steps: {} in {:.3?}\ninstructions: INP 1, STA ",
+        stats.steps, stats.elapsed
+    )));
+    assert!(summary.ends_with(
+        "branches: 3 taken, 2 not taken\ninputs: 1, outputs: 4\npeak pc: 11\nmailboxes touched: 2\n"
+    ));
+}
+
+#[test]
+fn test_max_outputs() {
+    let mut lmc = LMC::builder()
+        .memory(assemble("LOOP OUT\nBRA LOOP"))
+        .max_outputs(5)
+        .io(TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        })
+        .build();
+
+    assert_eq!(lmc.run(), Ok(HaltReason::OutputLimit));
+    assert_eq!(lmc.state.outputs_produced, 5);
+    assert_eq!(lmc.io_handler.output_buffer.len(), 5);
+    assert_eq!(lmc.state.steps_executed, 11);
+}
+
+#[test]
+fn test_predecode() {
+    // overwrites the HLT at TARGET with an OUT after the table has been built
+    let code = "        LDA OUTOP\n        STA TARGET\nTARGET  HLT\n        HLT\nOUTOP   DAT 902";
+    let run_with = |predecode| {
+        let config = Config {
+            predecode,
+            ..Config::default()
+        };
+        let mut io_handler = TestIO {
+            input_buffer: vec![],
+            output_buffer: vec![],
+        };
+        let result =
+            lmc_assembly::run_with_config(assemble(code), &mut io_handler, false, config).unwrap();
+        (result.halt_reason, result.steps, io_handler.output_buffer)
+    };
+
+    let expected = (HaltReason::Halted, 4, vec![Output::Int(902)]);
+    assert_eq!(run_with(false), expected);
+    assert_eq!(run_with(true), expected);
+
+    // writes from outside the program are picked up too
+    let config = Config {
+        predecode: true,
+        ..Config::default()
+    };
+    let mut state = ExecutionState::with_config(assemble("        OUT\n        HLT"), config);
+    state.record_history();
+    let mut io_handler = TestIO {
+        input_buffer: vec![],
+        output_buffer: vec![],
+    };
+    state.step(&mut io_handler).unwrap();
+    state.step_back();
+    // replaces the OUT, which has already been decoded
+    state.set_mailbox(0, 501);
+    let event = state.step(&mut io_handler).unwrap();
+    assert_eq!(event.instruction, Some(DecodedInstruction::Lda(1)));
+}
+
+#[test]
+fn test_compiled_program() {
+    let registers = |state: &ExecutionState| {
+        (
+            state.ram,
+            (state.pc, state.cir, state.mar, state.mdr, state.acc),
+            (state.negative, state.overflow),
+            (state.steps_executed, state.cycles),
+        )
+    };
+    let programs = [
+        (
+            std::fs::read_to_string("./examples/countdown.lmc").unwrap(),
+            vec![9],
+        ),
+        (
+            std::fs::read_to_string("./examples/multiplication.lmc").unwrap(),
+            vec![12, 34],
+        ),
+        // rewrites TARGET, which has already been compiled
+        (
+            "        LDA OUTOP\n        STA TARGET\nTARGET  HLT\n        HLT\nOUTOP   DAT 902"
+                .to_string(),
+            vec![],
+        ),
+    ];
+
+    for (code, inputs) in programs {
+        let mut io_handler = TestIO {
+            input_buffer: inputs.clone(),
+            output_buffer: vec![],
+        };
+        let expected = lmc_assembly::run(assemble(&code), &mut io_handler, false).unwrap();
+
+        let mut state = ExecutionState::new(assemble(&code));
+        let mut compiled_io = TestIO {
+            input_buffer: inputs,
+            output_buffer: vec![],
+        };
+        let compiled = CompiledProgram::compile(&state);
+        let halt_reason = compiled.run(&mut state, &mut compiled_io).unwrap();
+
+        assert_eq!(halt_reason, expected.halt_reason);
+        assert_eq!(registers(&state), registers(&expected.state));
+        assert_eq!(compiled_io.output_buffer, io_handler.output_buffer);
+    }
+}
+
+#[test]
+fn test_bench_measure() {
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembled = lmc_assembly::assemble(lmc_assembly::parse(&code, false).unwrap()).unwrap();
+
+    // countdown halts long before 1000 steps, so it gets restarted along the way
+    for measurement in [
+        lmc_assembly::bench::measure(assembled, 1000),
+        lmc_assembly::bench::measure_compiled(assembled, 1000),
+    ] {
+        assert_eq!(measurement.steps, 1000);
+        assert!(measurement.steps_per_second() > 0.0);
+    }
+}
+
+#[test]
+fn test_state_diff() {
+    let code = std::fs::read_to_string("./examples/multiplication.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    let mut mirror = state.clone();
+    let mut io_handler = TestIO {
+        input_buffer: vec![3, 4],
+        output_buffer: vec![],
+    };
+
+    let mut outputs = vec![];
+    loop {
+        let before = state.clone();
+        let diff = state.step_diff(&mut io_handler).unwrap();
+        assert_eq!(
+            StateDiff {
+                outputs: diff.outputs.clone(),
+                halt_reason: diff.halt_reason.clone(),
+                ..StateDiff::between(&before, &state)
+            },
+            diff
+        );
+
+        diff.apply(&mut mirror);
+        assert_eq!(mirror.ram, state.ram);
+        assert_eq!(
+            (mirror.acc, mirror.pc, mirror.mar, mirror.mdr, mirror.cir),
+            (state.acc, state.pc, state.mar, state.mdr, state.cir)
+        );
+        assert_eq!(
+            (mirror.negative, mirror.overflow),
+            (state.negative, state.overflow)
+        );
+        outputs.extend(diff.outputs);
+
+        if let Some(halt_reason) = diff.halt_reason {
+            assert_eq!(halt_reason, HaltReason::Halted);
+            break;
+        }
+    }
+
+    assert_eq!(outputs, vec![Output::Int(12)]);
+    assert!(StateDiff::between(&state, &mirror).is_empty());
+}
+
+#[test]
+fn test_debugger() {
+    use lmc_assembly::debugger::{Debugger, Response};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut debugger = Debugger::with_source(&assembly, &code);
+    let mut run = |command: &str| debugger.execute_str(command);
+
+    let ran = |steps, outputs, status| Response::Ran {
+        steps,
+        events: vec![],
+        outputs,
+        status,
+        watches: vec![],
+    };
+
+    assert_eq!(
+        run("continue"),
+        Ok(ran(0, vec![], SessionStatus::NeedsInput))
+    );
+    assert_eq!(run("input 3"), Ok(Response::Done));
+    assert_eq!(run("break LOOP"), Ok(Response::Breakpoints(vec![2])));
+    assert_eq!(run("c"), Ok(ran(2, vec![], SessionStatus::Paused)));
+    assert_eq!(run("print ACC + 1"), Ok(Response::Value(4)));
+    assert_eq!(run("x A"), Ok(Response::Memory(vec![(12, 3)])));
+
+    let Ok(Response::Listing { lines, current }) = run("list") else {
+        panic!("expected a listing");
+    };
+    assert_eq!(current, Some(3));
+    assert_eq!(lines.first().unwrap().0, 1);
+    assert_eq!(lines.last().unwrap(), &(8, "        BRA LOOP".to_string()));
+
+    let Ok(Response::Ran {
+        steps,
+        events,
+        outputs,
+        ..
+    }) = run("step 2")
+    else {
+        panic!("expected to step");
+    };
+    assert_eq!((steps, events.len()), (2, 2));
+    assert_eq!(outputs, vec![Output::Int(3)]);
+
+    // ONE already holds 1
+    assert_eq!(
+        run("set ONE 1"),
+        Ok(Response::Changed(StateDiff::default()))
+    );
+    assert_eq!(run("delete LOOP"), Ok(Response::Breakpoints(vec![])));
+    assert_eq!(
+        run("c"),
+        Ok(ran(
+            19,
+            vec![Output::Int(2), Output::Int(1), Output::Int(0)],
+            SessionStatus::Stopped(HaltReason::Halted)
+        ))
+    );
+
+    assert_eq!(
+        run("frobnicate"),
+        Err("Unknown command... frobnicate".to_string())
+    );
+    assert_eq!(
+        run("break 100"),
+        Err("Address out of range... 100".to_string())
+    );
+    assert_eq!(run("step x"), Err("Invalid number... x".to_string()));
+}
+
+#[test]
+fn test_debugger_breakpoint_at_start() {
+    use lmc_assembly::debugger::{Debugger, Response};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut debugger = Debugger::with_source(&assembly, &code);
+    let mut run = |command: &str| match debugger.execute_str(command) {
+        Ok(Response::Ran { steps, status, .. }) => Some((steps, status)),
+        _ => None,
+    };
+
+    run("break 0");
+    // a fresh run stops before the first instruction
+    assert_eq!(run("c"), Some((0, SessionStatus::Paused)));
+    // resuming from the breakpoint carries on, here as far as the INP
+    assert_eq!(run("c"), Some((0, SessionStatus::NeedsInput)));
+    run("input 2");
+    assert_eq!(
+        run("c").map(|(_, status)| status),
+        Some(SessionStatus::Stopped(HaltReason::Halted))
+    );
+}
+
+#[test]
+fn test_debugger_source_stepping() {
+    use lmc_assembly::debugger::{Command, Debugger, Response};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut debugger = Debugger::with_source(&assembly, &code);
+    debugger.execute(Command::Input(3)).unwrap();
+
+    let mut run = |command: &str| match debugger.execute_str(command) {
+        Ok(Response::Ran {
+            steps,
+            outputs,
+            status,
+            ..
+        }) => Ok((steps, outputs, status)),
+        Ok(response) => panic!("unexpected response {response:?}"),
+        Err(message) => Err(message),
+    };
+
+    assert_eq!(run("next"), Ok((1, vec![], SessionStatus::Running)));
+    assert_eq!(
+        run("until 9"),
+        Ok((
+            18,
+            vec![Output::Int(3), Output::Int(2), Output::Int(1)],
+            SessionStatus::Running
+        ))
+    );
+    assert_eq!(run("n"), Ok((1, vec![], SessionStatus::Running)));
+    // line 3 is never reached again
+    assert_eq!(
+        run("until 3"),
+        Ok((
+            3,
+            vec![Output::Int(0)],
+            SessionStatus::Stopped(HaltReason::Halted)
+        ))
+    );
+    assert_eq!(
+        run("until 99"),
+        Err("No instruction on line 99".to_string())
+    );
+}
+
+#[test]
+fn test_debugger_watches() {
+    use lmc_assembly::debugger::{Debugger, Response, WatchValue};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut debugger = Debugger::with_source(&assembly, &code);
+
+    let Ok(Response::Watches(watches)) = debugger.execute_str("watch RAM[A] + ONE") else {
+        panic!("expected the watch list");
+    };
+    assert_eq!(watches, vec!["RAM[12] + 13".parse::<Expr>().unwrap()]);
+    debugger.execute_str("watch ACC").unwrap();
+    debugger.execute_str("watch RAM[ACC - 5]").unwrap();
+
+    debugger.execute_str("input 4").unwrap();
+    let Ok(Response::Ran { watches, .. }) = debugger.execute_str("step 2") else {
+        panic!("expected to step");
+    };
+    let values: Vec<_> = watches
+        .into_iter()
+        .map(|WatchValue { value, .. }| value)
+        .collect();
+    assert_eq!(values[..2], [Ok(17), Ok(4)]);
+    assert!(values[2].is_err());
+
+    assert!(debugger.execute_str("unwatch 2").is_ok());
+    assert_eq!(debugger.watches().len(), 2);
+    assert!(debugger.execute_str("unwatch 2").is_err());
+    assert!(debugger.execute_str("watch RAM[NOWHERE]").is_err());
+}
+
+#[test]
+fn test_debugger_disassembly() {
+    use lmc_assembly::debugger::{Debugger, Response};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut debugger = Debugger::with_source(&assembly, &code);
+
+    let render = |debugger: &Debugger, range| -> Vec<String> {
+        debugger
+            .disassemble(range)
+            .into_iter()
+            .map(|cell| {
+                let marker = if cell.current { ">" } else { " " };
+                let label = cell.label.unwrap_or_default();
+                format!(
+                    "{marker}{:02} {:03} {label} {}",
+                    cell.address, cell.word, cell.instruction
+                )
+            })
+            .collect()
+    };
+    assert_eq!(
+        render(&debugger, 0..3),
+        [">00 901  INP", " 01 312  STA A", " 02 512 LOOP LDA A"]
+    );
+    assert_eq!(
+        render(&debugger, 12..15),
+        [" 12 000 A DAT 0", " 13 001 ONE DAT 1", " 14 000  HLT"]
+    );
+    assert_eq!(debugger.disassemble(90..200).len(), 10);
+
+    // self-modifying code shows up straight away
+    debugger.execute_str("write 1 313").unwrap();
+    debugger.execute_str("input 4").unwrap();
+    debugger.execute_str("step").unwrap();
+    assert_eq!(render(&debugger, 1..2), [">01 313  STA ONE"]);
+
+    let Ok(Response::Disassembly(cells)) = debugger.execute_str("dis LOOP 2") else {
+        panic!("expected a disassembly");
+    };
+    assert_eq!(cells.len(), 2);
+    assert_eq!(cells[1].instruction.to_string(), "OUT");
+    let Ok(Response::Disassembly(cells)) = debugger.execute_str("disassemble") else {
+        panic!("expected a disassembly");
+    };
+    assert_eq!(cells.len(), 7);
+    assert!(debugger.execute_str("dis 100").is_err());
+}
+
+#[test]
+fn test_debugger_mutation() {
+    use lmc_assembly::debugger::{Command, Debugger, Response};
+
+    let code = std::fs::read_to_string("./examples/countdown.lmc").unwrap();
+    let assembly = lmc_assembly::build(&code, &Default::default()).unwrap();
+    let mut debugger = Debugger::with_source(&assembly, &code);
+    let mut run = |command: &str| debugger.execute_str(command);
+
+    // skip the INP and start the countdown from 2
+    assert_eq!(
+        run("set pc LOOP"),
+        Ok(Response::Changed(StateDiff {
+            registers: vec![RegisterChange {
+                register: Register::Pc,
+                old: 0,
+                new: 2,
+            }],
+            ..StateDiff::default()
+        }))
+    );
+    assert_eq!(
+        run("write A 2"),
+        Ok(Response::Changed(StateDiff {
+            ram: vec![MemoryWrite {
+                address: 12,
+                old: 0,
+                new: 2,
+            }],
+            ..StateDiff::default()
+        }))
+    );
+    assert_eq!(run("x A 2"), Ok(Response::Memory(vec![(12, 2), (13, 1)])));
+    assert!(run("set ACC -999").is_ok());
+
+    assert_eq!(
+        run("set ACC 1000"),
+        Err("Value out of range... 1000".to_string())
+    );
+    assert_eq!(
+        run("set A -1000"),
+        Err("Value out of range... -1000".to_string())
+    );
+    assert_eq!(
+        run("set PC 100"),
+        Err("Address out of range... 100".to_string())
+    );
+    assert!(run("set SP 100").is_ok());
+
+    let Ok(Response::Ran { outputs, .. }) = run("c") else {
+        panic!("expected to run");
+    };
+    assert_eq!(
+        outputs,
+        vec![Output::Int(2), Output::Int(1), Output::Int(0)]
+    );
+    assert_eq!(
+        debugger.parse("set mdr 5"),
+        Ok(Command::SetRegister {
+            register: Register::Mdr,
+            value: 5,
+        })
+    );
+}
+
+#[test]
+fn test_debugger_extreme_arguments() {
+    use lmc_assembly::debugger::{Command, Debugger, Response};
+
+    let mut debugger = Debugger::new(ExecutionState::new([0; 100]));
+    assert_eq!(
+        debugger.execute_str("write 5 -32768"),
+        Err("Value out of range... -32768".to_string())
+    );
+    assert_eq!(
+        debugger.execute_str("set ACC 32767"),
+        Err("Value out of range... 32767".to_string())
+    );
+
+    let Ok(Response::Memory(cells)) = debugger.execute(Command::Read {
+        address: 95,
+        count: usize::MAX,
+    }) else {
+        panic!("expected memory");
+    };
+    assert_eq!(cells.len(), 5);
+
+    // after running off either end of memory, the window stays inside it
+    for pc in [-5, 100, i16::MAX] {
+        let mut state = ExecutionState::new([0; 100]);
+        state.pc = pc;
+        let mut debugger = Debugger::new(state);
+        let Ok(Response::Disassembly(cells)) = debugger.execute(Command::Disassemble(None)) else {
+            panic!("expected a disassembly");
+        };
+        assert!(cells.len() <= 11);
+        assert!(cells.iter().all(|cell| (0..100).contains(&cell.address)));
+    }
+}
+
+#[test]
+fn test_trace_json() {
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    state.start_trace();
+    let mut io_handler = TestIO {
+        input_buffer: vec![2, 1],
+        output_buffer: vec![],
+    };
+    while state.step(&mut io_handler).unwrap().halt_reason.is_none() {}
+
+    let json: serde_json::Value =
+        serde_json::from_str(&state.take_trace().unwrap().to_json()).unwrap();
+    assert_eq!(json["version"], lmc_assembly::TRACE_JSON_VERSION);
+
+    let steps = json["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 6);
+    assert_eq!(
+        steps[1],
+        serde_json::json!({
+            "pc": 1,
+            "word": 306,
+            "instruction": "STA 6",
+            "acc": 1,
+            "mar": 6,
+            "mdr": 306,
+            "next_pc": 2,
+            "registers": [
+                {"register": "PC", "old": 1, "new": 2},
+                {"register": "MAR", "old": 0, "new": 6},
+                {"register": "MDR", "old": 901, "new": 306},
+                {"register": "CIR", "old": 901, "new": 306},
+            ],
+            "read": null,
+            "write": {"address": 6, "old": 0, "new": 1},
+            "input": null,
+            "output": null,
+            "branch": null,
+            "halt_reason": null,
+        })
+    );
+    assert_eq!(steps[4]["output"], serde_json::json!({"int": 3}));
+    assert_eq!(steps[5]["halt_reason"], "Halted");
+}
+
+#[test]
+fn test_chrome_trace() {
+    let code = std::fs::read_to_string("./examples/sum.lmc").unwrap();
+    let mut state = ExecutionState::new(assemble(&code));
+    state.start_trace();
+    let mut io_handler = TestIO {
+        input_buffer: vec![2, 1],
+        output_buffer: vec![],
+    };
+    while state.step(&mut io_handler).unwrap().halt_reason.is_none() {}
+
+    let costs = CycleCosts {
+        io: 10,
+        ..CycleCosts::default()
+    };
+    let json: serde_json::Value =
+        serde_json::from_str(&state.take_trace().unwrap().to_chrome_trace(&costs)).unwrap();
+    let events = json["traceEvents"].as_array().unwrap();
+
+    let instructions: Vec<_> = events
+        .iter()
+        .filter(|event| event["ph"] == "X")
+        .map(|event| {
+            (
+                event["name"].as_str().unwrap(),
+                event["ts"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        instructions,
+        vec![
+            ("INP", 0),
+            ("STA 6", 10),
+            ("INP", 11),
+            ("ADD 6", 21),
+            ("OUT", 22),
+            ("HLT", 32)
+        ]
+    );
+
+    let io: Vec<_> = events
+        .iter()
+        .filter(|event| event["ph"] == "i")
+        .map(|event| event["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(io, vec!["INP 1", "INP 2", "OUT 3"]);
+    assert_eq!(events.last().unwrap()["args"]["ACC"], 3);
+}
+
+#[test]
+fn test_coverage() {
+    use lmc_assembly::io::BufferedIO;
+
+    let code = "        INP\n        BRZ ZERO\n        OUT\n        HLT\nZERO    LDA ONE\n        OUT\n        HLT\nONE     DAT 1\n";
+    let assembly = lmc_assembly::build(code, &Default::default()).unwrap();
+    let coverage = |input| {
+        let mut io_handler = BufferedIO::new([input]);
+        lmc_assembly::run(assembly.image.0, &mut io_handler, false)
+            .unwrap()
+            .state
+            .coverage()
+    };
+
+    let mut coverage_so_far = coverage(5);
+    assert_eq!(coverage_so_far.uncovered(&assembly), vec![4, 5, 6]);
+    assert_eq!(coverage_so_far.ratio(&assembly), 4.0 / 7.0);
+    assert_eq!(
+        coverage_so_far.lines(&assembly)[3..5],
+        [(4, true), (5, false)]
+    );
+
+    coverage_so_far.merge(&coverage(0));
+    assert!(coverage_so_far.uncovered(&assembly).is_empty());
+    assert_eq!(coverage_so_far.ratio(&assembly), 1.0);
+    assert_eq!(coverage_so_far.lines(&assembly).len(), 7);
+}